@@ -8,10 +8,26 @@ struct BlitVertexOutput {
     @location(0) uv_coords: vec2<f32>,
 }
 
+#include overdraw.wgsl
+
+struct BlitParams {
+    overdraw_heatmap: u32,
+    calibration_pattern: u32,
+    view_scales: vec2<f32>,
+    gamma: f32,
+    brightness: f32,
+    contrast: f32,
+    // The per-eye colour correction, indexed by view; only the RGB channels are used.
+    eye_gain: array<vec4<f32>, 2>,
+    eye_gamma: array<vec4<f32>, 2>,
+}
+
 @group(0) @binding(0)
 var blit_texture: texture_2d_array<f32>;
 @group(0) @binding(1)
 var blit_sampler: sampler;
+@group(0) @binding(2)
+var<uniform> blit_params: BlitParams;
 
 @vertex
 fn blit_vs_main(model: BlitVertexInput) -> BlitVertexOutput {
@@ -19,4 +35,76 @@ fn blit_vs_main(model: BlitVertexInput) -> BlitVertexOutput {
     out.position = vec4<f32>(model.position, 1.0);
     out.uv_coords = model.uv_coords;
     return out;
-}
\ No newline at end of file
+}
+
+// Maps `t` in [0, 1] to a blue-cyan-green-yellow-red colour ramp.
+fn heatmap_ramp(t: f32) -> vec3<f32> {
+    let x = clamp(t, 0.0, 1.0) * 4.0;
+    return clamp(
+        vec3<f32>(x - 2.0, min(x, 4.0 - x), 2.0 - x),
+        vec3<f32>(0.0),
+        vec3<f32>(1.0)
+    );
+}
+
+// The size of the calibration checkerboard's squares, in output pixels.
+let CALIBRATION_SQUARE_SIZE: f32 = 32.0;
+// The size of the calibration corner markers, as a fraction of the output's size.
+let CALIBRATION_MARKER_SIZE: f32 = 0.05;
+
+// A checkerboard at the output's full resolution, with a one-pixel white border, a coloured
+// marker in each corner (red, green, blue and yellow, clockwise from the top left) and a
+// crosshair in the centre. If any of these are missing or distorted, the output is being
+// cropped or scaled.
+fn calibration_pattern(in: BlitVertexOutput) -> vec4<f32> {
+    // The size of a pixel in UV coordinates.
+    let pixel = fwidth(in.uv_coords);
+    let uv = in.uv_coords;
+
+    if (any(uv < pixel) || any(uv > vec2<f32>(1.0) - pixel)) {
+        return vec4<f32>(1.0);
+    }
+    let from_centre = abs(uv - vec2<f32>(0.5)) / pixel;
+    if (min(from_centre.x, from_centre.y) < 1.0 && max(from_centre.x, from_centre.y) < 64.0) {
+        return vec4<f32>(1.0, 0.0, 1.0, 1.0);
+    }
+    let near_left = uv.x < CALIBRATION_MARKER_SIZE;
+    let near_right = uv.x > 1.0 - CALIBRATION_MARKER_SIZE;
+    let near_top = uv.y < CALIBRATION_MARKER_SIZE;
+    let near_bottom = uv.y > 1.0 - CALIBRATION_MARKER_SIZE;
+    if (near_top && near_left) {
+        return vec4<f32>(1.0, 0.0, 0.0, 1.0);
+    } else if (near_top && near_right) {
+        return vec4<f32>(0.0, 1.0, 0.0, 1.0);
+    } else if (near_bottom && near_right) {
+        return vec4<f32>(0.0, 0.0, 1.0, 1.0);
+    } else if (near_bottom && near_left) {
+        return vec4<f32>(1.0, 1.0, 0.0, 1.0);
+    }
+
+    let square = vec2<u32>(in.position.xy / CALIBRATION_SQUARE_SIZE);
+    let shade = select(0.1, 0.9, (square.x + square.y) % 2u == 0u);
+    return vec4<f32>(vec3<f32>(shade), 1.0);
+}
+
+// Applies the view's colour correction, then the gamma, contrast and brightness adjustments, in
+// that order. The defaults (a gain and gamma of 1 per channel, then 1, 1 and 0) leave the
+// colour unchanged.
+fn adjust_color(color: vec3<f32>, view_index: u32) -> vec3<f32> {
+    let eye_gain = blit_params.eye_gain[view_index].rgb;
+    let eye_gamma = blit_params.eye_gamma[view_index].rgb;
+    let corrected = pow(max(color, vec3<f32>(0.0)), vec3<f32>(1.0) / eye_gamma) * eye_gain;
+    var adjusted = pow(max(corrected, vec3<f32>(0.0)), vec3<f32>(1.0 / blit_params.gamma));
+    adjusted = (adjusted - 0.5) * blit_params.contrast + 0.5 + blit_params.brightness;
+    return clamp(adjusted, vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+fn blit_post_process(in: BlitVertexOutput, color: vec4<f32>, view_index: u32) -> vec4<f32> {
+    if (blit_params.calibration_pattern != 0u) {
+        return calibration_pattern(in);
+    }
+    if (blit_params.overdraw_heatmap != 0u) {
+        return vec4<f32>(heatmap_ramp(color.r / OVERDRAW_STEP / OVERDRAW_MAX), 1.0);
+    }
+    return vec4<f32>(adjust_color(color.rgb, view_index), color.a);
+}
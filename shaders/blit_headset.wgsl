@@ -5,5 +5,9 @@ fn blit_fs_main(
     in: BlitVertexOutput,
     @builtin(view_index) view_index: i32
 ) -> @location(0) vec4<f32> {
-    return textureSample(blit_texture, blit_sampler, in.uv_coords, i32(view_index));
+    return blit_post_process(
+        in,
+        textureSample(blit_texture, blit_sampler, in.uv_coords, i32(view_index)),
+        u32(view_index)
+    );
 }
\ No newline at end of file
@@ -3,5 +3,11 @@
 var<push_constant> view_index: u32;
 @fragment
 fn blit_fs_main(in: BlitVertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(blit_texture, blit_sampler, in.uv_coords, i32(view_index));
+    // Only the top-left of the view's layer is used if it's rendered at a reduced resolution.
+    let uv_coords = in.uv_coords * blit_params.view_scales[view_index];
+    return blit_post_process(
+        in,
+        textureSample(blit_texture, blit_sampler, uv_coords, i32(view_index)),
+        view_index
+    );
 }
\ No newline at end of file
@@ -0,0 +1,15 @@
+@group(0) @binding(0)
+var<uniform> view_projection_matrix: array<mat4x4<f32>, 2>;
+// The view-projection matrices of the previous frame.
+@group(0) @binding(1)
+var<uniform> previous_view_projection_matrix: array<mat4x4<f32>, 2>;
+
+struct FrameTime {
+    // Seconds since the application started. In XR, this follows the runtime's predicted
+    // display time, so animation matches when the frame is shown.
+    seconds: f32,
+    // Seconds since the previous frame.
+    delta_seconds: f32,
+}
+@group(0) @binding(2)
+var<uniform> frame_time: FrameTime;
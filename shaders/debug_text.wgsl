@@ -0,0 +1,87 @@
+#include camera.wgsl
+
+struct TextParams {
+    // The size of a single font pixel, in normalized device coordinates.
+    glyph_pixel_size: vec2<f32>,
+}
+
+@group(1) @binding(0)
+var font_texture: texture_2d<f32>;
+@group(1) @binding(1)
+var<uniform> params: TextParams;
+
+let GLYPH_WIDTH: f32 = 5.0;
+let GLYPH_HEIGHT: f32 = 7.0;
+
+struct GlyphInput {
+    @location(0) anchor: vec3<f32>,
+    @location(1) offset: vec2<f32>,
+    @location(2) glyph: u32,
+    @location(3) color: vec4<f32>,
+    @location(4) scale: f32,
+    @location(5) view_mask: u32,
+    @location(6) screen_space: u32,
+}
+
+struct GlyphOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) font_coords: vec2<f32>,
+    @location(1) @interpolate(flat) glyph: u32,
+    @location(2) color: vec4<f32>,
+    @location(3) @interpolate(flat) view_mask: u32,
+}
+
+@vertex
+fn text_vs_main(
+    glyph: GlyphInput,
+    @builtin(vertex_index) vertex_index: u32,
+    @builtin(view_index) view_index: i32,
+) -> GlyphOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 1.0),
+    );
+    let corner = corners[vertex_index];
+
+    var out: GlyphOutput;
+    var anchor = vec4<f32>(glyph.anchor, 1.0);
+    if (glyph.screen_space == 0u) {
+        anchor = view_projection_matrix[view_index] * anchor;
+    }
+    if (anchor.w <= 0.0) {
+        // The anchor is behind the viewer; move the glyph outside of the clip volume.
+        out.position = vec4<f32>(2.0, 2.0, 2.0, 1.0);
+    } else {
+        // Offset in clip space so that the text is always facing the viewer at a constant size.
+        let font_pixel = (glyph.offset + corner * vec2<f32>(GLYPH_WIDTH, GLYPH_HEIGHT)) * glyph.scale;
+        out.position = vec4<f32>(
+            anchor.xy + font_pixel * params.glyph_pixel_size * anchor.w,
+            anchor.zw
+        );
+    }
+    out.font_coords = vec2<f32>(corner.x * GLYPH_WIDTH, (1.0 - corner.y) * GLYPH_HEIGHT);
+    out.glyph = glyph.glyph;
+    out.color = glyph.color;
+    out.view_mask = glyph.view_mask;
+    return out;
+}
+
+@fragment
+fn text_fs_main(
+    in: GlyphOutput,
+    @builtin(view_index) view_index: i32,
+) -> @location(0) vec4<f32> {
+    if (((in.view_mask >> u32(view_index)) & 1u) == 0u) {
+        discard;
+    }
+    let coords = min(vec2<i32>(floor(in.font_coords)), vec2<i32>(4, 6));
+    let texel = textureLoad(font_texture, vec2<i32>(i32(in.glyph) * 5 + coords.x, coords.y), 0);
+    if (texel.r < 0.5) {
+        discard;
+    }
+    return in.color;
+}
@@ -0,0 +1,60 @@
+let ENVIRONMENT_PI: f32 = 3.141592654;
+
+// Must match `EnvironmentParams` in `environment_state.rs`.
+struct EnvironmentParams {
+    // The inverse of each view's view-projection matrix, to find the direction that each pixel
+    // looks in.
+    inverse_view_projection: array<mat4x4<f32>, 2>,
+}
+
+@group(0) @binding(0)
+var environment_depth: texture_depth_2d_array;
+@group(0) @binding(1)
+var<uniform> environment_params: EnvironmentParams;
+@group(0) @binding(2)
+var environment_map: texture_2d<f32>;
+@group(0) @binding(3)
+var environment_sampler: sampler;
+
+struct EnvironmentVertexOutput {
+    @builtin(position) position: vec4<f32>,
+    // The position in normalized device coordinates, which doesn't depend on the viewport.
+    @location(0) ndc: vec2<f32>,
+}
+
+// Draws a single triangle that covers the whole target.
+@vertex
+fn environment_vs_main(@builtin(vertex_index) vertex_index: u32) -> EnvironmentVertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: EnvironmentVertexOutput;
+    out.ndc = uv * 2.0 - 1.0;
+    out.position = vec4<f32>(out.ndc, 0.0, 1.0);
+    return out;
+}
+
+// Draws the environment map wherever nothing else was drawn, i.e. where the depth buffer is
+// still at the far plane.
+@fragment
+fn environment_fs_main(
+    in: EnvironmentVertexOutput,
+    @builtin(view_index) view_index: i32
+) -> @location(0) vec4<f32> {
+    let depth = textureLoad(environment_depth, vec2<i32>(in.position.xy), view_index, 0);
+    if (depth < 1.0) {
+        discard;
+    }
+
+    let inverse = environment_params.inverse_view_projection[view_index];
+    let near = inverse * vec4<f32>(in.ndc, 0.0, 1.0);
+    let far = inverse * vec4<f32>(in.ndc, 1.0, 1.0);
+    let direction = normalize(far.xyz / far.w - near.xyz / near.w);
+
+    // The map is equirectangular, with -Z (forward) at its centre and +Y at its top.
+    let uv = vec2<f32>(
+        atan2(direction.x, -direction.z) / (2.0 * ENVIRONMENT_PI) + 0.5,
+        acos(clamp(direction.y, -1.0, 1.0)) / ENVIRONMENT_PI
+    );
+    // The map isn't mipmapped, so the seam where U wraps around doesn't need its own LOD.
+    let color = textureSampleLevel(environment_map, environment_sampler, uv, 0.0).rgb;
+    return vec4<f32>(color, 1.0);
+}
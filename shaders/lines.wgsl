@@ -0,0 +1,88 @@
+#include camera.wgsl
+
+struct LineParams {
+    // The size of the render target, in pixels.
+    viewport_size: vec2<f32>,
+    // The width of the lines, in pixels.
+    width: f32,
+}
+
+@group(1) @binding(0)
+var<uniform> params: LineParams;
+
+// Segments ending closer than this (in clip-space W) are cut short, so that a segment that
+// passes behind the viewer doesn't get flipped across the screen by the perspective divide.
+let NEAR_W: f32 = 0.0001;
+
+struct LineInput {
+    @location(0) start: vec3<f32>,
+    @location(1) end: vec3<f32>,
+    @location(2) color: vec4<f32>,
+}
+
+struct LineOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    // The signed distance from the centre of the line, in pixels.
+    @location(1) edge_distance: f32,
+}
+
+@vertex
+fn line_vs_main(
+    segment: LineInput,
+    @builtin(vertex_index) vertex_index: u32,
+    @builtin(view_index) view_index: i32,
+) -> LineOutput {
+    // Each corner is (which end of the segment, which side of the line).
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 1.0),
+    );
+    let corner = corners[vertex_index];
+
+    var out: LineOutput;
+    out.color = segment.color;
+
+    var start = view_projection_matrix[view_index] * vec4<f32>(segment.start, 1.0);
+    var end = view_projection_matrix[view_index] * vec4<f32>(segment.end, 1.0);
+    if (start.w < NEAR_W && end.w < NEAR_W) {
+        // The segment is entirely behind the viewer; move it outside of the clip volume.
+        out.position = vec4<f32>(2.0, 2.0, 2.0, 1.0);
+        out.edge_distance = 0.0;
+        return out;
+    }
+    if (start.w < NEAR_W) {
+        start = mix(start, end, (NEAR_W - start.w) / (end.w - start.w));
+    } else if (end.w < NEAR_W) {
+        end = mix(end, start, (NEAR_W - end.w) / (start.w - end.w));
+    }
+
+    // Work out the line's direction on screen, in pixels, to expand it perpendicularly.
+    let half_viewport = params.viewport_size * 0.5;
+    let screen_start = start.xy / start.w * half_viewport;
+    let screen_end = end.xy / end.w * half_viewport;
+    var direction = vec2<f32>(1.0, 0.0);
+    if (distance(screen_start, screen_end) > 0.0001) {
+        direction = normalize(screen_end - screen_start);
+    }
+    let normal = vec2<f32>(-direction.y, direction.x);
+
+    // The quad is widened by a pixel on either side to leave room for the anti-aliased edge.
+    let half_width = params.width * 0.5 + 1.0;
+    let position = mix(start, end, corner.x);
+    let offset = normal * corner.y * half_width / half_viewport;
+    out.position = vec4<f32>(position.xy + offset * position.w, position.zw);
+    out.edge_distance = corner.y * half_width;
+    return out;
+}
+
+@fragment
+fn line_fs_main(in: LineOutput) -> @location(0) vec4<f32> {
+    // Fade out over the pixel straddling the edge of the line.
+    let coverage = clamp(params.width * 0.5 + 0.5 - abs(in.edge_distance), 0.0, 1.0);
+    return vec4<f32>(in.color.rgb, in.color.a * coverage);
+}
@@ -1,20 +1,87 @@
-@group(0) @binding(0)
-var<uniform> view_projection_matrix: array<mat4x4<f32>, 2>;
+#include camera.wgsl
+#include overdraw.wgsl
+
+// The direction towards the light, in world space.
+let LIGHT_DIRECTION: vec3<f32> = vec3<f32>(0.267, 0.891, 0.367);
+
+struct Shading {
+    // The light reaching every surface regardless of orientation. This is a fixed default, or the
+    // average colour of the HDR environment map with `--environment-lighting`.
+    ambient_light: vec3<f32>,
+    // If non-zero, fragments are coloured by their distance from the viewer instead of being
+    // lit, ramping logarithmically from blue at the start of `depth_range` to red at its end.
+    depth_shading: u32,
+    depth_range: vec2<f32>,
+}
+
+@group(1) @binding(0)
+var<uniform> shading: Shading;
 
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) color: vec3<f32>,
+    @location(2) normal: vec3<f32>,
 }
 struct InstanceInput {
-    @location(2) model_matrix_0: vec4<f32>,
-    @location(3) model_matrix_1: vec4<f32>,
-    @location(4) model_matrix_2: vec4<f32>,
-    @location(5) model_matrix_3: vec4<f32>,
+    @location(3) model_matrix_0: vec4<f32>,
+    @location(4) model_matrix_1: vec4<f32>,
+    @location(5) model_matrix_2: vec4<f32>,
+    @location(6) model_matrix_3: vec4<f32>,
+    @location(7) normal_matrix_0: vec3<f32>,
+    @location(8) normal_matrix_1: vec3<f32>,
+    @location(9) normal_matrix_2: vec3<f32>,
+    @location(10) color: vec4<f32>,
+    @location(11) previous_model_matrix_0: vec4<f32>,
+    @location(12) previous_model_matrix_1: vec4<f32>,
+    @location(13) previous_model_matrix_2: vec4<f32>,
+    @location(14) previous_model_matrix_3: vec4<f32>,
+};
+// The compact alternative to `InstanceInput`, from which the matrices are built here rather than
+// on the CPU. The translation's W is 1, or everything is zero for hidden instances.
+struct CompactInstanceInput {
+    @location(3) rotation: vec4<f32>,
+    @location(4) translation: vec4<f32>,
+    @location(5) scale: vec3<f32>,
+    @location(6) color: vec4<f32>,
+    @location(7) previous_rotation: vec4<f32>,
+    @location(8) previous_translation: vec4<f32>,
+    @location(9) previous_scale: vec3<f32>,
 };
 
 struct VertexOutput {
     @builtin(position) position: vec4<f32>,
     @location(0) color: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    // The clip-space positions in this frame and the previous one, for motion vectors. These
+    // are interpolated separately, as the perspective divide can't be interpolated linearly.
+    @location(2) clip_position: vec4<f32>,
+    @location(3) previous_clip_position: vec4<f32>,
+}
+
+// The outputs of the passes that also render motion vectors.
+struct MotionVectorOutput {
+    @location(0) color: vec4<f32>,
+    // How far the surface has moved since the previous frame, in UV units (0 to 1 across the
+    // render target, with Y pointing down).
+    @location(1) motion_vector: vec2<f32>,
+}
+
+fn vertex(
+    model: VertexInput,
+    model_matrix: mat4x4<f32>,
+    normal_matrix: mat3x3<f32>,
+    color: vec4<f32>,
+    previous_model_matrix: mat4x4<f32>,
+    view_index: i32,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = view_projection_matrix[view_index] * model_matrix * vec4<f32>(model.position, 1.0);
+    out.color = model.color * color.rgb;
+    out.normal = normal_matrix * model.normal;
+    out.clip_position = out.position;
+    out.previous_clip_position = previous_view_projection_matrix[view_index]
+        * previous_model_matrix * vec4<f32>(model.position, 1.0);
+    return out;
 }
 
 @vertex
@@ -29,14 +96,120 @@ fn vs_main(
         instance.model_matrix_2,
         instance.model_matrix_3,
     );
+    // The inverse-transpose of the model matrix, so that normals stay perpendicular to their
+    // surfaces under non-uniform scale.
+    let normal_matrix = mat3x3<f32>(
+        instance.normal_matrix_0,
+        instance.normal_matrix_1,
+        instance.normal_matrix_2,
+    );
 
-    var out: VertexOutput;
-    out.position = view_projection_matrix[view_index] * model_matrix * vec4<f32>(model.position, 1.0);
-    out.color = model.color;
-    return out;
+    let previous_model_matrix = mat4x4<f32>(
+        instance.previous_model_matrix_0,
+        instance.previous_model_matrix_1,
+        instance.previous_model_matrix_2,
+        instance.previous_model_matrix_3,
+    );
+
+    return vertex(model, model_matrix, normal_matrix, instance.color, previous_model_matrix, view_index);
+}
+
+// The rotation matrix of a unit quaternion (x, y, z, w).
+fn quaternion_to_matrix(q: vec4<f32>) -> mat3x3<f32> {
+    let x2 = q.x * 2.0;
+    let y2 = q.y * 2.0;
+    let z2 = q.z * 2.0;
+    return mat3x3<f32>(
+        vec3<f32>(1.0 - q.y * y2 - q.z * z2, q.x * y2 + q.w * z2, q.x * z2 - q.w * y2),
+        vec3<f32>(q.x * y2 - q.w * z2, 1.0 - q.x * x2 - q.z * z2, q.y * z2 + q.w * x2),
+        vec3<f32>(q.x * z2 + q.w * y2, q.y * z2 - q.w * x2, 1.0 - q.x * x2 - q.y * y2),
+    );
+}
+
+// Scales, then rotates, then translates.
+fn compact_model_matrix(rotation: vec4<f32>, translation: vec4<f32>, scale: vec3<f32>) -> mat4x4<f32> {
+    let r = quaternion_to_matrix(rotation);
+    return mat4x4<f32>(
+        vec4<f32>(r[0] * scale.x, 0.0),
+        vec4<f32>(r[1] * scale.y, 0.0),
+        vec4<f32>(r[2] * scale.z, 0.0),
+        translation,
+    );
+}
+
+@vertex
+fn vs_main_compact(
+    model: VertexInput,
+    instance: CompactInstanceInput,
+    @builtin(view_index) view_index: i32,
+) -> VertexOutput {
+    let model_matrix = compact_model_matrix(instance.rotation, instance.translation, instance.scale);
+    // The inverse-transpose of a rotation and a scale is the rotation and the inverse scale.
+    let r = quaternion_to_matrix(instance.rotation);
+    let normal_matrix = mat3x3<f32>(
+        r[0] / instance.scale.x,
+        r[1] / instance.scale.y,
+        r[2] / instance.scale.z,
+    );
+    let previous_model_matrix = compact_model_matrix(
+        instance.previous_rotation,
+        instance.previous_translation,
+        instance.previous_scale,
+    );
+
+    return vertex(model, model_matrix, normal_matrix, instance.color, previous_model_matrix, view_index);
+}
+
+fn depth_ramp(distance: f32) -> vec3<f32> {
+    let range = shading.depth_range;
+    let t = clamp(log(distance / range.x) / log(range.y / range.x), 0.0, 1.0);
+    // Blue through green to red, so that the middle of the range stands out too.
+    return vec3<f32>(t, 1.0 - abs(t * 2.0 - 1.0), 1.0 - t);
+}
+
+fn shade(in: VertexOutput) -> vec4<f32> {
+    if (shading.depth_shading != 0u) {
+        // For a perspective projection, clip-space W is the distance along the view direction.
+        return vec4<f32>(depth_ramp(in.clip_position.w), 1.0);
+    }
+    // Lit from both sides, as back faces aren't culled by default.
+    let diffuse = abs(dot(normalize(in.normal), LIGHT_DIRECTION));
+    return vec4<f32>(in.color * mix(shading.ambient_light, vec3<f32>(1.0), diffuse), 1.0);
 }
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return vec4<f32>(in.color, 1.0);
+    return shade(in);
+}
+
+@fragment
+fn fs_overdraw(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(OVERDRAW_STEP, 0.0, 0.0, 1.0);
+}
+
+fn motion_vector(in: VertexOutput) -> vec2<f32> {
+    // Points that were behind the viewer (or, for instances that were hidden, collapsed to the
+    // origin) in the previous frame have nowhere sensible to have come from.
+    if (in.previous_clip_position.w <= 0.0) {
+        return vec2<f32>(0.0);
+    }
+    let current = in.clip_position.xy / in.clip_position.w;
+    let previous = in.previous_clip_position.xy / in.previous_clip_position.w;
+    return (current - previous) * vec2<f32>(0.5, -0.5);
+}
+
+@fragment
+fn fs_main_motion_vectors(in: VertexOutput) -> MotionVectorOutput {
+    var out: MotionVectorOutput;
+    out.color = shade(in);
+    out.motion_vector = motion_vector(in);
+    return out;
+}
+
+@fragment
+fn fs_overdraw_motion_vectors(in: VertexOutput) -> MotionVectorOutput {
+    var out: MotionVectorOutput;
+    out.color = vec4<f32>(OVERDRAW_STEP, 0.0, 0.0, 1.0);
+    out.motion_vector = vec2<f32>(0.0);
+    return out;
 }
@@ -0,0 +1,4 @@
+// The amount each fragment contributes to the overdraw heatmap.
+let OVERDRAW_STEP: f32 = 0.03125;
+// The number of overlapping fragments at which the heatmap saturates.
+let OVERDRAW_MAX: f32 = 16.0;
\ No newline at end of file
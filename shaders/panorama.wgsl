@@ -0,0 +1,64 @@
+let PANORAMA_PI: f32 = 3.14159265;
+
+// Each face's forward and up vectors, interleaved. Must match `CUBE_FACES` in
+// `panorama_state.rs`.
+struct PanoramaParams {
+    faces: array<vec4<f32>, 12>,
+}
+
+struct PanoramaVertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+// The cube faces for both eyes; layer `face * 2 + eye`.
+@group(0) @binding(0)
+var panorama_faces: texture_2d_array<f32>;
+@group(0) @binding(1)
+var panorama_sampler: sampler;
+@group(0) @binding(2)
+var<uniform> panorama_params: PanoramaParams;
+
+// Draws a single triangle that covers the whole target.
+@vertex
+fn panorama_vs_main(@builtin(vertex_index) vertex_index: u32) -> PanoramaVertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: PanoramaVertexOutput;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+// Converts the cube faces to a top-bottom stereo equirectangular panorama, with the left eye
+// on top. The centre of each half looks down +Z, the camera's default forward direction.
+@fragment
+fn panorama_fs_main(in: PanoramaVertexOutput) -> @location(0) vec4<f32> {
+    let eye = select(0, 1, in.uv.y >= 0.5);
+    let longitude = (in.uv.x - 0.5) * 2.0 * PANORAMA_PI;
+    let latitude = (0.5 - fract(in.uv.y * 2.0)) * PANORAMA_PI;
+    let direction = vec3<f32>(
+        -sin(longitude) * cos(latitude),
+        sin(latitude),
+        cos(longitude) * cos(latitude),
+    );
+
+    // The face to sample is the one that looks most directly along the direction.
+    var face = 0;
+    var best = -2.0;
+    for (var i = 0; i < 6; i = i + 1) {
+        let alignment = dot(direction, panorama_params.faces[i * 2].xyz);
+        if (alignment > best) {
+            best = alignment;
+            face = i;
+        }
+    }
+    let forward = panorama_params.faces[face * 2].xyz;
+    let up = panorama_params.faces[face * 2 + 1].xyz;
+    // Matches the basis that `Mat4::look_at_rh` builds for the face.
+    let right = cross(forward, up);
+
+    // Project onto the face, which covers [-1, 1] on both axes with a 90 degree field of view.
+    let projected = direction / dot(direction, forward);
+    let face_uv = vec2<f32>(dot(projected, right), -dot(projected, up)) * 0.5 + 0.5;
+    return textureSampleLevel(panorama_faces, panorama_sampler, face_uv, face * 2 + eye, 0.0);
+}
@@ -0,0 +1,35 @@
+let SPINNER_TAU: f32 = 6.283185307;
+let SPINNER_INNER_RADIUS: f32 = 0.6;
+let SPINNER_OUTER_RADIUS: f32 = 0.9;
+// Revolutions per second.
+let SPINNER_SPEED: f32 = 1.0;
+
+struct SpinnerVertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) coords: vec2<f32>,
+}
+
+// The time in seconds, used to animate the spinner.
+var<push_constant> spinner_time: f32;
+
+// Draws a single triangle that covers the whole target.
+@vertex
+fn spinner_vs_main(@builtin(vertex_index) vertex_index: u32) -> SpinnerVertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: SpinnerVertexOutput;
+    out.position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.coords = uv * 2.0 - 1.0;
+    return out;
+}
+
+@fragment
+fn spinner_fs_main(in: SpinnerVertexOutput) -> @location(0) vec4<f32> {
+    let radius = length(in.coords);
+    if (radius < SPINNER_INNER_RADIUS || radius > SPINNER_OUTER_RADIUS) {
+        return vec4<f32>(0.0);
+    }
+    // The arc fades out behind its leading edge, which goes around the ring over time.
+    let angle = atan2(in.coords.y, in.coords.x) / SPINNER_TAU;
+    let alpha = fract(angle + spinner_time * SPINNER_SPEED);
+    return vec4<f32>(1.0, 1.0, 1.0, alpha);
+}
@@ -0,0 +1,86 @@
+let SSAO_TAU: f32 = 6.283185307;
+// Successive samples are this far apart around the disk, which spreads them out evenly.
+let SSAO_GOLDEN_ANGLE: f32 = 2.399963230;
+
+// Must match `SsaoParams` in `ssao_state.rs`.
+struct SsaoParams {
+    // The radius of the sampled disk, in pixels, for a surface 1 metre away. It shrinks with
+    // distance, so that the darkened area stays roughly the same size in the world.
+    radius: f32,
+    intensity: f32,
+    z_near: f32,
+    z_far: f32,
+    sample_count: u32,
+}
+
+@group(0) @binding(0)
+var ssao_depth: texture_depth_2d_array;
+@group(0) @binding(1)
+var<uniform> ssao_params: SsaoParams;
+
+struct SsaoVertexOutput {
+    @builtin(position) position: vec4<f32>,
+}
+
+// Draws a single triangle that covers the whole target.
+@vertex
+fn ssao_vs_main(@builtin(vertex_index) vertex_index: u32) -> SsaoVertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: SsaoVertexOutput;
+    out.position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    return out;
+}
+
+// The distance from the camera, in metres, of a depth buffer value.
+fn ssao_linear_depth(depth: f32) -> f32 {
+    let near = ssao_params.z_near;
+    let far = ssao_params.z_far;
+    return near * far / (far - depth * (far - near));
+}
+
+// Interleaved gradient noise, to rotate the samples differently at each pixel; this trades the
+// banding of a fixed pattern for noise.
+fn ssao_noise(position: vec2<f32>) -> f32 {
+    return fract(52.9829189 * fract(dot(position, vec2<f32>(0.06711056, 0.00583715))));
+}
+
+// Outputs the ambient occlusion factor, which is multiplied into the render target by the
+// pipeline's blend state.
+@fragment
+fn ssao_fs_main(
+    in: SsaoVertexOutput,
+    @builtin(view_index) view_index: i32
+) -> @location(0) vec4<f32> {
+    let coords = vec2<i32>(in.position.xy);
+    let depth = textureLoad(ssao_depth, coords, view_index, 0);
+    // Nothing was drawn here.
+    if (depth >= 1.0) {
+        return vec4<f32>(1.0);
+    }
+    let center = ssao_linear_depth(depth);
+    let max_coords = textureDimensions(ssao_depth) - vec2<i32>(1);
+    let radius = ssao_params.radius / center;
+    let rotation = ssao_noise(in.position.xy) * SSAO_TAU;
+
+    var occlusion = 0.0;
+    for (var i = 0u; i < ssao_params.sample_count; i = i + 1u) {
+        let angle = f32(i) * SSAO_GOLDEN_ANGLE + rotation;
+        let distance = radius * sqrt((f32(i) + 0.5) / f32(ssao_params.sample_count));
+        let offset = vec2<f32>(cos(angle), sin(angle)) * distance;
+        let sample_coords = clamp(coords + vec2<i32>(offset), vec2<i32>(0), max_coords);
+        let sample_depth = ssao_linear_depth(textureLoad(ssao_depth, sample_coords, view_index, 0));
+        // Samples that are in front of the surface occlude it, unless they're so far in front
+        // that they belong to a separate object, which would otherwise leave dark halos.
+        let in_front = center - sample_depth;
+        if (in_front > center * 0.01) {
+            occlusion = occlusion + 1.0 - smoothstep(0.0, center * 0.25, in_front);
+        }
+    }
+
+    let ao = clamp(
+        1.0 - ssao_params.intensity * occlusion / f32(max(ssao_params.sample_count, 1u)),
+        0.0,
+        1.0
+    );
+    return vec4<f32>(ao, ao, ao, 1.0);
+}
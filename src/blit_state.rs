@@ -1,12 +1,152 @@
+use anyhow::Context;
 use glam::{vec3, Vec3};
-use std::borrow::Cow;
+use std::{borrow::Cow, collections::HashMap, num::NonZeroU32};
 use wgpu::util::DeviceExt;
 
+use crate::types::LoadBehavior;
+
+/// Parameters for the post-processing applied during the blit. Must match `BlitParams` in
+/// `blit_common.wgsl`.
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct BlitParams {
+    /// If non-zero, the render target is assumed to contain the overdraw output of
+    /// [crate::main_state::MainState], and is displayed as a heatmap.
+    pub overdraw_heatmap: u32,
+    /// If non-zero, a calibration checkerboard is shown at the output's full resolution in
+    /// place of the render target, to check that the image isn't cropped or scaled.
+    pub calibration_pattern: u32,
+    /// The fraction of the render target that each view occupies, from its top-left corner,
+    /// when blitting to the window. See [crate::camera::clip_to_top_left].
+    pub view_scales: [f32; 2],
+    /// The gamma applied to the output, to match different display calibrations; values above
+    /// 1 brighten the mid-tones. Clamped to [GAMMA_RANGE] by [Self::clamp_adjustments].
+    pub gamma: f32,
+    /// Added to the output after the gamma and contrast. Clamped to [BRIGHTNESS_RANGE].
+    pub brightness: f32,
+    /// Scales the output's distance from mid-grey. Clamped to [CONTRAST_RANGE].
+    pub contrast: f32,
+    _padding: f32,
+    /// The per-channel gain of each view's [ColorCorrection], padded to a `vec4`.
+    eye_gain: [[f32; 4]; 2],
+    /// The per-channel gamma of each view's [ColorCorrection], padded to a `vec4`.
+    eye_gamma: [[f32; 4]; 2],
+}
+impl Default for BlitParams {
+    fn default() -> Self {
+        Self {
+            overdraw_heatmap: 0,
+            calibration_pattern: 0,
+            view_scales: [1.0; 2],
+            gamma: 1.0,
+            brightness: 0.0,
+            contrast: 1.0,
+            _padding: 0.0,
+            eye_gain: [[1.0; 4]; 2],
+            eye_gamma: [[1.0; 4]; 2],
+        }
+    }
+}
+impl BlitParams {
+    /// Keeps the colour adjustments within ranges that still produce a usable image.
+    pub fn clamp_adjustments(&mut self) {
+        self.gamma = self.gamma.clamp(*GAMMA_RANGE.start(), *GAMMA_RANGE.end());
+        self.brightness = self
+            .brightness
+            .clamp(*BRIGHTNESS_RANGE.start(), *BRIGHTNESS_RANGE.end());
+        self.contrast = self
+            .contrast
+            .clamp(*CONTRAST_RANGE.start(), *CONTRAST_RANGE.end());
+        for gain in self.eye_gain.iter_mut().flatten() {
+            *gain = gain.clamp(*EYE_GAIN_RANGE.start(), *EYE_GAIN_RANGE.end());
+        }
+        for gamma in self.eye_gamma.iter_mut().flatten() {
+            *gamma = gamma.clamp(*GAMMA_RANGE.start(), *GAMMA_RANGE.end());
+        }
+    }
+
+    /// The colour correction applied to view `view_index` (0 or 1).
+    pub fn eye_correction(&self, view_index: usize) -> ColorCorrection {
+        let [r, g, b, _] = self.eye_gain[view_index];
+        let [gamma_r, gamma_g, gamma_b, _] = self.eye_gamma[view_index];
+        ColorCorrection {
+            gain: [r, g, b],
+            gamma: [gamma_r, gamma_g, gamma_b],
+        }
+    }
+
+    /// Sets the colour correction applied to view `view_index` (0 or 1). Like the other
+    /// adjustments, this should be followed by [Self::clamp_adjustments].
+    pub fn set_eye_correction(&mut self, view_index: usize, correction: ColorCorrection) {
+        let [r, g, b] = correction.gain;
+        let [gamma_r, gamma_g, gamma_b] = correction.gamma;
+        self.eye_gain[view_index] = [r, g, b, 1.0];
+        self.eye_gamma[view_index] = [gamma_r, gamma_g, gamma_b, 1.0];
+    }
+}
+
+/// A per-channel colour correction for one eye's display, to calibrate out differences between
+/// the eyes that a single adjustment can't fix. It's applied before the gamma, contrast and
+/// brightness of [BlitParams], which still apply to both eyes on top of it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "xr",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct ColorCorrection {
+    /// Multiplies the red, green and blue channels. Clamped to [EYE_GAIN_RANGE].
+    pub gain: [f32; 3],
+    /// The gamma of the red, green and blue channels, as with [BlitParams::gamma]. Clamped to
+    /// [GAMMA_RANGE].
+    pub gamma: [f32; 3],
+}
+impl Default for ColorCorrection {
+    /// No correction.
+    fn default() -> Self {
+        Self {
+            gain: [1.0; 3],
+            gamma: [1.0; 3],
+        }
+    }
+}
+
+/// The range that [BlitParams::gamma] can be adjusted within.
+pub const GAMMA_RANGE: std::ops::RangeInclusive<f32> = 0.5..=2.5;
+/// The range that [BlitParams::brightness] can be adjusted within.
+pub const BRIGHTNESS_RANGE: std::ops::RangeInclusive<f32> = -0.5..=0.5;
+/// The range that [BlitParams::contrast] can be adjusted within.
+pub const CONTRAST_RANGE: std::ops::RangeInclusive<f32> = 0.5..=2.0;
+/// The range that each channel of [ColorCorrection::gain] can be adjusted within.
+pub const EYE_GAIN_RANGE: std::ops::RangeInclusive<f32> = 0.5..=1.5;
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct BlitVertex {
+    position: [f32; 3],
+    uv_coords: [f32; 2],
+}
+impl BlitVertex {
+    fn new(position: Vec3, uv_coords: [f32; 2]) -> Self {
+        Self {
+            position: position.to_array(),
+            uv_coords,
+        }
+    }
+}
+
 pub struct BlitState {
     sampler: wgpu::Sampler,
+    params: BlitParams,
+    params_buffer: wgpu::Buffer,
     bind_group_layout: wgpu::BindGroupLayout,
     bind_group: wgpu::BindGroup,
-    render_pipeline_window: wgpu::RenderPipeline,
+    pipeline_layout: wgpu::PipelineLayout,
+    window_shader: wgpu::ShaderModule,
+    window_swapchain_format: wgpu::TextureFormat,
+    /// Pipelines for blitting a single view, by output format. This always includes the
+    /// window's format, and any formats added by [Self::prepare_output_format].
+    render_pipelines_window: HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>,
     render_pipeline_headset: wgpu::RenderPipeline,
     #[allow(dead_code)]
     vertex_buffer: wgpu::Buffer,
@@ -19,21 +159,6 @@ impl BlitState {
         window_swapchain_format: wgpu::TextureFormat,
         headset_swapchain_format: wgpu::TextureFormat,
     ) -> Self {
-        #[repr(C)]
-        #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-        struct BlitVertex {
-            position: [f32; 3],
-            uv_coords: [f32; 2],
-        }
-        impl BlitVertex {
-            fn new(position: Vec3, uv_coords: [f32; 2]) -> Self {
-                Self {
-                    position: position.to_array(),
-                    uv_coords,
-                }
-            }
-        }
-
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::ClampToEdge,
             address_mode_v: wgpu::AddressMode::ClampToEdge,
@@ -44,6 +169,13 @@ impl BlitState {
             ..Default::default()
         });
 
+        let params = BlitParams::default();
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Blit Params Buffer"),
+            contents: bytemuck::bytes_of(&params),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             entries: &[
                 wgpu::BindGroupLayoutEntry {
@@ -62,11 +194,26 @@ impl BlitState {
                     ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
             ],
             label: Some("bind_group_layout"),
         });
-        let bind_group =
-            Self::create_bind_group(device, &bind_group_layout, render_target_view, &sampler);
+        let bind_group = Self::create_bind_group(
+            device,
+            &bind_group_layout,
+            render_target_view,
+            &sampler,
+            &params_buffer,
+        );
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: None,
@@ -76,53 +223,32 @@ impl BlitState {
                 range: 0..4,
             }],
         });
-        let vertex_buffer_layout = wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<BlitVertex>() as _,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    offset: 0,
-                    shader_location: 0,
-                    format: wgpu::VertexFormat::Float32x3,
-                },
-                wgpu::VertexAttribute {
-                    offset: std::mem::size_of::<[f32; 3]>() as _,
-                    shader_location: 1,
-                    format: wgpu::VertexFormat::Float32x2,
-                },
-            ],
-        };
-
-        let [render_pipeline_window, render_pipeline_headset] = [
-            ("blit_window.wgsl", window_swapchain_format, None),
-            ("blit_headset.wgsl", headset_swapchain_format, Some(2)),
-        ]
-        .map(|(filename, swapchain_format, multiview)| {
-            let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-                label: None,
-                source: wgpu::ShaderSource::Wgsl(Cow::Owned(
-                    preprocessor.preprocess(filename).unwrap(),
-                )),
+        let [window_shader, headset_shader] =
+            ["blit_window.wgsl", "blit_headset.wgsl"].map(|filename| {
+                device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                    label: None,
+                    source: wgpu::ShaderSource::Wgsl(Cow::Owned(
+                        preprocessor.preprocess(filename).unwrap(),
+                    )),
+                })
             });
-            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: None,
-                layout: Some(&pipeline_layout),
-                vertex: wgpu::VertexState {
-                    module: &shader,
-                    entry_point: "blit_vs_main",
-                    buffers: &[vertex_buffer_layout.clone()],
-                },
-                fragment: Some(wgpu::FragmentState {
-                    module: &shader,
-                    entry_point: "blit_fs_main",
-                    targets: &[Some(swapchain_format.into())],
-                }),
-                primitive: wgpu::PrimitiveState::default(),
-                depth_stencil: None,
-                multisample: wgpu::MultisampleState::default(),
-                multiview: multiview.map(|i| i.try_into().unwrap()),
-            })
-        });
+        let render_pipelines_window = HashMap::from([(
+            window_swapchain_format,
+            Self::create_pipeline(
+                device,
+                &pipeline_layout,
+                &window_shader,
+                window_swapchain_format,
+                None,
+            ),
+        )]);
+        let render_pipeline_headset = Self::create_pipeline(
+            device,
+            &pipeline_layout,
+            &headset_shader,
+            headset_swapchain_format,
+            Some(2),
+        );
 
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Blit Vertex Buffer"),
@@ -140,9 +266,14 @@ impl BlitState {
 
         BlitState {
             sampler,
+            params,
+            params_buffer,
             bind_group_layout,
             bind_group,
-            render_pipeline_window,
+            pipeline_layout,
+            window_shader,
+            window_swapchain_format,
+            render_pipelines_window,
             render_pipeline_headset,
             vertex_buffer,
         }
@@ -154,14 +285,83 @@ impl BlitState {
             &self.bind_group_layout,
             render_target_view,
             &self.sampler,
+            &self.params_buffer,
         );
     }
 
+    pub fn params(&self) -> &BlitParams {
+        &self.params
+    }
+
+    pub fn set_params(&mut self, queue: &wgpu::Queue, params: BlitParams) {
+        self.params = params;
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::bytes_of(&self.params));
+    }
+
+    /// Prepares to blit views to textures of `format` with [Self::encode_draw_pass_to], for
+    /// textures other than the window's, such as the viewport of a larger application.
+    #[cfg_attr(not(feature = "xr"), allow(dead_code))]
+    pub fn prepare_output_format(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat) {
+        if !self.render_pipelines_window.contains_key(&format) {
+            let pipeline = Self::create_pipeline(
+                device,
+                &self.pipeline_layout,
+                &self.window_shader,
+                format,
+                None,
+            );
+            self.render_pipelines_window.insert(format, pipeline);
+        }
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        pipeline_layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        format: wgpu::TextureFormat,
+        multiview: Option<u32>,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "blit_vs_main",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<BlitVertex>() as _,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &[
+                        wgpu::VertexAttribute {
+                            offset: 0,
+                            shader_location: 0,
+                            format: wgpu::VertexFormat::Float32x3,
+                        },
+                        wgpu::VertexAttribute {
+                            offset: std::mem::size_of::<[f32; 3]>() as _,
+                            shader_location: 1,
+                            format: wgpu::VertexFormat::Float32x2,
+                        },
+                    ],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "blit_fs_main",
+                targets: &[Some(format.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: multiview.and_then(NonZeroU32::new),
+        })
+    }
+
     fn create_bind_group(
         device: &wgpu::Device,
         bind_group_layout: &wgpu::BindGroupLayout,
         render_target_view: &wgpu::TextureView,
         sampler: &wgpu::Sampler,
+        params_buffer: &wgpu::Buffer,
     ) -> wgpu::BindGroup {
         device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: bind_group_layout,
@@ -174,17 +374,57 @@ impl BlitState {
                     binding: 1,
                     resource: wgpu::BindingResource::Sampler(sampler),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: params_buffer.as_entire_binding(),
+                },
             ],
             label: Some("blit_bind_group"),
         })
     }
 
+    /// The blit currently overwrites the whole output, so `load` won't make a visible
+    /// difference unless the blit is changed to blend.
     pub fn encode_draw_pass(
         &self,
         encoder: &mut wgpu::CommandEncoder,
         output_view: &wgpu::TextureView,
         // if none, assumed to be blitting to the headset
         view_index: Option<u32>,
+        load: LoadBehavior,
+    ) {
+        let pipeline = match view_index {
+            Some(_) => &self.render_pipelines_window[&self.window_swapchain_format],
+            None => &self.render_pipeline_headset,
+        };
+        self.encode_pass(encoder, output_view, pipeline, view_index, load);
+    }
+
+    /// Blits view `view_index` of the render target to `output_view`, a caller-provided
+    /// texture of any size, whose `format` has been passed to [Self::prepare_output_format].
+    /// The view is stretched to fill the texture.
+    pub fn encode_draw_pass_to(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        output_view: &wgpu::TextureView,
+        format: wgpu::TextureFormat,
+        view_index: u32,
+        load: LoadBehavior,
+    ) -> anyhow::Result<()> {
+        let pipeline = self.render_pipelines_window.get(&format).with_context(|| {
+            format!("the blit hasn't been prepared for {format:?} output textures")
+        })?;
+        self.encode_pass(encoder, output_view, pipeline, Some(view_index), load);
+        Ok(())
+    }
+
+    fn encode_pass(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        output_view: &wgpu::TextureView,
+        pipeline: &wgpu::RenderPipeline,
+        view_index: Option<u32>,
+        load: LoadBehavior,
     ) {
         let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: None,
@@ -192,21 +432,19 @@ impl BlitState {
                 view: output_view,
                 resolve_target: None,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    load: load.load_op(wgpu::Color::BLACK),
                     store: true,
                 },
             })],
             depth_stencil_attachment: None,
         });
+        rpass.set_pipeline(pipeline);
         if let Some(view_index) = view_index {
-            rpass.set_pipeline(&self.render_pipeline_window);
             rpass.set_push_constants(
                 wgpu::ShaderStages::FRAGMENT,
                 0,
                 bytemuck::cast_slice(&[view_index]),
             );
-        } else {
-            rpass.set_pipeline(&self.render_pipeline_headset);
         }
         rpass.set_bind_group(0, &self.bind_group, &[]);
         rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
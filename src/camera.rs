@@ -1,6 +1,53 @@
 use glam::{vec3, vec4, Mat4, Vec3};
 use wgpu::util::DeviceExt;
 
+use crate::types::VIEW_COUNT;
+
+/// The distance between the eyes, in metres, when not using XR.
+const DESKTOP_IPD: f32 = 63.0 / 1_000.0;
+
+/// How the scene is rendered when not using XR.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum DesktopView {
+    /// A view for each eye, as in a headset, either of which can be shown in the window.
+    #[default]
+    Stereo,
+    /// A single view from between the eyes, for a flat display. Only one view is rendered, so
+    /// this does half the work of the stereo preview.
+    Mono,
+}
+impl DesktopView {
+    /// The number of views that are rendered with multiview.
+    pub fn view_count(self) -> u32 {
+        match self {
+            Self::Stereo => VIEW_COUNT,
+            Self::Mono => 1,
+        }
+    }
+}
+
+/// The view and projection matrices of each eye, kept separate so that code outside of the
+/// main pass (e.g. custom post-processing or overlays) can reconstruct the world, view and clip
+/// space transforms, not just the combined view-projection that the shaders use.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EyeMatrices {
+    pub view: [Mat4; VIEW_COUNT as usize],
+    pub projection: [Mat4; VIEW_COUNT as usize],
+}
+impl EyeMatrices {
+    pub fn view_projection(&self) -> [Mat4; VIEW_COUNT as usize] {
+        [0, 1].map(|i| self.projection[i] * self.view[i])
+    }
+
+    /// The view-projection matrices, flattened for upload to the camera buffer.
+    pub fn to_view_proj_matrices(self) -> Vec<f32> {
+        self.view_projection()
+            .iter()
+            .flat_map(|m| m.to_cols_array())
+            .collect()
+    }
+}
+
 pub struct PerspectiveCamera {
     pub eye: Vec3,
     pub target: Vec3,
@@ -11,67 +58,130 @@ pub struct PerspectiveCamera {
 
     pub z_near: f32,
     pub z_far: f32,
+
+    /// The distance at which the two eyes' images line up (i.e. have zero disparity) when not
+    /// using XR. Closer objects appear in front of the screen, and further objects behind it.
+    /// An infinite distance keeps the eyes parallel.
+    pub convergence: f32,
+    /// With [DesktopView::Mono], both eyes are at [Self::eye], so the matrices of the first eye
+    /// are those of the single view.
+    pub desktop_view: DesktopView,
 }
 impl PerspectiveCamera {
     pub fn to_view_proj_matrices(&self) -> Vec<f32> {
-        let ipd = 63.0 / 1_000.0;
-        let offset = vec4(ipd / 2.0, 0.0, 0.0, 0.0);
+        self.eye_matrices().to_view_proj_matrices()
+    }
+
+    /// The view and projection matrices of each eye when not using XR.
+    pub fn eye_matrices(&self) -> EyeMatrices {
+        let offset = vec4(self.ipd() / 2.0, 0.0, 0.0, 0.0);
 
         let view = Mat4::look_at_rh(self.eye, self.target, self.up);
         let proj = Mat4::perspective_rh(self.fov_y_rad, self.aspect_ratio, self.z_near, self.z_far);
 
-        [-offset, offset]
-            .map(|o| {
-                let mut view = view;
-                view.w_axis += view * o;
-                (proj * view).to_cols_array()
-            })
-            .concat()
+        let [left, right] = [-offset, offset].map(|o| {
+            let mut view = view;
+            let shift = view * o;
+            view.w_axis += shift;
+            // Shift the frustum sideways, rather than rotating the eyes inwards, so that a
+            // point at the convergence distance lands in the same place for both eyes.
+            let mut proj = proj;
+            proj.z_axis.x += proj.x_axis.x * shift.x / self.convergence;
+            (view, proj)
+        });
+        EyeMatrices {
+            view: [left.0, right.0],
+            projection: [left.1, right.1],
+        }
+    }
+
+    /// The world-space positions of the left and right eyes.
+    pub fn eye_positions(&self) -> [Vec3; 2] {
+        let right = (self.target - self.eye).cross(self.up).normalize();
+        [-1.0, 1.0].map(|side| self.eye + right * side * self.ipd() / 2.0)
+    }
+
+    fn ipd(&self) -> f32 {
+        match self.desktop_view {
+            DesktopView::Stereo => DESKTOP_IPD,
+            DesktopView::Mono => 0.0,
+        }
     }
 
     #[cfg(feature = "xr")]
-    pub fn to_view_proj_matrices_with_xr_views(&self, views: &[openxr::View]) -> Vec<f32> {
-        views
-            .iter()
-            .flat_map(|v| {
-                let pose = v.pose;
-                let (xr_translation, xr_rotation) = crate::xr::openxr_pose_to_glam(&pose);
-
-                let view = Mat4::look_at_rh(
-                    self.eye + xr_translation,
-                    self.eye + xr_translation + xr_rotation * Vec3::Z,
-                    xr_rotation * Vec3::Y,
-                );
-
-                let [tan_left, tan_right, tan_down, tan_up] = [
-                    v.fov.angle_left,
-                    v.fov.angle_right,
-                    v.fov.angle_down,
-                    v.fov.angle_up,
-                ]
-                .map(f32::tan);
-                let tan_width = tan_right - tan_left;
-                let tan_height = tan_up - tan_down;
-
-                let a11 = 2.0 / tan_width;
-                let a22 = 2.0 / tan_height;
-
-                let a31 = (tan_right + tan_left) / tan_width;
-                let a32 = (tan_up + tan_down) / tan_height;
-                let a33 = -self.z_far / (self.z_far - self.z_near);
-
-                let a43 = -(self.z_far * self.z_near) / (self.z_far - self.z_near);
-
-                let proj = glam::Mat4::from_cols_array(&[
-                    a11, 0.0, 0.0, 0.0, //
-                    0.0, a22, 0.0, 0.0, //
-                    a31, a32, a33, -1.0, //
-                    0.0, 0.0, a43, 0.0, //
-                ]);
-
-                (proj * view).to_cols_array()
-            })
-            .collect()
+    pub fn eye_positions_with_xr_views(
+        &self,
+        views: &[openxr::View],
+        axis_convention: &crate::xr::AxisConvention,
+    ) -> [Vec3; 2] {
+        [0, 1].map(|i| self.eye + axis_convention.pose_to_glam(&views[i].pose).0)
+    }
+
+    #[cfg(feature = "xr")]
+    pub fn to_view_proj_matrices_with_xr_views(
+        &self,
+        views: &[openxr::View],
+        axis_convention: &crate::xr::AxisConvention,
+    ) -> Vec<f32> {
+        self.eye_matrices_with_xr_views(views, axis_convention)
+            .map(|m| m.to_view_proj_matrices())
+            .unwrap_or_default()
+    }
+
+    /// The view and projection matrices of each eye, for the given XR views, or `None` if
+    /// there aren't any views yet (e.g. while the swapchain is being created).
+    #[cfg(feature = "xr")]
+    pub fn eye_matrices_with_xr_views(
+        &self,
+        views: &[openxr::View],
+        axis_convention: &crate::xr::AxisConvention,
+    ) -> Option<EyeMatrices> {
+        if views.len() < VIEW_COUNT as usize {
+            return None;
+        }
+        let [left, right] = [0, 1].map(|i| {
+            let v = &views[i];
+            let pose = v.pose;
+            let (xr_translation, xr_rotation) = axis_convention.pose_to_glam(&pose);
+
+            let view = Mat4::look_at_rh(
+                self.eye + xr_translation,
+                self.eye + xr_translation + xr_rotation * axis_convention.forward(),
+                xr_rotation * axis_convention.up(),
+            );
+
+            let [tan_left, tan_right, tan_down, tan_up] = [
+                v.fov.angle_left,
+                v.fov.angle_right,
+                v.fov.angle_down,
+                v.fov.angle_up,
+            ]
+            .map(f32::tan);
+            let tan_width = tan_right - tan_left;
+            let tan_height = tan_up - tan_down;
+
+            let a11 = 2.0 / tan_width;
+            let a22 = 2.0 / tan_height;
+
+            let a31 = (tan_right + tan_left) / tan_width;
+            let a32 = (tan_up + tan_down) / tan_height;
+            let a33 = -self.z_far / (self.z_far - self.z_near);
+
+            let a43 = -(self.z_far * self.z_near) / (self.z_far - self.z_near);
+
+            let proj = glam::Mat4::from_cols_array(&[
+                a11, 0.0, 0.0, 0.0, //
+                0.0, a22, 0.0, 0.0, //
+                a31, a32, a33, -1.0, //
+                0.0, 0.0, a43, 0.0, //
+            ]);
+
+            (view, proj)
+        });
+        Some(EyeMatrices {
+            view: [left.0, right.0],
+            projection: [left.1, right.1],
+        })
     }
 
     pub fn resize(&mut self, inner_size: winit::dpi::PhysicalSize<u32>) {
@@ -79,15 +189,51 @@ impl PerspectiveCamera {
     }
 }
 
+/// Maps clip space so that a view is rendered to the top-left `scale` of the render target on
+/// each axis, rather than all of it. Everything outside of that corner is left unused.
+#[cfg_attr(not(feature = "xr"), allow(dead_code))]
+pub fn clip_to_top_left(scale: f32) -> Mat4 {
+    Mat4::from_cols(
+        vec4(scale, 0.0, 0.0, 0.0),
+        vec4(0.0, scale, 0.0, 0.0),
+        vec4(0.0, 0.0, 1.0, 0.0),
+        vec4(scale - 1.0, 1.0 - scale, 0.0, 1.0),
+    )
+}
+
+/// Must match `FrameTime` in `camera.wgsl`.
+#[repr(C)]
+#[derive(Copy, Clone, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct FrameTime {
+    seconds: f32,
+    delta_seconds: f32,
+    _padding: [f32; 2],
+}
+
 pub struct CameraState {
     pub data: PerspectiveCamera,
 
     buffer: wgpu::Buffer,
+    /// The view-projection matrices from the previous [Self::write_matrices], so that shaders
+    /// can work out how far each point has moved on screen since the last frame.
+    previous_buffer: wgpu::Buffer,
+    matrices: Vec<f32>,
+    /// The time for shader animation, which is shared by everything that uses the camera.
+    time_buffer: wgpu::Buffer,
+    /// The time last written with [Self::write_time], in seconds.
+    time: Option<f64>,
     bind_group_layout: wgpu::BindGroupLayout,
     bind_group: wgpu::BindGroup,
+    view_count: u32,
 }
 impl CameraState {
-    pub fn new(device: &wgpu::Device, inner_size: winit::dpi::PhysicalSize<u32>) -> Self {
+    /// `desktop_view` is how the scene is rendered when not using XR; it must be
+    /// [DesktopView::Stereo] when using XR.
+    pub fn new(
+        device: &wgpu::Device,
+        inner_size: winit::dpi::PhysicalSize<u32>,
+        desktop_view: DesktopView,
+    ) -> Self {
         let data = PerspectiveCamera {
             eye: Vec3::ZERO,
             target: vec3(0.0, 0.0, 1.0),
@@ -98,44 +244,123 @@ impl CameraState {
 
             z_near: 0.05,
             z_far: 1000.0,
+
+            convergence: f32::INFINITY,
+            desktop_view,
         };
-        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Camera buffer"),
-            contents: bytemuck::cast_slice(&data.to_view_proj_matrices()),
+        let matrices = data.to_view_proj_matrices();
+        let [buffer, previous_buffer] = ["Camera buffer", "Previous Camera buffer"].map(|label| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: bytemuck::cast_slice(&matrices),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            })
+        });
+        let time_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Frame Time Buffer"),
+            contents: bytemuck::bytes_of(&FrameTime::default()),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("Camera Bind Group Layout"),
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX,
+            entries: &[
+                (0, wgpu::ShaderStages::VERTEX),
+                (1, wgpu::ShaderStages::VERTEX),
+                (2, wgpu::ShaderStages::VERTEX_FRAGMENT),
+            ]
+            .map(|(binding, visibility)| wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility,
                 ty: wgpu::BindingType::Buffer {
                     ty: wgpu::BufferBindingType::Uniform,
                     has_dynamic_offset: false,
                     min_binding_size: None,
                 },
                 count: None,
-            }],
-        });
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Camera Bind Group"),
-            layout: &bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: buffer.as_entire_binding(),
-            }],
+            }),
         });
+        let bind_group = Self::create_bind_group(
+            device,
+            &bind_group_layout,
+            "Camera Bind Group",
+            &buffer,
+            &previous_buffer,
+            &time_buffer,
+        );
 
         Self {
             data,
             buffer,
+            previous_buffer,
+            matrices,
+            time_buffer,
+            time: None,
             bind_group_layout,
             bind_group,
+            view_count: desktop_view.view_count(),
         }
     }
 
-    pub fn buffer(&self) -> &wgpu::Buffer {
-        &self.buffer
+    /// The number of views rendered with multiview, which the pipelines that use the camera's
+    /// bind group must be created with, and the render targets must have a layer for each of.
+    /// The camera buffers always hold [VIEW_COUNT] matrices; the views use the first of them.
+    pub fn view_count(&self) -> u32 {
+        self.view_count
+    }
+
+    /// Creates a bind group for [Self::bind_group_layout] from the given buffers of current and
+    /// previous view-projection matrices. A camera that doesn't move can use the same buffer
+    /// for both. `time_buffer` is usually [Self::time_buffer].
+    pub fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        label: &str,
+        buffer: &wgpu::Buffer,
+        previous_buffer: &wgpu::Buffer,
+        time_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(label),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: previous_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: time_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    /// Uploads this frame's view-projection matrices, and moves the last frame's into the
+    /// previous matrices.
+    pub fn write_matrices(&mut self, queue: &wgpu::Queue, matrices: Vec<f32>) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&matrices));
+        let previous = std::mem::replace(&mut self.matrices, matrices);
+        queue.write_buffer(&self.previous_buffer, 0, bytemuck::cast_slice(&previous));
+    }
+
+    /// Uploads the time for shader animation, in seconds since the application started, along
+    /// with how long it has been since the last call.
+    pub fn write_time(&mut self, queue: &wgpu::Queue, seconds: f64) {
+        let delta_seconds = self.time.map_or(0.0, |last| (seconds - last).max(0.0));
+        self.time = Some(seconds);
+        let time = FrameTime {
+            seconds: seconds as f32,
+            delta_seconds: delta_seconds as f32,
+            _padding: [0.0; 2],
+        };
+        queue.write_buffer(&self.time_buffer, 0, bytemuck::bytes_of(&time));
+    }
+    pub fn time_buffer(&self) -> &wgpu::Buffer {
+        &self.time_buffer
     }
     pub fn bind_group(&self) -> &wgpu::BindGroup {
         &self.bind_group
@@ -144,3 +369,90 @@ impl CameraState {
         &self.bind_group_layout
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Mat4;
+
+    fn camera(convergence: f32) -> PerspectiveCamera {
+        PerspectiveCamera {
+            eye: vec3(0.5, 1.0, 0.0),
+            target: vec3(0.5, 1.0, 1.0),
+            up: Vec3::Y,
+            aspect_ratio: 1.0,
+            fov_y_rad: 90.0f32.to_radians(),
+            z_near: 0.05,
+            z_far: 1000.0,
+            convergence,
+            desktop_view: DesktopView::Stereo,
+        }
+    }
+
+    fn project(matrices: &[f32], eye: usize, point: Vec3) -> f32 {
+        let matrix = Mat4::from_cols_slice(&matrices[eye * 16..(eye + 1) * 16]);
+        matrix.project_point3(point).x
+    }
+
+    #[test]
+    fn convergence_distance_has_zero_disparity() {
+        let matrices = camera(2.0).to_view_proj_matrices();
+        let converged = vec3(0.5, 1.0, 2.0);
+        let disparity = project(&matrices, 0, converged) - project(&matrices, 1, converged);
+        assert!(disparity.abs() < 1e-5);
+
+        let far = vec3(0.5, 1.0, 10.0);
+        let disparity = project(&matrices, 0, far) - project(&matrices, 1, far);
+        assert!(disparity.abs() > 1e-3);
+    }
+
+    #[test]
+    fn mono_views_are_from_between_the_eyes() {
+        let mono = PerspectiveCamera {
+            desktop_view: DesktopView::Mono,
+            ..camera(f32::INFINITY)
+        };
+        let matrices = mono.to_view_proj_matrices();
+        assert_eq!(matrices[..16], matrices[16..]);
+        assert!(project(&matrices, 0, vec3(0.5, 1.0, 2.0)).abs() < 1e-5);
+        let [left, right] = mono.eye_positions();
+        assert_eq!((left, right), (mono.eye, mono.eye));
+    }
+
+    #[test]
+    fn infinite_convergence_keeps_eyes_parallel() {
+        let parallel = camera(f32::INFINITY).to_view_proj_matrices();
+        let point = vec3(0.5, 1.0, 2.0);
+        // With parallel eyes, the disparity is the IPD projected at the point's depth.
+        let disparity = project(&parallel, 0, point) - project(&parallel, 1, point);
+        assert!((disparity.abs() - DESKTOP_IPD / 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn eye_matrices_separate_view_and_projection() {
+        let matrices = camera(2.0).eye_matrices();
+        let point = vec3(0.5, 1.0, 2.0);
+        for eye in 0..2 {
+            // The camera looks down +Z, which is -Z in view space.
+            let in_view = matrices.view[eye].transform_point3(point);
+            assert!((in_view.z + 2.0).abs() < 1e-5);
+            assert!(matrices.view_projection()[eye]
+                .project_point3(point)
+                .abs_diff_eq(matrices.projection[eye].project_point3(in_view), 1e-5));
+        }
+    }
+
+    #[test]
+    fn clip_to_top_left_maps_the_clip_volume_into_the_corner() {
+        let matrix = clip_to_top_left(0.25);
+        // The top-left corner stays put, and the bottom-right moves to a quarter of the way
+        // across, regardless of W.
+        for w in [1.0, 3.0] {
+            let top_left = matrix * vec4(-w, w, 0.5, w);
+            assert!((top_left.truncate() / top_left.w).abs_diff_eq(vec3(-1.0, 1.0, 0.5 / w), 1e-6));
+            let bottom_right = matrix * vec4(w, -w, 0.5, w);
+            assert!((bottom_right.truncate() / bottom_right.w)
+                .abs_diff_eq(vec3(-0.5, 0.5, 0.5 / w), 1e-6));
+        }
+    }
+}
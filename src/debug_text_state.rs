@@ -0,0 +1,437 @@
+use glam::{Vec2, Vec3, Vec4};
+use std::{borrow::Cow, num::NonZeroU32};
+use wgpu::util::DeviceExt;
+
+use crate::{camera::CameraState, growable_buffer::GrowableBuffer, types::RenderRegion};
+
+/// The glyphs available to the debug text renderer, as 5x7 bitmaps. Each row is stored in
+/// the low five bits of a byte, with the most significant bit being the leftmost pixel.
+/// Lowercase letters are rendered as uppercase, and unknown characters are rendered as `?`.
+const GLYPHS: &[(char, [u8; 7])] = &[
+    (' ', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    ('!', [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04]),
+    ('#', [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A]),
+    ('%', [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03]),
+    ('\'', [0x0C, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00]),
+    ('(', [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02]),
+    (')', [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08]),
+    ('*', [0x00, 0x04, 0x15, 0x0E, 0x15, 0x04, 0x00]),
+    ('+', [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00]),
+    (',', [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08]),
+    ('-', [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00]),
+    ('.', [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C]),
+    ('/', [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00]),
+    ('0', [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E]),
+    ('1', [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('2', [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F]),
+    ('3', [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E]),
+    ('4', [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02]),
+    ('5', [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E]),
+    ('6', [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E]),
+    ('7', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08]),
+    ('8', [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E]),
+    ('9', [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C]),
+    (':', [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00]),
+    ('<', [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02]),
+    ('=', [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00]),
+    ('>', [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08]),
+    ('?', [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04]),
+    ('A', [0x0E, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11]),
+    ('B', [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E]),
+    ('C', [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E]),
+    ('D', [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C]),
+    ('E', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F]),
+    ('F', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10]),
+    ('G', [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F]),
+    ('H', [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11]),
+    ('I', [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('J', [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C]),
+    ('K', [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11]),
+    ('L', [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F]),
+    ('M', [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11]),
+    ('N', [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11]),
+    ('O', [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('P', [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10]),
+    ('Q', [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D]),
+    ('R', [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11]),
+    ('S', [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E]),
+    ('T', [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04]),
+    ('U', [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('V', [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04]),
+    ('W', [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A]),
+    ('X', [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11]),
+    ('Y', [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04]),
+    ('Z', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F]),
+    ('[', [0x0E, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0E]),
+    (']', [0x0E, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0E]),
+    ('_', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F]),
+    ('|', [0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04]),
+];
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+/// The horizontal and vertical distance between glyphs, in font pixels.
+const GLYPH_ADVANCE: (f32, f32) = (6.0, 9.0);
+/// How many physical pixels a font pixel covers.
+const FONT_PIXEL_SCALE: f32 = 2.0;
+
+/// Where a [TextLabel] is centred.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TextAnchor {
+    /// A position in the world, which is projected through each view's view-projection matrix.
+    World(Vec3),
+    /// A position in normalized device coordinates, which is the same for every view.
+    Screen(Vec2),
+}
+
+/// A piece of text to be drawn on top of the scene.
+pub struct TextLabel {
+    pub anchor: TextAnchor,
+    pub text: String,
+    pub color: Vec4,
+    /// A multiplier on the size of the text.
+    pub scale: f32,
+    /// A bitmask of the views this label is visible in; bit `i` corresponds to view `i`.
+    pub view_mask: u32,
+}
+impl TextLabel {
+    pub const ALL_VIEWS: u32 = u32::MAX;
+
+    pub fn world(anchor: Vec3, text: impl Into<String>, color: Vec4) -> Self {
+        Self {
+            anchor: TextAnchor::World(anchor),
+            text: text.into(),
+            color,
+            scale: 1.0,
+            view_mask: Self::ALL_VIEWS,
+        }
+    }
+
+    pub fn screen(anchor: Vec2, text: impl Into<String>, color: Vec4) -> Self {
+        Self {
+            anchor: TextAnchor::Screen(anchor),
+            ..Self::world(Vec3::ZERO, text, color)
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct GlyphInstance {
+    anchor: [f32; 3],
+    offset: [f32; 2],
+    glyph: u32,
+    color: [f32; 4],
+    scale: f32,
+    view_mask: u32,
+    screen_space: u32,
+}
+
+/// Renders text labels on top of the scene. Labels anchored to a world position are projected
+/// through each view's view-projection matrix, so that the text always faces the viewer and
+/// stays the same size on screen.
+pub struct DebugTextState {
+    #[allow(dead_code)]
+    font_texture: wgpu::Texture,
+    params_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+    instance_buffer: GrowableBuffer,
+    instance_count: u32,
+}
+impl DebugTextState {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        preprocessor: &crate::wgsl::Preprocessor,
+        camera_state: &CameraState,
+        swapchain_format: wgpu::TextureFormat,
+        inner_size: winit::dpi::PhysicalSize<u32>,
+    ) -> Self {
+        let font_texture = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("Debug Font Texture"),
+                size: wgpu::Extent3d {
+                    width: GLYPH_WIDTH * GLYPHS.len() as u32,
+                    height: GLYPH_HEIGHT,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::R8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            },
+            &Self::font_texture_data(),
+        );
+        let font_view = font_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Debug Text Params Buffer"),
+            contents: bytemuck::cast_slice(&Self::glyph_pixel_size(inner_size)),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Debug Text Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Debug Text Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&font_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: params_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(Cow::Owned(
+                preprocessor.preprocess("debug_text.wgsl").unwrap(),
+            )),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[camera_state.bind_group_layout(), &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Debug Text Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "text_vs_main",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<GlyphInstance>() as _,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &wgpu::vertex_attr_array![
+                        0 => Float32x3,
+                        1 => Float32x2,
+                        2 => Uint32,
+                        3 => Float32x4,
+                        4 => Float32,
+                        5 => Uint32,
+                        6 => Uint32,
+                    ],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "text_fs_main",
+                targets: &[Some(swapchain_format.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: NonZeroU32::new(camera_state.view_count()),
+        });
+
+        let instance_buffer = GrowableBuffer::new(
+            device,
+            "Debug Text Instance Buffer",
+            wgpu::BufferUsages::VERTEX,
+            (256 * std::mem::size_of::<GlyphInstance>()) as _,
+        );
+
+        Self {
+            font_texture,
+            params_buffer,
+            bind_group,
+            pipeline,
+            instance_buffer,
+            instance_count: 0,
+        }
+    }
+
+    pub fn resize(&self, queue: &wgpu::Queue, inner_size: winit::dpi::PhysicalSize<u32>) {
+        queue.write_buffer(
+            &self.params_buffer,
+            0,
+            bytemuck::cast_slice(&Self::glyph_pixel_size(inner_size)),
+        );
+    }
+
+    /// Replaces the labels to be drawn with `labels`.
+    pub fn upload_labels(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        labels: &[TextLabel],
+    ) {
+        let instances: Vec<_> = labels.iter().flat_map(Self::layout_label).collect();
+        self.instance_buffer
+            .write(device, queue, bytemuck::cast_slice(&instances));
+        self.instance_count = instances.len() as u32;
+    }
+
+    pub fn encode_draw_pass(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        rt_view: &wgpu::TextureView,
+        region: &RenderRegion,
+        camera_bind_group: &wgpu::BindGroup,
+    ) {
+        if self.instance_count == 0 {
+            return;
+        }
+
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Debug Text Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: rt_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        region.apply(&mut rpass);
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, camera_bind_group, &[]);
+        rpass.set_bind_group(1, &self.bind_group, &[]);
+        rpass.set_vertex_buffer(0, self.instance_buffer.buffer().slice(..));
+        rpass.draw(0..6, 0..self.instance_count);
+    }
+
+    fn glyph_pixel_size(inner_size: winit::dpi::PhysicalSize<u32>) -> [f32; 4] {
+        // Normalized device coordinates span two units across the viewport.
+        [
+            2.0 * FONT_PIXEL_SCALE / inner_size.width.max(1) as f32,
+            2.0 * FONT_PIXEL_SCALE / inner_size.height.max(1) as f32,
+            0.0,
+            0.0,
+        ]
+    }
+
+    fn font_texture_data() -> Vec<u8> {
+        let width = GLYPH_WIDTH as usize * GLYPHS.len();
+        let mut data = vec![0; width * GLYPH_HEIGHT as usize];
+        for (glyph_index, (_, rows)) in GLYPHS.iter().enumerate() {
+            for (y, row) in rows.iter().enumerate() {
+                for x in 0..GLYPH_WIDTH as usize {
+                    if row & (1 << (GLYPH_WIDTH as usize - 1 - x)) != 0 {
+                        data[y * width + glyph_index * GLYPH_WIDTH as usize + x] = u8::MAX;
+                    }
+                }
+            }
+        }
+        data
+    }
+
+    fn glyph_index(c: char) -> u32 {
+        let c = c.to_ascii_uppercase();
+        GLYPHS
+            .iter()
+            .position(|(g, _)| *g == c)
+            .or_else(|| GLYPHS.iter().position(|(g, _)| *g == '?'))
+            .unwrap() as u32
+    }
+
+    fn layout_label(label: &TextLabel) -> impl Iterator<Item = GlyphInstance> + '_ {
+        // Centre the block of text on the anchor.
+        let columns = label.text.lines().map(|l| l.chars().count()).max();
+        let lines = label.text.lines().count();
+        let origin = [
+            -(columns.unwrap_or_default() as f32 * GLYPH_ADVANCE.0 - 1.0) / 2.0,
+            ((lines.max(1) - 1) as f32 * GLYPH_ADVANCE.1 - GLYPH_HEIGHT as f32) / 2.0,
+        ];
+        let (anchor, screen_space) = match label.anchor {
+            TextAnchor::World(anchor) => (anchor, false),
+            TextAnchor::Screen(anchor) => (anchor.extend(0.0), true),
+        };
+
+        label
+            .text
+            .lines()
+            .enumerate()
+            .flat_map(move |(line, text)| {
+                text.chars()
+                    .enumerate()
+                    .filter(|(_, c)| *c != ' ')
+                    .map(move |(column, c)| GlyphInstance {
+                        anchor: anchor.to_array(),
+                        offset: [
+                            origin[0] + column as f32 * GLYPH_ADVANCE.0,
+                            origin[1] - line as f32 * GLYPH_ADVANCE.1,
+                        ],
+                        glyph: Self::glyph_index(c),
+                        color: label.color.to_array(),
+                        scale: label.scale,
+                        view_mask: label.view_mask,
+                        screen_space: screen_space.into(),
+                    })
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec3, Vec4};
+
+    use super::{DebugTextState, TextLabel, GLYPHS, GLYPH_ADVANCE, GLYPH_HEIGHT};
+
+    #[test]
+    fn layout_centres_text_on_anchor() {
+        let label = TextLabel::world(Vec3::ZERO, "ab", Vec4::ONE);
+        let glyphs: Vec<_> = DebugTextState::layout_label(&label).collect();
+
+        let width = 2.0 * GLYPH_ADVANCE.0 - 1.0;
+        assert_eq!(
+            glyphs[0].offset,
+            [-width / 2.0, -(GLYPH_HEIGHT as f32) / 2.0]
+        );
+    }
+
+    #[test]
+    fn glyphs_fit_within_glyph_width() {
+        assert!(GLYPHS.iter().flat_map(|(_, rows)| rows).all(|r| r < &0x20));
+    }
+
+    #[test]
+    fn layout_skips_spaces_and_folds_case() {
+        let label = TextLabel::world(Vec3::ZERO, "a b\n?", Vec4::ONE);
+        let glyphs: Vec<_> = DebugTextState::layout_label(&label).collect();
+
+        assert_eq!(glyphs.len(), 3);
+        assert_eq!(glyphs[0].glyph, DebugTextState::glyph_index('A'));
+        assert_eq!(
+            glyphs[1].offset[0] - glyphs[0].offset[0],
+            2.0 * GLYPH_ADVANCE.0
+        );
+        assert_eq!(glyphs[2].offset[1] - glyphs[0].offset[1], -GLYPH_ADVANCE.1);
+        assert_eq!(
+            DebugTextState::glyph_index('~'),
+            DebugTextState::glyph_index('?')
+        );
+    }
+}
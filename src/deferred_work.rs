@@ -0,0 +1,85 @@
+use std::{collections::VecDeque, time::Duration};
+
+/// A queue of non-critical work (e.g. captures and diagnostics) that is run at the end of
+/// frames that have CPU time to spare, so that it doesn't cause an otherwise busy frame to miss
+/// its deadline.
+pub struct DeferredWork<T> {
+    /// Each task, along with the number of frames it has been put off for.
+    tasks: VecDeque<(T, u32)>,
+    /// The fraction of the frame period that a frame can use before its deferred work is put
+    /// off until a later frame.
+    budget_fraction: f32,
+    /// Tasks that have been put off for this many frames are run regardless of the budget, so
+    /// that a consistently busy application still gets to them eventually.
+    max_deferred_frames: u32,
+}
+impl<T> DeferredWork<T> {
+    pub fn new(budget_fraction: f32, max_deferred_frames: u32) -> Self {
+        Self {
+            tasks: VecDeque::new(),
+            budget_fraction,
+            max_deferred_frames,
+        }
+    }
+
+    pub fn push(&mut self, task: T) {
+        self.tasks.push_back((task, 0));
+    }
+
+    /// Returns the next task to run, if the frame (which has taken `elapsed` so far) still has
+    /// headroom within its `frame_period`, or if the task can't be put off any longer. Call
+    /// this repeatedly, running each task, and then call [Self::end_frame].
+    pub fn next(&mut self, elapsed: Duration, frame_period: Duration) -> Option<T> {
+        let (_, deferred_frames) = self.tasks.front()?;
+        let within_budget =
+            elapsed.as_secs_f32() < frame_period.as_secs_f32() * self.budget_fraction;
+        if within_budget || *deferred_frames >= self.max_deferred_frames {
+            self.tasks.pop_front().map(|(task, _)| task)
+        } else {
+            None
+        }
+    }
+
+    /// Records that the remaining tasks have been put off for another frame.
+    pub fn end_frame(&mut self) {
+        for (_, deferred_frames) in &mut self.tasks {
+            *deferred_frames += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PERIOD: Duration = Duration::from_millis(10);
+
+    #[test]
+    fn runs_tasks_only_within_budget() {
+        let mut work = DeferredWork::new(0.5, 100);
+        work.push(1);
+        work.push(2);
+
+        assert_eq!(work.next(Duration::from_millis(6), PERIOD), None);
+        work.end_frame();
+
+        assert_eq!(work.next(Duration::from_millis(2), PERIOD), Some(1));
+        assert_eq!(work.next(Duration::from_millis(7), PERIOD), None);
+        work.end_frame();
+
+        assert_eq!(work.next(Duration::from_millis(4), PERIOD), Some(2));
+        assert_eq!(work.next(Duration::ZERO, PERIOD), None);
+    }
+
+    #[test]
+    fn runs_tasks_that_have_been_deferred_too_long() {
+        let mut work = DeferredWork::new(0.5, 2);
+        work.push(1);
+
+        for _ in 0..2 {
+            assert_eq!(work.next(Duration::from_millis(9), PERIOD), None);
+            work.end_frame();
+        }
+        assert_eq!(work.next(Duration::from_millis(9), PERIOD), Some(1));
+    }
+}
@@ -0,0 +1,87 @@
+use std::error::Error;
+
+/// The message that wgpu-core's `DeviceError::Lost` is displayed with. wgpu doesn't expose the
+/// error type, so it's recognised by its message instead.
+const DEVICE_LOST_MESSAGE: &str = "parent device is lost";
+
+/// Makes losing the GPU device (`VK_ERROR_DEVICE_LOST`, e.g. because the driver crashed or
+/// timed out) report what happened and which adapter it happened on, rather than surfacing as
+/// an opaque panic deep inside wgpu.
+///
+/// This version of wgpu has no device-lost callback, so loss is detected in the two places it
+/// surfaces: errors reported to the device's uncaptured error handler, after which the process
+/// exits, and the panics wgpu raises for errors it treats as fatal (e.g. in `Queue::submit`),
+/// which are left to unwind as before.
+pub fn install_handlers(device: &wgpu::Device, adapter_info: &wgpu::AdapterInfo) {
+    let message = format!(
+        "GPU device lost; the driver may have crashed or timed out. Adapter: {:?} ({:?}, {:?}, \
+         vendor {:#06x}, device {:#06x})",
+        adapter_info.name,
+        adapter_info.backend,
+        adapter_info.device_type,
+        adapter_info.vendor,
+        adapter_info.device,
+    );
+
+    let error_message = message.clone();
+    device.on_uncaptured_error(move |error| {
+        if is_device_lost(&error) {
+            log::error!("{error_message}\n{error}");
+            std::process::exit(1);
+        }
+        // The same as wgpu's default handler.
+        panic!("wgpu error: {error}\n");
+    });
+
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let payload = info
+            .payload()
+            .downcast_ref::<String>()
+            .map(String::as_str)
+            .or_else(|| info.payload().downcast_ref::<&str>().copied());
+        if payload.is_some_and(|p| p.contains(DEVICE_LOST_MESSAGE)) {
+            log::error!("{message}");
+        }
+        default_hook(info);
+    }));
+}
+
+/// Whether `error`, or any of its sources, is the device having been lost.
+fn is_device_lost(error: &(dyn Error + 'static)) -> bool {
+    std::iter::successors(Some(error), |&e| e.source())
+        .any(|e| e.to_string().contains(DEVICE_LOST_MESSAGE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Wrapped(Option<Box<dyn Error + 'static>>, &'static str);
+    impl std::fmt::Display for Wrapped {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str(self.1)
+        }
+    }
+    impl Error for Wrapped {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            self.0.as_deref()
+        }
+    }
+
+    #[test]
+    fn device_loss_is_found_among_the_sources() {
+        let lost = Wrapped(
+            Some(Box::new(Wrapped(None, DEVICE_LOST_MESSAGE))),
+            "Error in Queue::submit",
+        );
+        assert!(is_device_lost(&lost));
+
+        let invalid = Wrapped(
+            Some(Box::new(Wrapped(None, "parent device is invalid"))),
+            "Error in Queue::submit",
+        );
+        assert!(!is_device_lost(&invalid));
+    }
+}
@@ -0,0 +1,55 @@
+use std::ops::Range;
+
+/// Tracks which elements of an array have changed since it was last uploaded, as a sorted list
+/// of disjoint ranges. Adjacent and overlapping ranges are coalesced, so that each range can be
+/// uploaded with a single write.
+#[derive(Default)]
+pub struct DirtyRanges {
+    ranges: Vec<Range<usize>>,
+}
+impl DirtyRanges {
+    /// Marks the element at `index` as changed.
+    pub fn insert(&mut self, index: usize) {
+        // The first range that doesn't end before `index`; it's the only one that can contain
+        // `index` or be extended to cover it.
+        let i = self.ranges.partition_point(|r| r.end < index);
+        match self.ranges.get_mut(i) {
+            Some(r) if r.contains(&index) => {}
+            Some(r) if r.end == index => {
+                r.end += 1;
+                // The extended range may now touch the next one.
+                let end = r.end;
+                if self.ranges.get(i + 1).is_some_and(|next| next.start == end) {
+                    let next = self.ranges.remove(i + 1);
+                    self.ranges[i].end = next.end;
+                }
+            }
+            Some(r) if r.start == index + 1 => r.start = index,
+            _ => self.ranges.insert(i, index..index + 1),
+        }
+    }
+
+    /// Removes and returns the changed ranges, in ascending order.
+    pub fn take(&mut self) -> Vec<Range<usize>> {
+        std::mem::take(&mut self.ranges)
+    }
+
+    pub fn clear(&mut self) {
+        self.ranges.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adjacent_indices_are_coalesced() {
+        let mut dirty = DirtyRanges::default();
+        for index in [5, 1, 3, 9, 2, 5, 4, 8] {
+            dirty.insert(index);
+        }
+        assert_eq!(dirty.take(), vec![1..6, 8..10]);
+        assert!(dirty.take().is_empty());
+    }
+}
@@ -0,0 +1,259 @@
+use glam::Mat4;
+use std::{borrow::Cow, num::NonZeroU32};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    hdr::{self, HdrImage},
+    types::{RenderRegion, VIEW_COUNT},
+};
+
+/// Must match `EnvironmentParams` in `environment.wgsl`.
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct EnvironmentParams {
+    inverse_view_projection: [[f32; 16]; VIEW_COUNT as usize],
+}
+
+/// Shows an HDR equirectangular environment map behind the scene, as a skybox.
+///
+/// This is a post-process that runs after the main pass, like [crate::ssao_state::SsaoState]:
+/// it fills in the pixels where the depth buffer is still at the far plane, by looking up the
+/// direction that each of them looks in. The map is stored as `Rgba16Float`, but the render
+/// target isn't HDR, so anything brighter than white is clipped.
+pub struct EnvironmentState {
+    params_buffer: wgpu::Buffer,
+    map_view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+}
+impl EnvironmentState {
+    /// `map` must fit within the device's texture size limit (see [hdr::load]). `depth_view` must
+    /// only include the depth aspect (see [crate::texture::Texture::depth_view]) of the main
+    /// pass's depth buffer, which has a layer for each of the `view_count` views.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        preprocessor: &crate::wgsl::Preprocessor,
+        map: &HdrImage,
+        depth_view: &wgpu::TextureView,
+        color_format: wgpu::TextureFormat,
+        view_count: u32,
+    ) -> Self {
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Environment Params Buffer"),
+            contents: bytemuck::bytes_of(&EnvironmentParams {
+                inverse_view_projection: [Mat4::IDENTITY.to_cols_array(); VIEW_COUNT as usize],
+            }),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let texels: Vec<u16> = map
+            .pixels
+            .iter()
+            .flat_map(|pixel| pixel.extend(1.0).to_array().map(hdr::f16_bits))
+            .collect();
+        let map_texture = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("Environment Map"),
+                size: wgpu::Extent3d {
+                    width: map.width,
+                    height: map.height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba16Float,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            },
+            bytemuck::cast_slice(&texels),
+        );
+        let map_view = map_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        // The map wraps around horizontally, but not over the poles.
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Environment Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = Self::create_bind_group(
+            device,
+            &bind_group_layout,
+            depth_view,
+            &params_buffer,
+            &map_view,
+            &sampler,
+        );
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(Cow::Owned(
+                preprocessor.preprocess("environment.wgsl").unwrap(),
+            )),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Environment Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "environment_vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "environment_fs_main",
+                targets: &[Some(color_format.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: NonZeroU32::new(view_count),
+        });
+
+        Self {
+            params_buffer,
+            map_view,
+            sampler,
+            bind_group_layout,
+            bind_group,
+            pipeline,
+        }
+    }
+
+    /// Rebinds the depth texture after it has been recreated (e.g. because the window was
+    /// resized).
+    pub fn resize(&mut self, device: &wgpu::Device, depth_view: &wgpu::TextureView) {
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            depth_view,
+            &self.params_buffer,
+            &self.map_view,
+            &self.sampler,
+        );
+    }
+
+    /// Updates the views that the map is drawn for, from the flattened view-projection
+    /// matrices that are written to the camera buffer.
+    pub fn set_view_projections(&self, queue: &wgpu::Queue, matrices: &[f32]) {
+        let mut params = EnvironmentParams {
+            inverse_view_projection: [Mat4::IDENTITY.to_cols_array(); VIEW_COUNT as usize],
+        };
+        for (inverse, matrix) in params
+            .inverse_view_projection
+            .iter_mut()
+            .zip(matrices.chunks_exact(16))
+        {
+            *inverse = Mat4::from_cols_slice(matrix).inverse().to_cols_array();
+        }
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::bytes_of(&params));
+    }
+
+    /// Draws the map into `rt_view` wherever nothing else was drawn, within `region`.
+    pub fn encode_draw_pass(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        rt_view: &wgpu::TextureView,
+        region: &RenderRegion,
+    ) {
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Environment Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: rt_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        region.apply(&mut rpass);
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, &self.bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        depth_view: &wgpu::TextureView,
+        params_buffer: &wgpu::Buffer,
+        map_view: &wgpu::TextureView,
+        sampler: &wgpu::Sampler,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Environment Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(depth_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(map_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        })
+    }
+}
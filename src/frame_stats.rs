@@ -0,0 +1,156 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// The timing of a single frame, as reported by the render loop for the HUD and for any other
+/// consumers of a [FrameTimingQueue].
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct FrameTiming {
+    /// The time spent preparing the frame on the CPU, from the end of the wait for the XR frame
+    /// (if any) until its commands were submitted.
+    pub cpu_time: Duration,
+    /// The time the GPU spent on the most recently measured frame, if the device supports
+    /// timestamp queries. It's read back without waiting on the GPU, so it lags a frame or more
+    /// behind (see [crate::gpu_timer::GpuTimer]).
+    pub gpu_time: Option<Duration>,
+    /// The time since the previous frame ended.
+    pub frame_time: Duration,
+    /// The runtime's predicted display period, when rendering to a headset.
+    pub display_period: Option<Duration>,
+    /// Whether preparing the frame took longer than the display period (or the assumed desktop
+    /// frame period), so that it likely missed its display time.
+    pub late: bool,
+}
+
+/// The timings of recent frames, shared between the render loop, which pushes them, and any
+/// number of consumers (e.g. an external monitoring tool on another thread). Only the most
+/// recent frames are kept, so a consumer that falls behind loses the oldest ones.
+#[derive(Clone)]
+pub struct FrameTimingQueue {
+    timings: Arc<Mutex<VecDeque<FrameTiming>>>,
+    capacity: usize,
+}
+impl FrameTimingQueue {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            timings: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    pub fn push(&self, timing: FrameTiming) {
+        let mut timings = self.timings.lock().unwrap();
+        if timings.len() == self.capacity {
+            timings.pop_front();
+        }
+        timings.push_back(timing);
+    }
+
+    /// The most recent frame's timing, without removing it.
+    pub fn latest(&self) -> Option<FrameTiming> {
+        self.timings.lock().unwrap().back().copied()
+    }
+
+    /// Removes and returns the queued timings, oldest first.
+    #[cfg(test)]
+    pub fn drain(&self) -> Vec<FrameTiming> {
+        self.timings.lock().unwrap().drain(..).collect()
+    }
+}
+
+/// Frame-time statistics for the FPS readout: an exponential moving average, which is steadier
+/// than counting frames over a short interval, and percentiles over a window of recent frames,
+/// which show the hitches that an average hides.
+pub struct FrameStats {
+    /// The weight of each new frame time in the moving average, from 0 (exclusive) to 1. Lower
+    /// values are smoother, but slower to follow changes.
+    smoothing: f32,
+    /// The moving average of the frame time, in seconds.
+    average: Option<f32>,
+    /// The most recent frame times, oldest first.
+    window: VecDeque<Duration>,
+    window_len: usize,
+}
+impl FrameStats {
+    pub fn new(smoothing: f32, window_len: usize) -> Self {
+        Self {
+            smoothing: smoothing.clamp(f32::EPSILON, 1.0),
+            average: None,
+            window: VecDeque::with_capacity(window_len),
+            window_len,
+        }
+    }
+
+    pub fn record(&mut self, frame_time: Duration) {
+        let seconds = frame_time.as_secs_f32();
+        self.average = Some(match self.average {
+            Some(average) => average + (seconds - average) * self.smoothing,
+            None => seconds,
+        });
+
+        if self.window.len() == self.window_len {
+            self.window.pop_front();
+        }
+        self.window.push_back(frame_time);
+    }
+
+    /// The frame rate corresponding to the average frame time.
+    pub fn average_fps(&self) -> Option<f32> {
+        self.average.map(|average| 1.0 / average)
+    }
+
+    /// The frame times at each of `percentiles` (from 0 to 100) of the recent frames, using the
+    /// nearest rank. The 99th percentile is the frame rate's "1% low".
+    pub fn percentiles<const N: usize>(&self, percentiles: [f32; N]) -> Option<[Duration; N]> {
+        if self.window.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<Duration> = self.window.iter().copied().collect();
+        sorted.sort();
+        Some(percentiles.map(|p| {
+            let rank = (p / 100.0 * sorted.len() as f32).ceil() as usize;
+            sorted[rank.clamp(1, sorted.len()) - 1]
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles_catch_hitches_that_the_average_smooths_over() {
+        let mut stats = FrameStats::new(0.1, 100);
+        for i in 0..200 {
+            // Two hitches among the most recent 100 frames.
+            let ms = if i == 120 || i == 150 { 50 } else { 10 };
+            stats.record(Duration::from_millis(ms));
+        }
+
+        let fps = stats.average_fps().unwrap();
+        assert!((fps - 100.0).abs() < 1.0, "{fps}");
+
+        let [median, p99] = stats.percentiles([50.0, 99.0]).unwrap();
+        assert_eq!(median, Duration::from_millis(10));
+        assert_eq!(p99, Duration::from_millis(50));
+    }
+
+    #[test]
+    fn timing_queue_keeps_the_most_recent_frames() {
+        let queue = FrameTimingQueue::new(2);
+        let consumer = queue.clone();
+        for ms in [1, 2, 3] {
+            queue.push(FrameTiming {
+                frame_time: Duration::from_millis(ms),
+                ..Default::default()
+            });
+        }
+
+        assert_eq!(queue.latest().unwrap().frame_time, Duration::from_millis(3));
+        let frame_times: Vec<_> = consumer.drain().iter().map(|t| t.frame_time).collect();
+        assert_eq!(frame_times, [2, 3].map(Duration::from_millis));
+        assert_eq!(queue.latest(), None);
+    }
+}
@@ -0,0 +1,117 @@
+use std::{
+    sync::mpsc::{self, Receiver, TryRecvError},
+    time::Duration,
+};
+
+/// Measures the time the GPU spends on a frame's commands with a pair of timestamp queries,
+/// written at the start and end of the frame's encoder. The timestamps are read back without
+/// waiting on the GPU, so a measurement arrives a frame or more after the frame it measures,
+/// and frames that start while a measurement is still being read back aren't measured.
+pub struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    readback_buffer: wgpu::Buffer,
+    /// The number of nanoseconds per timestamp tick.
+    timestamp_period: f32,
+    /// Whether the current frame's commands are being measured.
+    measuring: bool,
+    /// Receives the result of mapping the readback buffer, while a measurement is in flight.
+    readback: Option<Receiver<Result<(), wgpu::BufferAsyncError>>>,
+    latest: Option<Duration>,
+}
+impl GpuTimer {
+    const QUERY_COUNT: u32 = 2;
+    const BUFFER_SIZE: wgpu::BufferAddress = Self::QUERY_COUNT as wgpu::BufferAddress
+        * std::mem::size_of::<u64>() as wgpu::BufferAddress;
+
+    /// Returns `None` if the device doesn't support timestamp queries.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("GPU Timer Query Set"),
+            ty: wgpu::QueryType::Timestamp,
+            count: Self::QUERY_COUNT,
+        });
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("GPU Timer Readback Buffer"),
+            size: Self::BUFFER_SIZE,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Some(Self {
+            query_set,
+            readback_buffer,
+            timestamp_period: queue.get_timestamp_period(),
+            measuring: false,
+            readback: None,
+            latest: None,
+        })
+    }
+
+    /// Starts measuring the frame, unless the previous measurement is still in flight. Call
+    /// this before encoding the frame's commands.
+    pub fn begin(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        self.measuring = self.readback.is_none();
+        if self.measuring {
+            encoder.write_timestamp(&self.query_set, 0);
+        }
+    }
+
+    /// Stops measuring the frame, and resolves the timestamps into the readback buffer.
+    /// Call this after encoding the frame's commands, before submitting them.
+    pub fn end(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if !self.measuring {
+            return;
+        }
+        encoder.write_timestamp(&self.query_set, 1);
+        encoder.resolve_query_set(
+            &self.query_set,
+            0..Self::QUERY_COUNT,
+            &self.readback_buffer,
+            0,
+        );
+    }
+
+    /// Starts reading the timestamps back. Call this after submitting the frame's commands.
+    pub fn submitted(&mut self) {
+        if !std::mem::take(&mut self.measuring) {
+            return;
+        }
+        let (sender, receiver) = mpsc::channel();
+        self.readback_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        self.readback = Some(receiver);
+    }
+
+    /// The GPU time of the most recently measured frame, picking up a new measurement if one
+    /// has been read back since the last call.
+    pub fn latest(&mut self, device: &wgpu::Device) -> Option<Duration> {
+        let Some(readback) = &self.readback else {
+            return self.latest;
+        };
+        device.poll(wgpu::Maintain::Poll);
+        match readback.try_recv() {
+            Err(TryRecvError::Empty) => return self.latest,
+            Ok(Ok(())) => {
+                let slice = self.readback_buffer.slice(..);
+                let [start, end]: [u64; 2] =
+                    bytemuck::pod_read_unaligned(&slice.get_mapped_range());
+                let ticks = end.saturating_sub(start);
+                self.readback_buffer.unmap();
+                self.latest = Some(Duration::from_nanos(
+                    (ticks as f64 * self.timestamp_period as f64) as u64,
+                ));
+            }
+            Ok(Err(err)) => log::warn!("failed to read back the GPU timestamps: {err}"),
+            Err(TryRecvError::Disconnected) => {}
+        }
+        self.readback = None;
+        self.latest
+    }
+}
@@ -0,0 +1,85 @@
+/// A GPU buffer whose contents are rewritten from the CPU, which is reused across frames and
+/// only reallocated when its capacity is exceeded.
+///
+/// `wgpu` stages the data given to [wgpu::Queue::write_buffer] and copies it over at the start
+/// of the next submission, so rewriting the buffer while a previous frame is still in flight is
+/// safe; a single allocation can be reused for every frame without any manual synchronisation.
+pub struct GrowableBuffer {
+    label: &'static str,
+    usage: wgpu::BufferUsages,
+    buffer: wgpu::Buffer,
+    capacity: wgpu::BufferAddress,
+    high_water_mark: wgpu::BufferAddress,
+}
+impl GrowableBuffer {
+    pub fn new(
+        device: &wgpu::Device,
+        label: &'static str,
+        usage: wgpu::BufferUsages,
+        capacity: wgpu::BufferAddress,
+    ) -> Self {
+        let usage = usage | wgpu::BufferUsages::COPY_DST;
+        let capacity = Self::aligned_capacity(capacity);
+        Self {
+            label,
+            usage,
+            buffer: Self::create_buffer(device, label, usage, capacity),
+            capacity,
+            high_water_mark: 0,
+        }
+    }
+
+    /// Writes `data` to the start of the buffer, reallocating it first if `data` does not fit.
+    /// Returns true if the buffer was reallocated.
+    pub fn write(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, data: &[u8]) -> bool {
+        let size = data.len() as wgpu::BufferAddress;
+        self.high_water_mark = self.high_water_mark.max(size);
+
+        let reallocated = size > self.capacity;
+        if reallocated {
+            self.capacity = Self::aligned_capacity(size);
+            log::debug!(
+                "reallocating {} to {} bytes (high-water mark: {} bytes)",
+                self.label,
+                self.capacity,
+                self.high_water_mark
+            );
+            self.buffer = Self::create_buffer(device, self.label, self.usage, self.capacity);
+        }
+        if !data.is_empty() {
+            queue.write_buffer(&self.buffer, 0, data);
+        }
+        reallocated
+    }
+
+    /// Overwrites part of the buffer with `data`, starting `offset` bytes in. Unlike
+    /// [Self::write], this never reallocates, so the range must be within the data last written.
+    pub fn write_at(&self, queue: &wgpu::Queue, offset: wgpu::BufferAddress, data: &[u8]) {
+        debug_assert!(offset + data.len() as wgpu::BufferAddress <= self.capacity);
+        if !data.is_empty() {
+            queue.write_buffer(&self.buffer, offset, data);
+        }
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    fn aligned_capacity(size: wgpu::BufferAddress) -> wgpu::BufferAddress {
+        size.max(wgpu::COPY_BUFFER_ALIGNMENT).next_power_of_two()
+    }
+
+    fn create_buffer(
+        device: &wgpu::Device,
+        label: &'static str,
+        usage: wgpu::BufferUsages,
+        size: wgpu::BufferAddress,
+    ) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size,
+            usage,
+            mapped_at_creation: false,
+        })
+    }
+}
@@ -0,0 +1,130 @@
+use std::time::Duration;
+
+/// One step of a [HapticPattern].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HapticSegment {
+    /// The strength of the vibration, from 0 to 1. A segment with an amplitude of 0 is a pause.
+    pub amplitude: f32,
+    /// The frequency of the vibration, in Hz, or `None` to leave it to the runtime.
+    pub frequency: Option<f32>,
+    pub duration: Duration,
+}
+
+/// A sequence of vibrations that are played one after the other, for feedback that a single
+/// pulse can't express (e.g. a double-buzz when something is picked up).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HapticPattern {
+    pub segments: Vec<HapticSegment>,
+}
+impl HapticPattern {
+    /// `count` pulses of `on` at `amplitude`, with pauses of `off` between them.
+    pub fn pulses(count: usize, amplitude: f32, on: Duration, off: Duration) -> Self {
+        let pulse = HapticSegment {
+            amplitude,
+            frequency: None,
+            duration: on,
+        };
+        let pause = HapticSegment {
+            amplitude: 0.0,
+            frequency: None,
+            duration: off,
+        };
+        let mut segments = vec![pause; (2 * count).saturating_sub(1)];
+        segments.iter_mut().step_by(2).for_each(|s| *s = pulse);
+        Self { segments }
+    }
+
+    /// A vibration whose amplitude ramps linearly from `from` to `to` over `duration`, in
+    /// `steps` segments of equal length.
+    pub fn envelope(from: f32, to: f32, duration: Duration, steps: u32) -> Self {
+        let steps = steps.max(1);
+        let segments = (0..steps)
+            .map(|step| HapticSegment {
+                amplitude: from + (to - from) * (step as f32 + 0.5) / steps as f32,
+                frequency: None,
+                duration: duration / steps,
+            })
+            .collect();
+        Self { segments }
+    }
+}
+
+/// Plays a [HapticPattern] on one controller, by working out which segment should be playing as
+/// time advances. Time is only checked once a frame, so segments start up to a frame late; a
+/// segment that starts late is shortened so that the pattern doesn't drift.
+pub struct HapticPlayback {
+    pattern: HapticPattern,
+    /// The segment that starts next.
+    next: usize,
+    /// When the pattern started, which is the time of the first update.
+    start: Option<Duration>,
+}
+impl HapticPlayback {
+    pub fn new(pattern: HapticPattern) -> Self {
+        Self {
+            pattern,
+            next: 0,
+            start: None,
+        }
+    }
+
+    /// Advances to `time` (which must be on a monotonic clock), and returns the segment that
+    /// should start now, with its duration reduced to what's left of it, if there is one. If
+    /// several segments have started since the last update, only the last of them is returned,
+    /// as it would replace the others anyway.
+    pub fn update(&mut self, time: Duration) -> Option<HapticSegment> {
+        let start = *self.start.get_or_insert(time);
+        let mut started = None;
+        let mut segment_start = start
+            + self.pattern.segments[..self.next]
+                .iter()
+                .map(|s| s.duration)
+                .sum::<Duration>();
+        while let Some(segment) = self.pattern.segments.get(self.next) {
+            if segment_start > time {
+                break;
+            }
+            let segment_end = segment_start + segment.duration;
+            started = Some(HapticSegment {
+                duration: segment_end.saturating_sub(time),
+                ..*segment
+            });
+            segment_start = segment_end;
+            self.next += 1;
+        }
+        started
+    }
+
+    /// Whether every segment has been started. The last one may still be playing, but the
+    /// runtime stops it once its duration is up.
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.pattern.segments.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn segments_start_in_order_and_late_segments_are_shortened() {
+        let ms = Duration::from_millis;
+        let pattern = HapticPattern::pulses(2, 0.8, ms(50), ms(30));
+        assert_eq!(pattern.segments.len(), 3);
+        assert_eq!(pattern.segments[1].amplitude, 0.0);
+
+        let mut playback = HapticPlayback::new(pattern);
+        let first = playback.update(ms(1000)).unwrap();
+        assert_eq!((first.amplitude, first.duration), (0.8, ms(50)));
+        assert_eq!(playback.update(ms(1040)), None);
+        // The pause started at 1050, so 20ms of it is left.
+        let pause = playback.update(ms(1060)).unwrap();
+        assert_eq!((pause.amplitude, pause.duration), (0.0, ms(20)));
+        assert!(!playback.is_finished());
+        // The second pulse started at 1080; a late frame skips straight to it.
+        let second = playback.update(ms(1090)).unwrap();
+        assert_eq!((second.amplitude, second.duration), (0.8, ms(40)));
+        assert!(playback.is_finished());
+        assert_eq!(playback.update(ms(1200)), None);
+    }
+}
@@ -0,0 +1,262 @@
+//! Loads Radiance HDR (`.hdr`) images, such as equirectangular environment maps, which store
+//! each pixel as RGB with a shared exponent (RGBE) to cover a much wider range than 8-bit PNGs.
+//!
+//! Only the common subset is supported: the `32-bit_rle_rgbe` format with the standard
+//! top-to-bottom, left-to-right orientation (`-Y height +X width`), with either flat or
+//! run-length encoded scanlines. OpenEXR files aren't supported; they can be converted with
+//! most image tools.
+
+use anyhow::Context;
+use glam::Vec3;
+use std::path::Path;
+
+const SIGNATURES: [&[u8]; 2] = [b"#?RADIANCE\n", b"#?RGBE\n"];
+
+/// The pixels of a Radiance HDR image, in linear RGB.
+pub struct HdrImage {
+    pub width: u32,
+    pub height: u32,
+    /// The pixels, row by row from the top.
+    pub pixels: Vec<Vec3>,
+}
+impl HdrImage {
+    /// Parses an image that is at most `max_size` pixels on each side, such as the device's
+    /// texture size limit. The size is checked before the pixels are allocated, along with
+    /// whether there's enough data for that many.
+    pub fn parse(bytes: &[u8], max_size: u32) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            SIGNATURES.iter().any(|s| bytes.starts_with(s)),
+            "not a Radiance HDR file"
+        );
+        let mut rest = bytes;
+        let mut next_line = || -> anyhow::Result<&str> {
+            let end = rest
+                .iter()
+                .position(|&b| b == b'\n')
+                .context("the HDR header is truncated")?;
+            let line = std::str::from_utf8(&rest[..end]).context("the HDR header isn't text")?;
+            rest = &rest[end + 1..];
+            Ok(line)
+        };
+
+        // The header is a list of variables, ended by an empty line.
+        loop {
+            let line = next_line()?;
+            if line.is_empty() {
+                break;
+            }
+            if let Some(format) = line.strip_prefix("FORMAT=") {
+                anyhow::ensure!(
+                    format == "32-bit_rle_rgbe",
+                    "unsupported HDR format {format:?}"
+                );
+            }
+        }
+        let resolution = next_line()?;
+        let (height, width) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+            ["-Y", height, "+X", width] => (height.parse::<u32>()?, width.parse::<u32>()?),
+            _ => anyhow::bail!("unsupported HDR orientation {resolution:?}"),
+        };
+        anyhow::ensure!(width > 0 && height > 0, "the HDR image is empty");
+        anyhow::ensure!(
+            width <= max_size && height <= max_size,
+            "the {width}x{height} HDR image is larger than the {max_size}x{max_size} limit"
+        );
+        anyhow::ensure!(
+            (height as usize).saturating_mul(min_scanline_len(width as usize)) <= rest.len(),
+            "the HDR image is truncated"
+        );
+
+        let mut data = rest;
+        let mut pixels = Vec::with_capacity(width as usize * height as usize);
+        let mut scanline = vec![[0u8; 4]; width as usize];
+        for row in 0..height {
+            data = read_scanline(data, &mut scanline)
+                .with_context(|| format!("scanline {row} is invalid"))?;
+            pixels.extend(scanline.iter().map(|&rgbe| rgbe_to_rgb(rgbe)));
+        }
+
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// The average light arriving from every direction, if the image is an equirectangular map
+    /// of the environment. This is the ambient light that a diffuse surface would be lit by,
+    /// ignoring which way it faces: the rows are weighted by the solid angle they cover, which
+    /// shrinks towards the poles.
+    pub fn average_radiance(&self) -> Vec3 {
+        let mut total = Vec3::ZERO;
+        let mut total_weight = 0.0;
+        for (row, pixels) in self.pixels.chunks(self.width as usize).enumerate() {
+            let latitude = (row as f32 + 0.5) / self.height as f32 * std::f32::consts::PI;
+            let weight = latitude.sin();
+            total += pixels.iter().sum::<Vec3>() * weight;
+            total_weight += weight * pixels.len() as f32;
+        }
+        total / total_weight
+    }
+}
+
+/// Loads the Radiance HDR image at `path`, which must be at most `max_size` pixels on each side.
+pub fn load(path: &Path, max_size: u32) -> anyhow::Result<HdrImage> {
+    let bytes = std::fs::read(path).with_context(|| format!("failed to read {path:?}"))?;
+    HdrImage::parse(&bytes, max_size).with_context(|| format!("failed to load {path:?}"))
+}
+
+/// The fewest bytes that a scanline `width` pixels wide can be stored in.
+fn min_scanline_len(width: usize) -> usize {
+    let flat = width * 4;
+    if !(8..0x8000).contains(&width) {
+        return flat;
+    }
+    // Run-length encoded, each component is at best a run of 127 values for every two bytes.
+    flat.min(4 + 4 * 2 * width.div_ceil(127))
+}
+
+/// Reads a scanline's pixels into `scanline` from the start of `data`, and returns the rest.
+fn read_scanline<'a>(data: &'a [u8], scanline: &mut [[u8; 4]]) -> anyhow::Result<&'a [u8]> {
+    let width = scanline.len();
+    // Run-length encoded scanlines start with 2, 2 and the width, and store each component
+    // separately. Scanlines that are too short or long to be encoded are stored flat.
+    let encoded = (8..0x8000).contains(&width)
+        && data.len() >= 4
+        && data[..2] == [2, 2]
+        && usize::from(u16::from_be_bytes([data[2], data[3]])) == width;
+    if !encoded {
+        let flat = data
+            .get(..width * 4)
+            .context("the HDR image is truncated")?;
+        for (pixel, rgbe) in scanline.iter_mut().zip(flat.chunks_exact(4)) {
+            pixel.copy_from_slice(rgbe);
+        }
+        return Ok(&data[width * 4..]);
+    }
+
+    let mut data = &data[4..];
+    for component in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let (&count, rest) = data.split_first().context("the HDR image is truncated")?;
+            // Counts above 128 are runs of a single value, and the others are literal values.
+            let (length, run) = if count > 128 {
+                (usize::from(count - 128), true)
+            } else {
+                (usize::from(count), false)
+            };
+            anyhow::ensure!(
+                length > 0 && x + length <= width,
+                "a run overflows the scanline"
+            );
+            let values = rest
+                .get(..if run { 1 } else { length })
+                .context("the HDR image is truncated")?;
+            for (i, pixel) in scanline[x..x + length].iter_mut().enumerate() {
+                pixel[component] = values[if run { 0 } else { i }];
+            }
+            data = &rest[values.len()..];
+            x += length;
+        }
+    }
+    Ok(data)
+}
+
+fn rgbe_to_rgb([r, g, b, e]: [u8; 4]) -> Vec3 {
+    if e == 0 {
+        return Vec3::ZERO;
+    }
+    let scale = 2f32.powi(i32::from(e) - (128 + 8));
+    Vec3::new(r as f32, g as f32, b as f32) * scale
+}
+
+/// Converts `value` to a half-precision float, for uploading to a `Rgba16Float` texture.
+/// Values beyond the range of a half are clamped to it, and the mantissa is truncated.
+pub fn f16_bits(value: f32) -> u16 {
+    let bits = value.clamp(-65504.0, 65504.0).to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+    let mantissa = (bits & 0x7f_ffff) | 0x80_0000;
+    if exponent <= 0 {
+        // Too small to be normal, so it's stored as a subnormal, or flushed to zero.
+        let shift = 14 - exponent;
+        return sign
+            | if shift < 24 {
+                (mantissa >> shift) as u16
+            } else {
+                0
+            };
+    }
+    sign | ((exponent as u16) << 10) | ((mantissa >> 13) & 0x3ff) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hdr(width: u32, height: u32, scanlines: &[u8]) -> Vec<u8> {
+        let mut bytes =
+            format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\nEXPOSURE=1\n\n-Y {height} +X {width}\n")
+                .into_bytes();
+        bytes.extend(scanlines);
+        bytes
+    }
+
+    #[test]
+    fn flat_and_run_length_encoded_scanlines_are_decoded() {
+        // A flat 2x1 image: 1.0 in red (128 * 2^(129 - 136)), and black.
+        let image = HdrImage::parse(&hdr(2, 1, &[128, 0, 0, 129, 0, 0, 0, 0]), 16).unwrap();
+        assert_eq!(image.pixels, [Vec3::X, Vec3::ZERO]);
+
+        // An 8x1 run-length encoded scanline: red is a run, green is literal, and blue and the
+        // exponent are runs split into two.
+        let mut encoded = vec![2, 2, 0, 8];
+        encoded.extend([128 + 8, 64]);
+        encoded.extend([8, 0, 32, 64, 96, 128, 160, 192, 224]);
+        encoded.extend([128 + 3, 0, 128 + 5, 128]);
+        encoded.extend([128 + 4, 129, 128 + 4, 130]);
+        let image = HdrImage::parse(&hdr(8, 1, &encoded), 16).unwrap();
+        assert_eq!(image.pixels[0], Vec3::new(0.5, 0.0, 0.0));
+        assert_eq!(image.pixels[7], Vec3::new(1.0, 3.5, 2.0));
+        assert!(HdrImage::parse(&hdr(8, 1, &encoded[..encoded.len() - 1]), 16).is_err());
+
+        assert!(HdrImage::parse(b"#?RADIANCE\nFORMAT=32-bit_rle_xyze\n\n-Y 1 +X 1\n", 16).is_err());
+        assert!(HdrImage::parse(&encoded, 16).is_err());
+
+        // Sizes beyond the limit, or beyond what the data could hold, are rejected up front.
+        assert!(HdrImage::parse(&hdr(8, 17, &encoded), 16).is_err());
+        assert!(HdrImage::parse(&hdr(8, 1 << 20, &encoded), u32::MAX).is_err());
+    }
+
+    #[test]
+    fn a_uniform_environment_averages_to_its_colour() {
+        let image = HdrImage {
+            width: 4,
+            height: 3,
+            pixels: vec![Vec3::new(0.5, 1.0, 2.0); 12],
+        };
+        assert!(image
+            .average_radiance()
+            .abs_diff_eq(Vec3::new(0.5, 1.0, 2.0), 1e-6));
+    }
+
+    #[test]
+    fn values_that_fit_in_a_half_are_converted_exactly() {
+        // The smallest normal half, and a subnormal one.
+        let (smallest, subnormal) = (2f32.powi(-14), 2f32.powi(-20));
+        for value in [0.0, 1.0, -2.5, smallest, subnormal, 65504.0] {
+            let bits = f16_bits(value);
+            // Reassemble normal halves by hand, to check them against the original value.
+            let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+            let exponent = i32::from((bits >> 10) & 0x1f);
+            let mantissa = f32::from(bits & 0x3ff);
+            let half = match exponent {
+                0 => sign * mantissa * 2f32.powi(-24),
+                _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+            };
+            assert_eq!(half, value);
+        }
+        assert_eq!(f16_bits(1e6), f16_bits(65504.0));
+    }
+}
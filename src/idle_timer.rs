@@ -0,0 +1,83 @@
+use glam::{Quat, Vec3};
+use std::time::Duration;
+
+/// Detects when the user has stopped moving, from the poses of the head and hands.
+///
+/// A pose only counts as having moved once it's moved far enough from where it was when the
+/// user was last active, so that tracking jitter on a headset that has been put down doesn't
+/// keep it awake, while slow movements still add up. A pose that starts or stops being tracked
+/// (e.g. a controller being picked up or turned off) counts as movement.
+pub struct IdleTimer {
+    timeout: Duration,
+    /// How far a pose has to move, in metres, to count as movement.
+    distance_threshold: f32,
+    /// How far a pose has to turn, in radians, to count as movement.
+    angle_threshold: f32,
+    /// The poses when the user was last active.
+    reference: Vec<Option<(Vec3, Quat)>>,
+    /// When the user was last active.
+    last_active: Option<Duration>,
+}
+impl IdleTimer {
+    pub fn new(timeout: Duration, distance_threshold: f32, angle_threshold: f32) -> Self {
+        Self {
+            timeout,
+            distance_threshold,
+            angle_threshold,
+            reference: vec![],
+            last_active: None,
+        }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Updates the timer with the poses at `time` (which must be on a monotonic clock), and
+    /// returns whether the user has been idle for at least the timeout.
+    pub fn update(&mut self, time: Duration, poses: &[Option<(Vec3, Quat)>]) -> bool {
+        let moved =
+            self.reference.len() != poses.len()
+                || self.reference.iter().zip(poses).any(|(reference, pose)| {
+                    match (reference, pose) {
+                        (Some((p0, r0)), Some((p1, r1))) => {
+                            p0.distance(*p1) > self.distance_threshold
+                                || r0.angle_between(*r1) > self.angle_threshold
+                        }
+                        (None, None) => false,
+                        _ => true,
+                    }
+                });
+        if moved || self.last_active.is_none() {
+            self.reference = poses.to_vec();
+            self.last_active = Some(time);
+        }
+        self.last_active
+            .is_some_and(|last_active| time.saturating_sub(last_active) >= self.timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_movement_past_the_thresholds_keeps_the_user_active() {
+        let mut timer = IdleTimer::new(Duration::from_secs(10), 0.02, 2.0f32.to_radians());
+        let at = |x: f32| Some((Vec3::new(x, 1.5, 0.0), Quat::IDENTITY));
+        let seconds = Duration::from_secs;
+
+        assert!(!timer.update(seconds(0), &[at(0.0), None]));
+        // Jitter doesn't count, but slowly moving past the threshold does.
+        assert!(!timer.update(seconds(6), &[at(0.01), None]));
+        assert!(!timer.update(seconds(8), &[at(0.03), None]));
+        assert!(!timer.update(seconds(17), &[at(0.04), None]));
+        assert!(timer.update(seconds(18), &[at(0.04), None]));
+
+        // A controller being turned on is movement.
+        assert!(!timer.update(seconds(19), &[at(0.04), at(1.0)]));
+        let turned = Some((Vec3::new(0.04, 1.5, 0.0), Quat::from_rotation_y(0.1)));
+        assert!(!timer.update(seconds(28), &[turned, at(1.0)]));
+        assert!(timer.update(seconds(38), &[turned, at(1.0)]));
+    }
+}
@@ -0,0 +1,289 @@
+use glam::{Quat, Vec3, Vec4};
+use std::{borrow::Cow, num::NonZeroU32};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    camera::CameraState,
+    growable_buffer::GrowableBuffer,
+    types::{has_stencil, RenderRegion},
+};
+
+/// A line segment to be drawn by [LineState].
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Line {
+    start: [f32; 3],
+    end: [f32; 3],
+    color: [f32; 4],
+}
+impl Line {
+    pub fn new(start: Vec3, end: Vec3, color: Vec4) -> Self {
+        Self {
+            start: start.into(),
+            end: end.into(),
+            color: color.into(),
+        }
+    }
+
+    /// The X (red), Y (green) and Z (blue) axes of the given transform, each `length` long.
+    pub fn axes(translation: Vec3, rotation: Quat, length: f32) -> [Self; 3] {
+        [
+            (Vec3::X, Vec4::new(1.0, 0.0, 0.0, 1.0)),
+            (Vec3::Y, Vec4::new(0.0, 1.0, 0.0, 1.0)),
+            (Vec3::Z, Vec4::new(0.0, 0.0, 1.0, 1.0)),
+        ]
+        .map(|(axis, color)| Self::new(translation, translation + rotation * axis * length, color))
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LineParams {
+    viewport_size: [f32; 2],
+    width: f32,
+    _padding: f32,
+}
+
+/// Renders anti-aliased lines of a configurable width, for debug visualizations. Each segment is
+/// expanded into a quad facing the viewer in the vertex shader, so the width is in pixels and
+/// doesn't depend on what the hardware supports for line primitives.
+///
+/// Lines are depth-tested against the scene, but don't write depth.
+pub struct LineState {
+    params: LineParams,
+    params_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    shader: wgpu::ShaderModule,
+    pipeline_layout: wgpu::PipelineLayout,
+    pipeline: wgpu::RenderPipeline,
+    swapchain_format: wgpu::TextureFormat,
+    depth_format: wgpu::TextureFormat,
+    depth_bias: wgpu::DepthBiasState,
+    view_count: u32,
+    instance_buffer: GrowableBuffer,
+    instance_count: u32,
+}
+impl LineState {
+    pub fn new(
+        device: &wgpu::Device,
+        preprocessor: &crate::wgsl::Preprocessor,
+        camera_state: &CameraState,
+        swapchain_format: wgpu::TextureFormat,
+        depth_format: wgpu::TextureFormat,
+        inner_size: winit::dpi::PhysicalSize<u32>,
+        width: f32,
+    ) -> Self {
+        let params = LineParams {
+            viewport_size: [inner_size.width as f32, inner_size.height as f32],
+            width,
+            _padding: 0.0,
+        };
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Line Params Buffer"),
+            contents: bytemuck::bytes_of(&params),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Line Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Line Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: params_buffer.as_entire_binding(),
+            }],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(Cow::Owned(
+                preprocessor.preprocess("lines.wgsl").unwrap(),
+            )),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[camera_state.bind_group_layout(), &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let depth_bias = wgpu::DepthBiasState::default();
+        let view_count = camera_state.view_count();
+        let pipeline = Self::create_pipeline(
+            device,
+            &shader,
+            &pipeline_layout,
+            swapchain_format,
+            depth_format,
+            depth_bias,
+            view_count,
+        );
+
+        let instance_buffer = GrowableBuffer::new(
+            device,
+            "Line Instance Buffer",
+            wgpu::BufferUsages::VERTEX,
+            (64 * std::mem::size_of::<Line>()) as _,
+        );
+
+        Self {
+            params,
+            params_buffer,
+            bind_group,
+            shader,
+            pipeline_layout,
+            pipeline,
+            swapchain_format,
+            depth_format,
+            depth_bias,
+            view_count,
+            instance_buffer,
+            instance_count: 0,
+        }
+    }
+
+    pub fn resize(&mut self, queue: &wgpu::Queue, inner_size: winit::dpi::PhysicalSize<u32>) {
+        self.params.viewport_size = [inner_size.width as f32, inner_size.height as f32];
+        self.write_params(queue);
+    }
+
+    pub fn width(&self) -> f32 {
+        self.params.width
+    }
+
+    /// Sets the width of the lines, in pixels.
+    pub fn set_width(&mut self, queue: &wgpu::Queue, width: f32) {
+        self.params.width = width;
+        self.write_params(queue);
+    }
+
+    /// Recreates the pipeline with the given depth bias (see
+    /// [crate::main_state::MainState::set_depth_bias]). Lines drawn along surfaces, such as
+    /// outlines on the floor, can be biased towards the viewer so that they don't z-fight with
+    /// them, independently of the scene. There's no bias by default.
+    pub fn set_depth_bias(&mut self, device: &wgpu::Device, depth_bias: wgpu::DepthBiasState) {
+        if depth_bias == self.depth_bias {
+            return;
+        }
+        self.depth_bias = depth_bias;
+        self.pipeline = Self::create_pipeline(
+            device,
+            &self.shader,
+            &self.pipeline_layout,
+            self.swapchain_format,
+            self.depth_format,
+            depth_bias,
+            self.view_count,
+        );
+    }
+
+    /// Replaces the lines to be drawn with `lines`.
+    pub fn upload_lines(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, lines: &[Line]) {
+        self.instance_buffer
+            .write(device, queue, bytemuck::cast_slice(lines));
+        self.instance_count = lines.len() as u32;
+    }
+
+    pub fn encode_draw_pass(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        rt_view: &wgpu::TextureView,
+        depth_view: &wgpu::TextureView,
+        region: &RenderRegion,
+        camera_bind_group: &wgpu::BindGroup,
+    ) {
+        if self.instance_count == 0 {
+            return;
+        }
+
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Line Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: rt_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                }),
+                stencil_ops: has_stencil(self.depth_format).then_some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                }),
+            }),
+        });
+        region.apply(&mut rpass);
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, camera_bind_group, &[]);
+        rpass.set_bind_group(1, &self.bind_group, &[]);
+        rpass.set_vertex_buffer(0, self.instance_buffer.buffer().slice(..));
+        rpass.draw(0..6, 0..self.instance_count);
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        pipeline_layout: &wgpu::PipelineLayout,
+        swapchain_format: wgpu::TextureFormat,
+        depth_format: wgpu::TextureFormat,
+        depth_bias: wgpu::DepthBiasState,
+        view_count: u32,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Line Pipeline"),
+            layout: Some(pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "line_vs_main",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<Line>() as _,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &wgpu::vertex_attr_array![
+                        0 => Float32x3,
+                        1 => Float32x3,
+                        2 => Float32x4,
+                    ],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "line_fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: swapchain_format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth_format,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: depth_bias,
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: NonZeroU32::new(view_count),
+        })
+    }
+
+    fn write_params(&self, queue: &wgpu::Queue) {
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::bytes_of(&self.params));
+    }
+}
@@ -10,12 +10,11 @@
 //! These modes are intended to show you how to gracefully integrate XR into your project's code
 //! and how you can move from one stage of integration to the next.
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use anyhow::Context;
-use glam::{vec3, vec4, Quat, Vec3};
+use glam::{vec3, vec4, Quat, Vec2, Vec3};
 use tracing_subscriber::EnvFilter;
-use wgpu::util::DeviceExt;
 use winit::{
     event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
@@ -26,19 +25,62 @@ mod xr;
 
 mod blit_state;
 mod camera;
+mod debug_text_state;
+mod deferred_work;
+mod device_lost;
+mod dirty_ranges;
+mod environment_state;
+mod frame_stats;
+mod gpu_timer;
+mod growable_buffer;
+#[cfg(feature = "xr")]
+mod haptics;
+mod hdr;
+#[cfg(feature = "xr")]
+mod idle_timer;
+mod line_state;
 mod main_state;
+mod mesh;
+mod panorama_state;
+mod render_graph;
+#[cfg(feature = "xr")]
+mod reticle;
+mod scene;
+#[cfg(feature = "xr")]
+mod simulated_headset;
+#[cfg(feature = "xr")]
+mod spinner_state;
+mod ssao_state;
 mod texture;
 mod types;
 
 pub mod wgsl;
 
-use blit_state::BlitState;
-use camera::CameraState;
+use blit_state::{BlitParams, BlitState, ColorCorrection};
+use camera::{CameraState, DesktopView};
+#[cfg(feature = "xr")]
+use clap::Subcommand;
 use clap::{command, Parser};
-use main_state::{Instance, MainState};
+use debug_text_state::{DebugTextState, TextLabel};
+use deferred_work::DeferredWork;
+use environment_state::EnvironmentState;
+use frame_stats::{FrameStats, FrameTiming, FrameTimingQueue};
+use line_state::{Line, LineState};
+use main_state::{DrawTargets, Instance, InstanceFormat, MainState};
+use mesh::Mesh;
+use panorama_state::PanoramaState;
+use render_graph::RenderGraph;
+use scene::SceneSpec;
+use ssao_state::{SsaoConfig, SsaoState};
 use texture::Texture;
 use types::*;
 
+/// Non-critical work that is put off until a frame has time to spare.
+enum DeferredTask {
+    /// Save a stereo panorama of the scene.
+    CapturePanorama,
+}
+
 /// Encapsulates all wgpu device-related state. Used to isolate XR initialisation
 /// from desktop initialisation.
 pub struct WgpuState {
@@ -47,6 +89,14 @@ pub struct WgpuState {
     device: wgpu::Device,
     queue: wgpu::Queue,
 }
+impl WgpuState {
+    /// The name, backend, device type and PCI vendor/device IDs of the adapter in use. Worth
+    /// including in bug reports, as a lot of issues are specific to a backend or driver. (This
+    /// version of wgpu doesn't report the driver version; the IDs identify the GPU instead.)
+    pub fn adapter_info(&self) -> wgpu::AdapterInfo {
+        self.adapter.get_info()
+    }
+}
 
 fn main() -> anyhow::Result<()> {
     tracing_subscriber::FmtSubscriber::builder()
@@ -55,20 +105,342 @@ fn main() -> anyhow::Result<()> {
 
     const MAIN_TRIANGLE_SCALE: f32 = 1.0;
     const HAND_TRIANGLE_SCALE: f32 = 0.1;
+    /// The fraction of a frame's period that can be used before deferred work is put off.
+    const FRAME_BUDGET_FRACTION: f32 = 0.75;
+    /// The number of frames that deferred work can be put off for before it's run regardless.
+    const MAX_DEFERRED_FRAMES: u32 = 90;
+    /// The frame period assumed outside of XR, where the display rate isn't known.
+    const DESKTOP_FRAME_PERIOD: std::time::Duration = std::time::Duration::from_micros(16_667);
+    /// The initial width of the debug lines, in pixels.
+    const LINE_WIDTH: f32 = 3.0;
+    /// The range that the debug line width can be adjusted within, in pixels.
+    const LINE_WIDTH_RANGE: std::ops::RangeInclusive<f32> = 1.0..=16.0;
+    /// The highest subdivision level for the triangle; each level quadruples its triangle count.
+    const MAX_SUBDIVISION_LEVEL: i64 = 10;
 
-    #[derive(Parser, PartialEq)]
+    #[derive(Parser)]
     #[command(author, version, about)]
-    enum Args {
+    struct Args {
+        #[cfg(feature = "xr")]
+        #[command(subcommand)]
+        mode: Mode,
+        /// Subdivide the triangle into 4^N smaller triangles, to add rasterization load for
+        /// testing
+        #[arg(
+            long,
+            value_name = "N",
+            default_value_t = 0,
+            value_parser = clap::value_parser!(u32).range(0..=MAX_SUBDIVISION_LEVEL),
+        )]
+        subdivide: u32,
+        /// Add `count` (at most 100000) procedurally placed instances to the scene, using an
+        /// RNG seeded with `seed`, so that the same scene is generated every run
+        #[arg(long, value_name = "SEED:COUNT")]
+        generate_scene: Option<SceneSpec>,
+        /// Also render per-pixel motion vectors from the main pass, as groundwork for temporal
+        /// anti-aliasing and reprojection
+        #[arg(long)]
+        motion_vectors: bool,
+        /// Before showing the first frame, create the XR swapchains and render a frame that
+        /// isn't shown, so that the first real frame doesn't hitch on first-use work
+        #[arg(long)]
+        warm_up: bool,
+        /// How the scene is rendered when not rendering to a headset: as a stereo preview of
+        /// what each eye would see, or as a single view for a flat display, which is cheaper
+        #[arg(long, value_enum, default_value_t = DesktopView::default())]
+        desktop_view: DesktopView,
+        /// How each instance's transform is uploaded: as matrices, or as a translation,
+        /// rotation and scale that the vertex shader builds the matrices from, which is about
+        /// half the size
+        #[arg(long, value_enum, default_value_t = InstanceFormat::default())]
+        instance_format: InstanceFormat,
+        /// The depth bias of the scene's geometry, as `constant,slope`, to push surfaces that
+        /// are drawn over each other apart and reduce z-fighting. Negative values bring them
+        /// towards the viewer
+        #[arg(
+            long,
+            value_name = "CONSTANT,SLOPE",
+            value_parser = parse_depth_bias,
+            allow_hyphen_values = true
+        )]
+        depth_bias: Option<wgpu::DepthBiasState>,
+        /// The depth bias of lines (the boundary and debug lines), as `constant,slope`; see
+        /// `--depth-bias`
+        #[arg(
+            long,
+            value_name = "CONSTANT,SLOPE",
+            value_parser = parse_depth_bias,
+            allow_hyphen_values = true
+        )]
+        line_depth_bias: Option<wgpu::DepthBiasState>,
+        /// Darken creases and corners with screen-space ambient occlusion, computed from the
+        /// depth buffer
+        #[arg(long)]
+        ssao: bool,
+        /// The radius that SSAO checks for occluders, in pixels, for a surface 1 metre away
+        #[arg(long, value_name = "PIXELS", default_value_t = SsaoConfig::default().radius)]
+        ssao_radius: f32,
+        /// How strongly SSAO darkens occluded pixels; 1 makes fully occluded pixels black
+        #[arg(long, default_value_t = SsaoConfig::default().intensity)]
+        ssao_intensity: f32,
+        /// The number of depth samples per pixel for SSAO
+        #[arg(
+            long,
+            value_name = "COUNT",
+            default_value_t = SsaoConfig::default().sample_count,
+            value_parser = clap::value_parser!(u32).range(1..=64),
+        )]
+        ssao_samples: u32,
+        /// Show this HDR equirectangular environment map (a Radiance `.hdr` file) as a skybox
+        /// behind the scene, in place of the clear colour
+        #[arg(long, value_name = "PATH")]
+        environment: Option<PathBuf>,
+        /// Light the scene with the average colour of the environment map, rather than the
+        /// fixed ambient light, where the runtime doesn't estimate the real-world lighting
+        #[arg(long, requires = "environment")]
+        environment_lighting: bool,
+        /// Which layers (views) of the render target the main pass clears, e.g. `0` to only
+        /// clear the left eye's; the others keep the previous frame's contents. Only applies
+        /// while the main pass is clearing (see the T key)
+        #[arg(
+            long,
+            value_name = "LAYERS",
+            value_delimiter = ',',
+            default_values_t = 0..VIEW_COUNT,
+            value_parser = clap::value_parser!(u32).range(0..VIEW_COUNT as i64),
+        )]
+        clear_layers: Vec<u32>,
+        /// Map the scene to this rectangle of the render target, in pixels from its top-left
+        /// corner, rather than the whole of it
+        #[arg(long, value_name = "X,Y,WIDTH,HEIGHT", value_parser = parse_rect)]
+        viewport: Option<Rect>,
+        /// Only draw the scene's pixels within this rectangle of the render target, in pixels
+        /// from its top-left corner
+        #[arg(long, value_name = "X,Y,WIDTH,HEIGHT", value_parser = parse_rect)]
+        scissor: Option<Rect>,
+        /// The weight of each new frame in the FPS readout's moving average, from 0 to 1. Lower
+        /// values give a steadier readout that is slower to follow changes
+        #[arg(long, value_name = "WEIGHT", default_value_t = 0.05)]
+        fps_smoothing: f32,
+        /// Also show the 1% low FPS and the median and 99th percentile frame times, over the
+        /// last 1000 frames
+        #[arg(long)]
+        fps_percentiles: bool,
+        /// Load the XR configuration from this TOML file. Options specified on the command line
+        /// take precedence over the file; switches such as `--half-rate` can be given `=false` to
+        /// turn off an option that the file turns on.
+        #[cfg(feature = "xr")]
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// Save the resulting XR configuration to this TOML file
+        #[cfg(feature = "xr")]
+        #[arg(long)]
+        save_config: Option<PathBuf>,
+        /// Translation (in metres) of the stage origin, specified as `x,y,z` in OpenXR's
+        /// coordinate system
+        #[cfg(feature = "xr")]
+        #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+        reference_space_offset: Option<Vec3>,
+        /// Show a marker on the floor at the origin of the stage
+        #[cfg(feature = "xr")]
+        #[arg(
+            long,
+            value_name = "BOOL",
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "true",
+        )]
+        origin_marker: Option<bool>,
+        /// Show a small dot a metre ahead of the centre of the views, to check that they line
+        /// up. It's a layer submitted alongside the built-in ones, rather than part of the scene
+        #[cfg(feature = "xr")]
+        #[arg(long)]
+        reticle: bool,
+        /// Show this equirectangular (360 degree) PNG image behind the scene, as an equirect
+        /// composition layer
+        #[cfg(feature = "xr")]
+        #[arg(long, value_name = "PATH")]
+        background_image: Option<PathBuf>,
+        /// The radius, in metres, of the sphere around the stage origin that the background
+        /// image is mapped onto; 0 makes it infinitely far away
+        #[cfg(feature = "xr")]
+        #[arg(long, value_name = "METRES")]
+        background_radius: Option<f32>,
+        /// Outline the play area's boundary on the floor
+        #[cfg(feature = "xr")]
+        #[arg(
+            long,
+            value_name = "BOOL",
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "true",
+        )]
+        show_boundary: Option<bool>,
+        /// Place the scene relative to the centre of the play area, rather than the stage
+        /// origin, which differ if the reference space has been offset
+        #[cfg(feature = "xr")]
+        #[arg(
+            long,
+            value_name = "BOOL",
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "true",
+        )]
+        center_scene: Option<bool>,
+        /// Render every other frame, and let the compositor reproject the frames in between
+        #[cfg(feature = "xr")]
+        #[arg(
+            long,
+            value_name = "BOOL",
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "true",
+        )]
+        half_rate: Option<bool>,
+        /// Run the frame's CPU work before waiting for the XR frame, overlapping it with the
+        /// display of the previous frame
+        #[cfg(feature = "xr")]
+        #[arg(
+            long,
+            value_name = "BOOL",
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "true",
+        )]
+        simulate_before_wait: Option<bool>,
+        /// Periodically log the state of every action, and whether each hand is tracked, to
+        /// diagnose bindings and interaction profiles
+        #[cfg(feature = "xr")]
+        #[arg(
+            long,
+            value_name = "BOOL",
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "true",
+        )]
+        log_input: Option<bool>,
+        /// End the XR session and exit after neither the head nor the hands have moved for
+        /// this many seconds
+        #[cfg(feature = "xr")]
+        #[arg(long, value_name = "SECONDS")]
+        idle_timeout: Option<u64>,
+        /// What happens to a hand's indicator while its controller isn't being tracked
+        #[cfg(feature = "xr")]
+        #[arg(long, value_enum)]
+        untracked_hands: Option<xr::UntrackedHands>,
+        /// Experimental: render this eye at a reduced resolution, to approximate foveated
+        /// rendering
+        #[cfg(feature = "xr")]
+        #[arg(long, value_enum)]
+        reduced_eye: Option<xr::Eye>,
+        /// The scale of the reduced eye's resolution on each axis, from 0.25 to 1
+        #[cfg(feature = "xr")]
+        #[arg(long, value_name = "SCALE")]
+        reduced_eye_scale: Option<f32>,
+        /// Whether the eyes share a projection swapchain, or each have their own
+        #[cfg(feature = "xr")]
+        #[arg(long, value_enum)]
+        eye_layout: Option<xr::EyeLayout>,
+        /// Replace the action bindings from the config file with the defaults. Combine with
+        /// `--save-config` to reset the bindings in the file
+        #[cfg(feature = "xr")]
+        #[arg(long)]
+        default_bindings: bool,
+        /// Whether the desktop mirror is presented every frame, or only with the frames
+        /// rendered for the headset
+        #[cfg(feature = "xr")]
+        #[arg(long, value_enum, default_value_t = MirrorSync::Immediate)]
+        mirror_sync: MirrorSync,
+    }
+
+    #[cfg(feature = "xr")]
+    #[derive(Subcommand, PartialEq, Clone, Copy)]
+    enum Mode {
         /// Only desktop
         Desktop,
         /// Desktop with XR initialization and resolution
         DesktopWithXrResolution,
         /// Render to headset
         Xr,
+        /// Desktop, with views and controllers driven by a simulated headset. This exercises
+        /// the XR rendering paths without needing hardware or an OpenXR runtime.
+        Simulated,
     }
 
+    /// How the desktop mirror is presented while rendering to the headset.
     #[cfg(feature = "xr")]
+    #[derive(clap::ValueEnum, PartialEq, Clone, Copy)]
+    enum MirrorSync {
+        /// Present every frame as soon as it's rendered, for the lowest latency. The mirror
+        /// may tear, and judders when the headset's and monitor's rates don't divide evenly
+        Immediate,
+        /// Only present the frames that were rendered for the headset, and let the monitor
+        /// pick up the latest one at its next refresh, without tearing. This is smoother, at
+        /// the cost of up to a monitor refresh of extra latency
+        Headset,
+    }
+
     let args = Args::parse();
+    #[cfg(feature = "xr")]
+    let mut xr_config = {
+        let mut xr_config = match &args.config {
+            Some(path) => xr::XrConfig::load(path)?,
+            None => xr::XrConfig::default(),
+        };
+        if let Some(offset) = args.reference_space_offset {
+            xr_config.reference_space_offset.position = openxr::Vector3f {
+                x: offset.x,
+                y: offset.y,
+                z: offset.z,
+            };
+        }
+        if let Some(origin_marker) = args.origin_marker {
+            xr_config.origin_marker = origin_marker;
+        }
+        if let Some(path) = &args.background_image {
+            xr_config.background_image = Some(path.clone());
+        }
+        if let Some(radius) = args.background_radius {
+            xr_config.background_radius = radius;
+        }
+        if let Some(show_boundary) = args.show_boundary {
+            xr_config.show_boundary = show_boundary;
+        }
+        if let Some(center_scene) = args.center_scene {
+            xr_config.center_scene = center_scene;
+        }
+        if let Some(half_rate) = args.half_rate {
+            xr_config.half_rate = half_rate;
+        }
+        if let Some(simulate_before_wait) = args.simulate_before_wait {
+            xr_config.simulate_before_wait = simulate_before_wait;
+        }
+        if let Some(log_input) = args.log_input {
+            xr_config.log_input = log_input;
+        }
+        if let Some(timeout) = args.idle_timeout {
+            xr_config.idle_timeout_secs = Some(timeout);
+        }
+        if let Some(untracked_hands) = args.untracked_hands {
+            xr_config.untracked_hands = untracked_hands;
+        }
+        if let Some(reduced_eye) = args.reduced_eye {
+            xr_config.reduced_eye = Some(reduced_eye);
+        }
+        if let Some(scale) = args.reduced_eye_scale {
+            xr_config.reduced_eye_scale = scale;
+        }
+        if let Some(eye_layout) = args.eye_layout {
+            xr_config.eye_layout = eye_layout;
+        }
+        if args.default_bindings {
+            xr_config.bindings = xr::XrConfig::default().bindings;
+        }
+        if let Some(path) = &args.save_config {
+            xr_config.save(path)?;
+        }
+        xr_config
+    };
 
     let wgpu_features = wgpu::Features::MULTIVIEW | wgpu::Features::PUSH_CONSTANTS;
     let wgpu_limits = wgpu::Limits {
@@ -77,38 +449,146 @@ fn main() -> anyhow::Result<()> {
     };
 
     let event_loop = EventLoop::new();
-    let window = winit::window::Window::new(&event_loop)?;
+    let window_icon = load_window_icon()
+        .map_err(|err| log::warn!("not setting the window icon: {err:#}"))
+        .ok();
+    let window = winit::window::WindowBuilder::new()
+        .with_window_icon(window_icon)
+        .build(&event_loop)?;
 
     #[cfg(feature = "xr")]
-    let (wgpu_state, surface, mut xr_state) = if args != Args::Desktop {
-        let (wgpu_state, xr_state) = xr::XrState::initialize_with_wgpu(wgpu_features, wgpu_limits)?;
-        window.set_resizable(false);
-        let view = xr_state.views()[0];
-        window.set_inner_size(winit::dpi::PhysicalSize::new(
-            view.recommended_image_rect_width,
-            view.recommended_image_rect_height,
-        ));
-        let surface = unsafe { wgpu_state.instance.create_surface(&window) };
-        (wgpu_state, surface, Some(xr_state))
-    } else {
-        let (wgpu_state, surface) = create_wgpu_state(&window, wgpu_features, wgpu_limits)?;
-        (wgpu_state, surface, None)
+    let (wgpu_state, surface, mut xr_state) =
+        if matches!(args.mode, Mode::DesktopWithXrResolution | Mode::Xr) {
+            let (wgpu_state, mut xr_state) =
+                xr::XrState::initialize_with_wgpu(wgpu_features, wgpu_limits, &xr_config, |_| {
+                    Ok(())
+                })?;
+            if xr_config.origin_marker {
+                if let Err(err) = add_origin_marker(&wgpu_state.device, &mut xr_state) {
+                    log::warn!("not showing the origin marker: {err:#}");
+                }
+            }
+            if let Some(path) = &xr_config.background_image {
+                // Check before decoding the image, which can take a while for a large one.
+                if !xr_state.supported_layer_types().equirect2 {
+                    log::warn!(
+                        "not showing the background image: the runtime has no equirect layers"
+                    );
+                } else if let Err(err) = add_background_layer(
+                    &wgpu_state.device,
+                    &mut xr_state,
+                    path,
+                    xr_config.background_radius,
+                ) {
+                    log::warn!("not showing the background image: {err:#}");
+                }
+            }
+            window.set_resizable(false);
+            // The window only mirrors the headset in XR mode, so the cursor would just be in the
+            // way of what the user is seeing.
+            if args.mode == Mode::Xr {
+                window.set_cursor_visible(false);
+            }
+            let view = xr_state.views()[0];
+            window.set_inner_size(winit::dpi::PhysicalSize::new(
+                view.recommended_image_rect_width,
+                view.recommended_image_rect_height,
+            ));
+            let surface = unsafe { wgpu_state.instance.create_surface(&window) };
+            (wgpu_state, surface, Some(xr_state))
+        } else {
+            let (wgpu_state, surface) = create_wgpu_state(&window, wgpu_features, wgpu_limits)?;
+            (wgpu_state, surface, None)
+        };
+    #[cfg(feature = "xr")]
+    let reticle = match (&xr_state, args.reticle) {
+        (Some(xr_state), true) => {
+            reticle::Reticle::new(&wgpu_state.device, &wgpu_state.queue, xr_state)
+                .map_err(|err| log::warn!("not showing the reticle: {err:#}"))
+                .ok()
+        }
+        _ => None,
     };
 
+    #[cfg(feature = "xr")]
+    let simulated_headset =
+        (args.mode == Mode::Simulated).then(simulated_headset::SimulatedHeadset::default);
+
     #[cfg(not(feature = "xr"))]
     let (wgpu_state, surface) = create_wgpu_state(&window, wgpu_features, wgpu_limits)?;
 
-    let mut camera_state = CameraState::new(&wgpu_state.device, window.inner_size());
+    let adapter_info = wgpu_state.adapter_info();
+    log::info!(
+        "using adapter {:?} ({:?}, {:?}, vendor {:#06x}, device {:#06x})",
+        adapter_info.name,
+        adapter_info.backend,
+        adapter_info.device_type,
+        adapter_info.vendor,
+        adapter_info.device,
+    );
+    device_lost::install_handlers(&wgpu_state.device, &adapter_info);
+
+    // The views come from the headset (real or simulated) in these modes, which are stereo.
+    #[cfg(feature = "xr")]
+    let desktop_view = if matches!(args.mode, Mode::Xr | Mode::Simulated)
+        && args.desktop_view != DesktopView::Stereo
+    {
+        log::warn!("ignoring --desktop-view, as the views come from the headset");
+        DesktopView::Stereo
+    } else {
+        args.desktop_view
+    };
+    #[cfg(not(feature = "xr"))]
+    let desktop_view = args.desktop_view;
+    let mut camera_state = CameraState::new(&wgpu_state.device, window.inner_size(), desktop_view);
+    let view_count = camera_state.view_count();
 
     let preprocessor = wgsl::Preprocessor::from_directory(Path::new("shaders"))?;
 
+    #[cfg(feature = "xr")]
+    if let Some(xr_state) = &mut xr_state {
+        add_loading_spinner(&wgpu_state.device, &preprocessor, xr_state)?;
+    }
+
     let window_swapchain_format = surface.get_supported_formats(&wgpu_state.adapter)[0];
+    // When using XR, only pick a depth format that the runtime can also create swapchains with.
+    #[cfg(feature = "xr")]
+    let xr_depth_formats = match &xr_state {
+        Some(xr_state) => Some(xr_state.depth_swapchain_formats()?),
+        None => None,
+    };
+    let depth_format =
+        match select_depth_format(&wgpu_state.adapter, &wgpu_state.device, |_format| {
+            #[cfg(feature = "xr")]
+            if let Some(xr_depth_formats) = &xr_depth_formats {
+                return xr_depth_formats.contains(&_format);
+            }
+            true
+        }) {
+            Ok(format) => format,
+            // The depth buffer is only shared with the runtime by a depth layer, so it can still be
+            // rendered with if the runtime can't create swapchains in any of the adapter's formats.
+            #[cfg(feature = "xr")]
+            Err(_) if xr_depth_formats.is_some() => {
+                log::warn!("the XR runtime doesn't support any of the adapter's depth formats");
+                select_depth_format(&wgpu_state.adapter, &wgpu_state.device, |_| true)?
+            }
+            Err(err) => return Err(err),
+        };
+    log::info!("using depth format {depth_format:?}");
+
     let mut main_state = MainState::new(
         &wgpu_state.device,
         &preprocessor,
         &camera_state,
         window_swapchain_format,
-        vec![
+        depth_format,
+        wgpu::PrimitiveState {
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None,
+            ..Default::default()
+        },
+        [
             Instance::new(
                 vec3(0.0, 0.0, 1.0),
                 Quat::IDENTITY,
@@ -124,8 +604,77 @@ fn main() -> anyhow::Result<()> {
                 Quat::IDENTITY,
                 Vec3::ONE * HAND_TRIANGLE_SCALE,
             ),
-        ],
+        ]
+        .into_iter()
+        .chain(args.generate_scene.iter().flat_map(SceneSpec::generate))
+        .collect(),
+    );
+    main_state.set_instance_format(&wgpu_state.device, args.instance_format);
+    if let Some(depth_bias) = args.depth_bias {
+        main_state.set_depth_bias(&wgpu_state.device, depth_bias);
+    }
+    // Let the background layer show through the scene's background.
+    #[cfg(feature = "xr")]
+    if xr_state
+        .as_ref()
+        .is_some_and(xr::XrState::has_background_layer)
+    {
+        main_state.clear_color = wgpu::Color::TRANSPARENT;
+    }
+
+    // The rotation of the scene as a whole, which the main triangle spins relative to.
+    #[cfg(feature = "xr")]
+    let scene_rotation = match xr_state.as_ref().map(xr::XrState::play_area) {
+        Some(Ok(play_area)) => {
+            log::info!(
+                "play area centre: {}, size: {:?}",
+                play_area.center.0,
+                play_area.size
+            );
+            if xr_config.center_scene {
+                center_scene(&mut main_state.instances, play_area.center);
+                play_area.center.1
+            } else {
+                Quat::IDENTITY
+            }
+        }
+        Some(Err(err)) => {
+            log::warn!("couldn't query the play area: {err:#}");
+            Quat::IDENTITY
+        }
+        None => Quat::IDENTITY,
+    };
+    #[cfg(not(feature = "xr"))]
+    let scene_rotation = Quat::IDENTITY;
+    #[cfg(feature = "xr")]
+    let mut boundary_lines = xr_state
+        .as_ref()
+        .filter(|_| xr_config.show_boundary)
+        .and_then(|x| x.play_area().ok())
+        .map(|play_area| boundary_lines(&play_area))
+        .unwrap_or_default();
+
+    let mut debug_text_state = DebugTextState::new(
+        &wgpu_state.device,
+        &wgpu_state.queue,
+        &preprocessor,
+        &camera_state,
+        window_swapchain_format,
+        window.inner_size(),
+    );
+
+    let mut line_state = LineState::new(
+        &wgpu_state.device,
+        &preprocessor,
+        &camera_state,
+        window_swapchain_format,
+        depth_format,
+        window.inner_size(),
+        LINE_WIDTH,
     );
+    if let Some(depth_bias) = args.line_depth_bias {
+        line_state.set_depth_bias(&wgpu_state.device, depth_bias);
+    }
 
     let mut config = {
         let size = window.inner_size();
@@ -137,10 +686,82 @@ fn main() -> anyhow::Result<()> {
             present_mode: wgpu::PresentMode::Immediate,
         }
     };
+    // Mailbox replaces the queued frame rather than waiting for a refresh, so presenting never
+    // blocks the headset's frame loop on the monitor.
+    #[cfg(feature = "xr")]
+    if args.mirror_sync == MirrorSync::Headset {
+        if surface
+            .get_supported_modes(&wgpu_state.adapter)
+            .contains(&wgpu::PresentMode::Mailbox)
+        {
+            config.present_mode = wgpu::PresentMode::Mailbox;
+        } else {
+            log::warn!("mailbox presentation isn't supported; the mirror may tear");
+        }
+    }
     surface.configure(&wgpu_state.device, &config);
-    let mut depth_texture = Texture::new_depth_texture(&wgpu_state.device, &config);
-    let mut rt_texture =
-        Texture::new_rt_texture(&wgpu_state.device, &config, window_swapchain_format);
+    let mut depth_texture = Texture::new_depth_texture(
+        &wgpu_state.device,
+        config.width,
+        config.height,
+        view_count,
+        depth_format,
+    );
+    let mut rt_texture = Texture::new_rt_texture(
+        &wgpu_state.device,
+        config.width,
+        config.height,
+        view_count,
+        window_swapchain_format,
+    );
+    let mut region = scene_region(args.viewport, args.scissor, config.width, config.height);
+    let mut ssao_state = args.ssao.then(|| {
+        SsaoState::new(
+            &wgpu_state.device,
+            &preprocessor,
+            &depth_texture.depth_view(),
+            window_swapchain_format,
+            SsaoConfig {
+                radius: args.ssao_radius,
+                intensity: args.ssao_intensity,
+                sample_count: args.ssao_samples,
+            },
+            camera_state.data.z_near..camera_state.data.z_far,
+            view_count,
+        )
+    });
+    let environment_map = args
+        .environment
+        .as_deref()
+        .map(|path| hdr::load(path, wgpu_state.device.limits().max_texture_dimension_2d))
+        .transpose()?;
+    if let Some(map) = environment_map
+        .as_ref()
+        .filter(|_| args.environment_lighting)
+    {
+        main_state.set_ambient_light(&wgpu_state.queue, map.average_radiance());
+    }
+    let mut environment_state = environment_map.map(|map| {
+        EnvironmentState::new(
+            &wgpu_state.device,
+            &wgpu_state.queue,
+            &preprocessor,
+            &map,
+            &depth_texture.depth_view(),
+            window_swapchain_format,
+            view_count,
+        )
+    });
+    let mut motion_vector_texture = args.motion_vectors.then(|| {
+        main_state.enable_motion_vectors(&wgpu_state.device);
+        Texture::new_rt_texture(
+            &wgpu_state.device,
+            config.width,
+            config.height,
+            view_count,
+            main_state::MOTION_VECTOR_FORMAT,
+        )
+    });
     let mut blit_state = BlitState::new(
         &wgpu_state.device,
         &preprocessor,
@@ -151,23 +772,170 @@ fn main() -> anyhow::Result<()> {
         #[cfg(feature = "xr")]
         xr::WGPU_COLOR_FORMAT,
     );
+    #[cfg(feature = "xr")]
+    {
+        let mut params = *blit_state.params();
+        for (view_index, correction) in xr_config.eye_color_correction.iter().enumerate() {
+            params.set_eye_correction(view_index, *correction);
+        }
+        params.clamp_adjustments();
+        blit_state.set_params(&wgpu_state.queue, params);
+    }
 
-    let triangle_vertex_buffer =
-        wgpu_state
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("Vertex Buffer"),
-                contents: bytemuck::cast_slice(&[
-                    Vertex::new(vec3(-1.0, -1.0, 0.0), vec4(1.0, 0.0, 0.0, 1.0)),
-                    Vertex::new(vec3(0.0, 1.0, 0.0), vec4(0.0, 1.0, 0.0, 1.0)),
-                    Vertex::new(vec3(1.0, -1.0, 0.0), vec4(0.0, 0.0, 1.0, 1.0)),
-                ]),
-                usage: wgpu::BufferUsages::VERTEX,
-            });
+    #[cfg(feature = "xr")]
+    if let Some(xr_state) = &xr_state {
+        let view_scales = xr_state.view_scales();
+        if view_scales != [1.0; 2] {
+            log::info!("rendering views at {view_scales:?} of full resolution");
+        }
+        let mut params = *blit_state.params();
+        params.view_scales = view_scales;
+        blit_state.set_params(&wgpu_state.queue, params);
+        // Each eye's swapchain is blitted to separately, like the window.
+        if xr_config.eye_layout == xr::EyeLayout::Separate {
+            blit_state.prepare_output_format(&wgpu_state.device, xr::WGPU_COLOR_FORMAT);
+        }
+    }
+
+    let panorama_state = PanoramaState::new(
+        &wgpu_state.device,
+        &preprocessor,
+        window_swapchain_format,
+        depth_format,
+    );
+
+    let triangle_mesh = {
+        let (vertices, indices) = mesh::subdivided_triangle(
+            [
+                vec3(-1.0, -1.0, 0.0),
+                vec3(0.0, 1.0, 0.0),
+                vec3(1.0, -1.0, 0.0),
+            ],
+            [
+                vec4(1.0, 0.0, 0.0, 1.0),
+                vec4(0.0, 1.0, 0.0, 1.0),
+                vec4(0.0, 0.0, 1.0, 1.0),
+            ],
+            Vec3::NEG_Z,
+            args.subdivide,
+        );
+        if args.subdivide > 0 {
+            log::info!(
+                "subdivided the triangle into {} triangles",
+                indices.len() / 3
+            );
+        }
+        Mesh::new(&wgpu_state.device, &vertices, &indices)
+    };
+
+    if args.warm_up {
+        let warm_up_start = std::time::Instant::now();
+        #[cfg(feature = "xr")]
+        if let (Mode::Xr, Some(xr_state)) = (&args.mode, &mut xr_state) {
+            xr_state.create_swapchains(&wgpu_state.device)?;
+        }
+
+        // Draws with each pipeline that the first frames could use, into the render target
+        // (which the first frame clears) and a scratch texture in place of the window, then
+        // waits for the GPU, so that the driver has done its first-use work.
+        let mut encoder =
+            wgpu_state
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Warm-up Encoder"),
+                });
+        // The lines and labels are replaced every frame, but there has to be something to draw.
+        main_state.upload_instances(&wgpu_state.device, &wgpu_state.queue);
+        line_state.upload_lines(
+            &wgpu_state.device,
+            &wgpu_state.queue,
+            &[Line::new(Vec3::ZERO, Vec3::Y, vec4(1.0, 1.0, 1.0, 1.0))],
+        );
+        debug_text_state.upload_labels(
+            &wgpu_state.device,
+            &wgpu_state.queue,
+            &[TextLabel::screen(
+                Vec2::ZERO,
+                "warm-up",
+                vec4(1.0, 1.0, 1.0, 1.0),
+            )],
+        );
+        let region = RenderRegion::full(config.width, config.height);
+        let targets = DrawTargets {
+            color: rt_texture.view(),
+            depth: depth_texture.view(),
+            motion_vectors: motion_vector_texture.as_ref().map(Texture::view),
+        };
+        let show_overdraw = main_state.show_overdraw;
+        for show_overdraw in [!show_overdraw, show_overdraw] {
+            main_state.show_overdraw = show_overdraw;
+            main_state.encode_draw_pass(
+                &mut encoder,
+                &targets,
+                LoadBehavior::Clear,
+                &region,
+                &triangle_mesh,
+                camera_state.bind_group(),
+            );
+        }
+        if let Some(environment_state) = &environment_state {
+            environment_state.encode_draw_pass(&mut encoder, rt_texture.view(), &region);
+        }
+        if let Some(ssao_state) = &ssao_state {
+            ssao_state.encode_draw_pass(&mut encoder, rt_texture.view(), &region);
+        }
+        line_state.encode_draw_pass(
+            &mut encoder,
+            rt_texture.view(),
+            depth_texture.view(),
+            &region,
+            camera_state.bind_group(),
+        );
+        debug_text_state.encode_draw_pass(
+            &mut encoder,
+            rt_texture.view(),
+            &region,
+            camera_state.bind_group(),
+        );
+        let scratch = Texture::new_rt_texture(&wgpu_state.device, 1, 1, 1, window_swapchain_format);
+        blit_state.encode_draw_pass_to(
+            &mut encoder,
+            &scratch.layer_view(0),
+            window_swapchain_format,
+            0,
+            LoadBehavior::Clear,
+        )?;
+        wgpu_state.queue.submit(Some(encoder.finish()));
+        wgpu_state.device.poll(wgpu::Maintain::Wait);
+        log::info!("warmed up in {:?}", warm_up_start.elapsed());
+    }
 
     let start_time = std::time::Instant::now();
-    let (mut fps_timer, mut fps_count) = (std::time::Instant::now(), 0);
+    // The predicted display time of the first XR frame, and the time since starting at that
+    // point, so that the shaders' time stays continuous when it switches to the XR clock.
+    #[cfg(feature = "xr")]
+    let mut xr_time_origin: Option<(openxr::Time, f64)> = None;
+    let mut frame_stats = FrameStats::new(args.fps_smoothing, 1000);
+    // Each frame's timing, for the HUD; consumers elsewhere can be given a clone.
+    let frame_timings = FrameTimingQueue::new(1000);
+    // Only available if the device supports timestamp queries.
+    let mut gpu_timer = gpu_timer::GpuTimer::new(&wgpu_state.device, &wgpu_state.queue);
+    let (mut last_frame_end, mut title_timer) =
+        (std::time::Instant::now(), std::time::Instant::now());
     let mut view_index = 0;
+    // The colour channel adjusted by the colour correction keys, or `None` for all of them.
+    let mut correction_channel: Option<usize> = None;
+    let mut show_labels = false;
+    let mut show_eye_letters = false;
+    let mut show_hud = false;
+    let mut show_gizmos = false;
+    // Loading the previous frame's contents instead of clearing them leaves trails behind
+    // moving content.
+    let mut main_pass_load = LoadBehavior::Clear;
+    // Toggled per axis with the X, Y and Z keys, to see what each choice of signs does.
+    #[cfg(feature = "xr")]
+    let mut axis_convention = xr::AxisConvention::default();
+    let mut deferred_work = DeferredWork::new(FRAME_BUDGET_FRACTION, MAX_DEFERRED_FRAMES);
     event_loop.run(move |event, _, control_flow| {
         // Have the closure take ownership of the resources.
         // `event_loop.run` never returns, therefore we must do this to ensure
@@ -176,11 +944,15 @@ fn main() -> anyhow::Result<()> {
         let _ = &xr_state;
         let _ = (
             &wgpu_state,
-            &triangle_vertex_buffer,
+            &triangle_mesh,
             &main_state,
             &depth_texture,
             &rt_texture,
+            &motion_vector_texture,
             &blit_state,
+            &debug_text_state,
+            &line_state,
+            &panorama_state,
         );
 
         let mut cleared = false;
@@ -195,12 +967,41 @@ fn main() -> anyhow::Result<()> {
                 config.width = size.width;
                 config.height = size.height;
                 surface.configure(&wgpu_state.device, &config);
-                depth_texture = Texture::new_depth_texture(&wgpu_state.device, &config);
-                rt_texture =
-                    Texture::new_rt_texture(&wgpu_state.device, &config, window_swapchain_format);
+                depth_texture = Texture::new_depth_texture(
+                    &wgpu_state.device,
+                    config.width,
+                    config.height,
+                    view_count,
+                    depth_format,
+                );
+                rt_texture = Texture::new_rt_texture(
+                    &wgpu_state.device,
+                    config.width,
+                    config.height,
+                    view_count,
+                    window_swapchain_format,
+                );
+                if motion_vector_texture.is_some() {
+                    motion_vector_texture = Some(Texture::new_rt_texture(
+                        &wgpu_state.device,
+                        config.width,
+                        config.height,
+                        view_count,
+                        main_state::MOTION_VECTOR_FORMAT,
+                    ));
+                }
 
                 blit_state.resize(&wgpu_state.device, rt_texture.view());
+                if let Some(ssao_state) = &mut ssao_state {
+                    ssao_state.resize(&wgpu_state.device, &depth_texture.depth_view());
+                }
+                if let Some(environment_state) = &mut environment_state {
+                    environment_state.resize(&wgpu_state.device, &depth_texture.depth_view());
+                }
+                debug_text_state.resize(&wgpu_state.queue, size);
+                line_state.resize(&wgpu_state.queue, size);
                 camera_state.data.resize(size);
+                region = scene_region(args.viewport, args.scissor, config.width, config.height);
 
                 // On macos the window needs to be redrawn manually after resizing
                 window.request_redraw();
@@ -222,14 +1023,20 @@ fn main() -> anyhow::Result<()> {
                     },
                 ..
             } => {
-                view_index = (view_index + 1) % 2;
+                view_index = (view_index + 1) % view_count;
             }
+            #[cfg(feature = "xr")]
             Event::WindowEvent {
                 event:
                     WindowEvent::KeyboardInput {
                         input:
                             KeyboardInput {
-                                virtual_keycode: Some(VirtualKeyCode::Escape),
+                                virtual_keycode:
+                                    Some(
+                                        key @ (VirtualKeyCode::X
+                                        | VirtualKeyCode::Y
+                                        | VirtualKeyCode::Z),
+                                    ),
                                 state: ElementState::Released,
                                 ..
                             },
@@ -237,119 +1044,1160 @@ fn main() -> anyhow::Result<()> {
                     },
                 ..
             } => {
-                *control_flow = ControlFlow::Exit;
-            }
-            Event::MainEventsCleared => {
-                window.request_redraw();
-                cleared = true;
+                let axis = match key {
+                    VirtualKeyCode::X => 0,
+                    VirtualKeyCode::Y => 1,
+                    VirtualKeyCode::Z => 2,
+                    _ => unreachable!(),
+                };
+                axis_convention.negate[axis] = !axis_convention.negate[axis];
+                log::info!("axis convention: {axis_convention:?}");
+                if let Some(xr_state) = &mut xr_state {
+                    xr_state.set_axis_convention(axis_convention);
+                }
             }
-            _ => {}
-        }
-
-        if !cleared {
-            return;
-        }
-
-        #[cfg(feature = "xr")]
-        let xr_frame_state = if args == Args::Xr {
-            xr_state.as_mut().and_then(|x| x.pre_frame().unwrap())
-        } else {
-            None
-        };
-
-        let mut encoder = wgpu_state
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-        main_state.encode_draw_pass(
-            &mut encoder,
-            rt_texture.view(),
-            depth_texture.view(),
-            &triangle_vertex_buffer,
-            camera_state.bind_group(),
-        );
-
-        let frame = surface
-            .get_current_texture()
-            .expect("Failed to acquire next swap chain texture");
-        let view = frame
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
-        blit_state.encode_draw_pass(&mut encoder, &view, Some(view_index));
-
-        #[cfg(feature = "xr")]
-        let pfd = xr_state
-            .as_mut()
-            .zip(xr_frame_state)
-            .map(|(xr_state, xr_frame_state)| {
-                xr_state
-                    .post_frame(
-                        &wgpu_state.device,
-                        xr_frame_state,
-                        &mut encoder,
-                        &blit_state,
-                    )
-                    .unwrap()
-            });
-
-        let time_since_start = start_time.elapsed().as_secs_f32();
-        {
-            let insts = &mut main_state.instances;
-            insts[0].rotation = Quat::from_rotation_y(time_since_start / std::f32::consts::PI);
             #[cfg(feature = "xr")]
-            if let Some(pfd) = &pfd {
-                if let Some(lh) = pfd.left_hand {
-                    (insts[1].translation, insts[1].rotation) = lh;
-                }
-                if let Some(rh) = pfd.right_hand {
-                    (insts[2].translation, insts[2].rotation) = rh;
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                virtual_keycode: Some(VirtualKeyCode::V),
+                                state: ElementState::Released,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                if let Some(xr_state) = &mut xr_state {
+                    let ms = std::time::Duration::from_millis;
+                    let patterns = [
+                        (xr::Hand::Left, haptics::HapticPattern::pulses(2, 0.8, ms(60), ms(80))),
+                        (xr::Hand::Right, haptics::HapticPattern::envelope(0.1, 1.0, ms(400), 8)),
+                    ];
+                    for (hand, pattern) in patterns {
+                        if let Err(err) = xr_state.play_haptic_pattern(hand, &pattern) {
+                            log::warn!(
+                                "couldn't play a haptic pattern on the {hand:?} hand: {err:#}"
+                            );
+                        }
+                    }
                 }
             }
-        }
-        main_state.upload_instances(&wgpu_state.queue);
-
-        wgpu_state.queue.write_buffer(
-            camera_state.buffer(),
-            0,
-            bytemuck::cast_slice(&{
-                #[cfg(feature = "xr")]
-                match &pfd {
-                    Some(pfd) => camera_state
-                        .data
-                        .to_view_proj_matrices_with_xr_views(&pfd.views),
-                    None => camera_state.data.to_view_proj_matrices(),
-                }
-                #[cfg(not(feature = "xr"))]
-                camera_state.data.to_view_proj_matrices()
-            }),
-        );
-
-        wgpu_state.queue.submit(Some(encoder.finish()));
-
-        #[cfg(feature = "xr")]
-        if let (Some(xr_state), Some(xr_frame_state), Some(pfd)) =
-            (xr_state.as_mut(), xr_frame_state, &pfd)
-        {
-            if xr_frame_state.should_render {
-                xr_state
-                    .post_queue_submit(xr_frame_state, &pfd.views)
-                    .unwrap();
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                virtual_keycode: Some(VirtualKeyCode::L),
+                                state: ElementState::Released,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                show_labels = !show_labels;
             }
-        }
-
-        frame.present();
-
-        fps_count += 1;
-        if fps_timer.elapsed().as_millis() > 100 {
-            window.set_title(&format!(
-                "wgpu-openxr-example: {:.02} FPS | {} view",
-                (fps_count as f32) / fps_timer.elapsed().as_secs_f32(),
-                if view_index == 0 { "left" } else { "right" }
-            ));
-
-            fps_count = 0;
-            fps_timer = std::time::Instant::now();
-        }
-    });
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                virtual_keycode: Some(VirtualKeyCode::E),
+                                state: ElementState::Released,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                show_eye_letters = !show_eye_letters;
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                virtual_keycode: Some(VirtualKeyCode::H),
+                                state: ElementState::Released,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                show_hud = !show_hud;
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                virtual_keycode: Some(VirtualKeyCode::C),
+                                state: ElementState::Released,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                let cull_mode = match main_state.cull_mode() {
+                    None => Some(wgpu::Face::Back),
+                    Some(_) => None,
+                };
+                log::info!("cull mode: {cull_mode:?}");
+                main_state.set_cull_mode(&wgpu_state.device, cull_mode);
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                virtual_keycode: Some(VirtualKeyCode::O),
+                                state: ElementState::Released,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                main_state.show_overdraw = !main_state.show_overdraw;
+                let mut params = *blit_state.params();
+                params.overdraw_heatmap = main_state.show_overdraw.into();
+                blit_state.set_params(&wgpu_state.queue, params);
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                virtual_keycode: Some(VirtualKeyCode::T),
+                                state: ElementState::Released,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                main_pass_load = match main_pass_load {
+                    LoadBehavior::Clear => LoadBehavior::Load,
+                    LoadBehavior::Load => LoadBehavior::Clear,
+                };
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                virtual_keycode: Some(VirtualKeyCode::D),
+                                state: ElementState::Released,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                // Ramp across the whole depth range, to show how much of it the scene uses.
+                let camera = &camera_state.data;
+                let range = main_state
+                    .depth_shading()
+                    .is_none()
+                    .then_some(camera.z_near..camera.z_far);
+                main_state.set_depth_shading(&wgpu_state.queue, range);
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                virtual_keycode: Some(VirtualKeyCode::P),
+                                state: ElementState::Released,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                // The panorama is stereo, so it needs the scene's pipelines to be.
+                if view_count == VIEW_COUNT {
+                    deferred_work.push(DeferredTask::CapturePanorama);
+                } else {
+                    log::warn!("panoramas can't be captured with a mono desktop view");
+                }
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                virtual_keycode: Some(VirtualKeyCode::K),
+                                state: ElementState::Released,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                let mut params = *blit_state.params();
+                params.calibration_pattern = (params.calibration_pattern == 0).into();
+                blit_state.set_params(&wgpu_state.queue, params);
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                virtual_keycode:
+                                    Some(
+                                        key @ (VirtualKeyCode::Minus
+                                        | VirtualKeyCode::Equals
+                                        | VirtualKeyCode::Comma
+                                        | VirtualKeyCode::Period
+                                        | VirtualKeyCode::Semicolon
+                                        | VirtualKeyCode::Apostrophe
+                                        | VirtualKeyCode::Back),
+                                    ),
+                                state: ElementState::Released,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                let mut params = *blit_state.params();
+                match key {
+                    VirtualKeyCode::Minus => params.gamma -= 0.1,
+                    VirtualKeyCode::Equals => params.gamma += 0.1,
+                    VirtualKeyCode::Comma => params.brightness -= 0.05,
+                    VirtualKeyCode::Period => params.brightness += 0.05,
+                    VirtualKeyCode::Semicolon => params.contrast -= 0.1,
+                    VirtualKeyCode::Apostrophe => params.contrast += 0.1,
+                    _ => {
+                        let defaults = BlitParams::default();
+                        params.gamma = defaults.gamma;
+                        params.brightness = defaults.brightness;
+                        params.contrast = defaults.contrast;
+                    }
+                }
+                params.clamp_adjustments();
+                log::info!(
+                    "gamma: {:.1}, brightness: {:.2}, contrast: {:.1}",
+                    params.gamma,
+                    params.brightness,
+                    params.contrast
+                );
+                blit_state.set_params(&wgpu_state.queue, params);
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                virtual_keycode:
+                                    Some(
+                                        key @ (VirtualKeyCode::Key1
+                                        | VirtualKeyCode::Key2
+                                        | VirtualKeyCode::Key3
+                                        | VirtualKeyCode::Key4),
+                                    ),
+                                state: ElementState::Released,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                correction_channel = match key {
+                    VirtualKeyCode::Key1 => Some(0),
+                    VirtualKeyCode::Key2 => Some(1),
+                    VirtualKeyCode::Key3 => Some(2),
+                    _ => None,
+                };
+                match correction_channel {
+                    Some(channel) => log::info!(
+                        "adjusting the {} channel's colour correction",
+                        ["red", "green", "blue"][channel]
+                    ),
+                    None => log::info!("adjusting every channel's colour correction"),
+                }
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                virtual_keycode:
+                                    Some(
+                                        key @ (VirtualKeyCode::Up
+                                        | VirtualKeyCode::Down
+                                        | VirtualKeyCode::PageUp
+                                        | VirtualKeyCode::PageDown
+                                        | VirtualKeyCode::Key0),
+                                    ),
+                                state: ElementState::Released,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                let view_index = view_index as usize;
+                let mut params = *blit_state.params();
+                let mut correction = params.eye_correction(view_index);
+                if key == VirtualKeyCode::Key0 {
+                    correction = ColorCorrection::default();
+                }
+                let channels = match correction_channel {
+                    Some(channel) => channel..channel + 1,
+                    None => 0..3,
+                };
+                for channel in channels {
+                    match key {
+                        VirtualKeyCode::Up => correction.gain[channel] += 0.02,
+                        VirtualKeyCode::Down => correction.gain[channel] -= 0.02,
+                        VirtualKeyCode::PageUp => correction.gamma[channel] += 0.05,
+                        VirtualKeyCode::PageDown => correction.gamma[channel] -= 0.05,
+                        _ => {}
+                    }
+                }
+                params.set_eye_correction(view_index, correction);
+                params.clamp_adjustments();
+                let correction = params.eye_correction(view_index);
+                log::info!(
+                    "view {view_index} colour correction: gain {:.2?}, gamma {:.2?}",
+                    correction.gain,
+                    correction.gamma
+                );
+                blit_state.set_params(&wgpu_state.queue, params);
+                // Kept in the config until it's saved with the S key.
+                #[cfg(feature = "xr")]
+                {
+                    xr_config.eye_color_correction[view_index] = correction;
+                }
+            }
+            #[cfg(feature = "xr")]
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                virtual_keycode: Some(VirtualKeyCode::S),
+                                state: ElementState::Released,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                // The correction is saved with the rest of the config if it's being saved, and
+                // otherwise written into the config file it was loaded from.
+                let saved = match (&args.save_config, &args.config) {
+                    (Some(path), _) => xr_config.save(path).map(|()| path),
+                    (None, Some(path)) => xr_config.save_eye_color_correction(path).map(|()| path),
+                    (None, None) => {
+                        Err(anyhow::anyhow!("there's no --config or --save-config file"))
+                    }
+                };
+                match saved {
+                    Ok(path) => log::info!("saved the colour correction to {path:?}"),
+                    Err(err) => log::warn!("couldn't save the colour correction: {err:#}"),
+                }
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                virtual_keycode: Some(VirtualKeyCode::G),
+                                state: ElementState::Released,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                show_gizmos = !show_gizmos;
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                virtual_keycode:
+                                    Some(
+                                        key @ (VirtualKeyCode::LBracket
+                                        | VirtualKeyCode::RBracket),
+                                    ),
+                                state: ElementState::Released,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                let delta = if key == VirtualKeyCode::LBracket {
+                    -1.0
+                } else {
+                    1.0
+                };
+                let width = (line_state.width() + delta)
+                    .clamp(*LINE_WIDTH_RANGE.start(), *LINE_WIDTH_RANGE.end());
+                log::info!("line width: {width}px");
+                line_state.set_width(&wgpu_state.queue, width);
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                virtual_keycode: Some(VirtualKeyCode::Escape),
+                                state: ElementState::Released,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                *control_flow = ControlFlow::Exit;
+            }
+            Event::MainEventsCleared => {
+                window.request_redraw();
+                cleared = true;
+            }
+            _ => {}
+        }
+
+        if !cleared {
+            return;
+        }
+
+        let time_since_start = start_time.elapsed().as_secs_f32();
+
+        #[cfg(feature = "xr")]
+        if let Some(xr_state) = &mut xr_state {
+            if xr_state.take_play_area_changed() && xr_config.show_boundary {
+                match xr_state.play_area() {
+                    Ok(play_area) => boundary_lines = self::boundary_lines(&play_area),
+                    Err(err) => log::warn!("couldn't query the play area: {err:#}"),
+                }
+            }
+        }
+
+        // The CPU work for the frame that doesn't depend on the XR frame's poses. This can be
+        // done before waiting for the XR frame, so that it overlaps with the compositor
+        // displaying the previous frame.
+        let simulate = |main_state: &mut MainState, line_state: &mut LineState| {
+            main_state.instances[0].rotation =
+                scene_rotation * Quat::from_rotation_y(time_since_start / std::f32::consts::PI);
+            main_state.mark_dirty(0);
+
+            // The gizmos and labels are built from the instances' transforms from the previous
+            // frame, as the hand transforms are only available after the headset blit has been
+            // encoded.
+            let mut lines = vec![];
+            #[cfg(feature = "xr")]
+            lines.extend_from_slice(&boundary_lines);
+            if show_gizmos {
+                lines.extend(Line::axes(Vec3::ZERO, Quat::IDENTITY, 1.0));
+                for instance in main_state.instances.iter().filter(|i| i.visible) {
+                    lines.extend(Line::axes(
+                        instance.translation,
+                        instance.rotation,
+                        instance.scale.max_element() * 1.5,
+                    ));
+                }
+            }
+            let mut labels = vec![];
+            if show_labels {
+                labels.extend(
+                    main_state
+                        .instances
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, instance)| instance.visible)
+                        .map(|(index, instance)| {
+                            let t = instance.translation;
+                            TextLabel::world(
+                                t,
+                                format!("#{index}\n{:.2} {:.2} {:.2}", t.x, t.y, t.z),
+                                vec4(1.0, 1.0, 0.0, 1.0),
+                            )
+                        }),
+                );
+            }
+            if show_eye_letters {
+                // Each letter is only visible to the eye it names; if you can see an "L" with
+                // your right eye, the views have been swapped somewhere.
+                labels.extend(
+                    ["L", "R"]
+                        .into_iter()
+                        .enumerate()
+                        .map(|(view, letter)| TextLabel {
+                            scale: 24.0,
+                            view_mask: 1 << view,
+                            ..TextLabel::screen(Vec2::ZERO, letter, vec4(1.0, 0.0, 1.0, 1.0))
+                        }),
+                );
+            }
+            line_state.upload_lines(&wgpu_state.device, &wgpu_state.queue, &lines);
+            labels
+        };
+        #[cfg(feature = "xr")]
+        let simulate_before_wait = xr_config.simulate_before_wait;
+        #[cfg(not(feature = "xr"))]
+        let simulate_before_wait = false;
+        let early_labels =
+            simulate_before_wait.then(|| simulate(&mut main_state, &mut line_state));
+
+        #[cfg(feature = "xr")]
+        let xr_frame_state = if args.mode == Mode::Xr {
+            xr_state.as_mut().and_then(|x| x.pre_frame().unwrap())
+        } else {
+            None
+        };
+        #[cfg(feature = "xr")]
+        if xr_state.as_ref().is_some_and(xr::XrState::is_exiting) {
+            log::info!("the XR session has ended; exiting");
+            *control_flow = ControlFlow::Exit;
+            return;
+        }
+        // The frame's CPU time is measured from here, as waiting for the XR frame isn't work.
+        let frame_start = std::time::Instant::now();
+        #[cfg_attr(not(feature = "xr"), allow(unused_mut))]
+        let mut labels = match early_labels {
+            Some(labels) => labels,
+            None => simulate(&mut main_state, &mut line_state),
+        };
+        if let (true, Some(timing)) = (show_hud, frame_timings.latest()) {
+            let ms = |duration: std::time::Duration| duration.as_secs_f32() * 1000.0;
+            labels.push(TextLabel::screen(
+                Vec2::new(0.0, 0.4),
+                format!(
+                    "cpu {:.2} ms | gpu {} | frame {:.2} ms{}",
+                    ms(timing.cpu_time),
+                    timing
+                        .gpu_time
+                        .map_or("n/a".to_string(), |gpu| format!("{:.2} ms", ms(gpu))),
+                    ms(timing.frame_time),
+                    if timing.late { " | late" } else { "" }
+                ),
+                vec4(1.0, 1.0, 1.0, 1.0),
+            ));
+        }
+        #[cfg(feature = "xr")]
+        if let (true, Some(xr_state)) = (show_hud, &xr_state) {
+            let profiles = xr_state.interaction_profiles().clone().map(|p| {
+                p.map(|p| p.trim_start_matches("/interaction_profiles/").to_string())
+                    .unwrap_or_else(|| "none".to_string())
+            });
+            labels.push(TextLabel::screen(
+                Vec2::new(0.0, 0.6),
+                format!("left: {}\nright: {}", profiles[0], profiles[1]),
+                vec4(1.0, 1.0, 1.0, 1.0),
+            ));
+        }
+        debug_text_state.upload_labels(&wgpu_state.device, &wgpu_state.queue, &labels);
+
+        // On frames that the compositor is reprojecting, the render target keeps its contents
+        // from the previous frame, so that the window still has something to show.
+        #[cfg(feature = "xr")]
+        let render_scene =
+            !(xr_frame_state.is_some() && xr_state.as_ref().is_some_and(|x| x.is_reprojecting()));
+        #[cfg(not(feature = "xr"))]
+        let render_scene = true;
+
+        let mut encoder = wgpu_state
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        if let Some(gpu_timer) = &mut gpu_timer {
+            gpu_timer.begin(&mut encoder);
+        }
+
+        // When the mirror is synced to the headset, frames that the headset won't show (or
+        // that it reprojects, which leaves the render target as it was) aren't presented.
+        #[cfg(feature = "xr")]
+        let present_mirror = args.mirror_sync == MirrorSync::Immediate
+            || (render_scene && xr_frame_state.is_none_or(|s| s.should_render));
+        #[cfg(not(feature = "xr"))]
+        let present_mirror = true;
+        let frame = present_mirror.then(|| {
+            surface
+                .get_current_texture()
+                .expect("Failed to acquire next swap chain texture")
+        });
+        let mirror_view = frame.as_ref().map(|frame| {
+            frame
+                .texture
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        });
+
+        // The passes that render to the render target (and the window), which can read it
+        // afterwards. The render target is modified in place, but each version of its contents
+        // is a separate resource in the graph.
+        let mut graph = RenderGraph::default();
+        if render_scene {
+            graph.add_pass("main", &[], &["scene", "depth"], |encoder| {
+                // A multiview pass clears every layer, so clearing only some of them takes a
+                // pass per layer, after which the main pass loads them all.
+                let mut load = main_pass_load;
+                if load == LoadBehavior::Clear
+                    && (0..view_count).any(|layer| !args.clear_layers.contains(&layer))
+                {
+                    for &layer in args.clear_layers.iter().filter(|&&layer| layer < view_count) {
+                        main_state.encode_clear_pass(
+                            encoder,
+                            &DrawTargets {
+                                color: &rt_texture.layer_view(layer),
+                                depth: &depth_texture.layer_view(layer),
+                                motion_vectors: motion_vector_texture
+                                    .as_ref()
+                                    .map(|texture| texture.layer_view(layer))
+                                    .as_ref(),
+                            },
+                        );
+                    }
+                    load = LoadBehavior::Load;
+                }
+                main_state.encode_draw_pass(
+                    encoder,
+                    &DrawTargets {
+                        color: rt_texture.view(),
+                        depth: depth_texture.view(),
+                        motion_vectors: motion_vector_texture.as_ref().map(Texture::view),
+                    },
+                    load,
+                    &region,
+                    &triangle_mesh,
+                    camera_state.bind_group(),
+                )
+            });
+            let mut color = "scene";
+            // The overdraw counts aren't colours, so there's no sky behind them, and they
+            // aren't darkened.
+            if let (Some(environment_state), false) =
+                (&environment_state, main_state.show_overdraw)
+            {
+                graph.add_pass("environment", &[color, "depth"], &["sky"], |encoder| {
+                    environment_state.encode_draw_pass(encoder, rt_texture.view(), &region)
+                });
+                color = "sky";
+            }
+            if let (Some(ssao_state), false) = (&ssao_state, main_state.show_overdraw) {
+                graph.add_pass("ssao", &[color, "depth"], &["occluded"], |encoder| {
+                    ssao_state.encode_draw_pass(encoder, rt_texture.view(), &region)
+                });
+                color = "occluded";
+            }
+            graph.add_pass("lines", &[color, "depth"], &["lines"], |encoder| {
+                line_state.encode_draw_pass(
+                    encoder,
+                    rt_texture.view(),
+                    depth_texture.view(),
+                    &region,
+                    camera_state.bind_group(),
+                )
+            });
+            graph.add_pass("debug_text", &["lines"], &["frame"], |encoder| {
+                debug_text_state.encode_draw_pass(
+                    encoder,
+                    rt_texture.view(),
+                    &region,
+                    camera_state.bind_group(),
+                )
+            });
+        } else {
+            // The render target still holds the previous frame.
+            graph.import("frame");
+        }
+        if let Some(view) = &mirror_view {
+            graph.add_pass("mirror", &["frame"], &["window"], |encoder| {
+                blit_state.encode_draw_pass(encoder, view, Some(view_index), LoadBehavior::Clear)
+            });
+        }
+        graph
+            .execute(&mut encoder)
+            .expect("the render graph is invalid");
+
+        // If the XR frame fails to render, the frame is still ended, but with a solid-colour
+        // fallback layer in place of the scene, so that the headset doesn't freeze.
+        #[cfg(feature = "xr")]
+        let mut xr_frame_failed = false;
+        #[cfg(feature = "xr")]
+        let pfd = if let Some(simulated_headset) = &simulated_headset {
+            Some(simulated_headset.post_frame(time_since_start))
+        } else {
+            xr_state
+                .as_mut()
+                .zip(xr_frame_state)
+                .and_then(|(xr_state, xr_frame_state)| {
+                    match xr_state.post_frame(
+                        &wgpu_state.device,
+                        xr_frame_state,
+                        &mut encoder,
+                        &blit_state,
+                    ) {
+                        Ok(pfd) => Some(pfd),
+                        Err(err) => {
+                            log::error!("Failed to render XR frame: {err:#}");
+                            xr_frame_failed = true;
+                            None
+                        }
+                    }
+                })
+        };
+
+        // Only follow the controllers while we have input focus, and hide or rest the ones that
+        // aren't being tracked. Reprojected frames don't locate the controllers, so they're left
+        // as-is.
+        #[cfg(feature = "xr")]
+        if let (Some(pfd), true) = (
+            &pfd,
+            xr_state
+                .as_ref()
+                .is_none_or(|x| x.is_focused() && !x.is_reprojecting()),
+        ) {
+            for (hand_index, hand) in [pfd.left_hand, pfd.right_hand].into_iter().enumerate() {
+                let index = hand_index + 1;
+                let pose = xr_config.hand_pose(hand_index, hand, &axis_convention);
+                main_state.set_visible(index, pose.is_some());
+                if let Some((translation, rotation)) = pose {
+                    let instance = &mut main_state.instances[index];
+                    (instance.translation, instance.rotation) = (translation, rotation);
+                }
+            }
+        }
+        // While the application is shown without input focus (e.g. under the runtime's system
+        // menu), its controllers aren't the ones the user is pointing with, so they're hidden.
+        #[cfg(feature = "xr")]
+        if xr_state
+            .as_ref()
+            .is_some_and(|x| x.is_visible() && !x.is_focused())
+        {
+            for index in [1, 2] {
+                main_state.set_visible(index, false);
+            }
+        }
+        main_state.upload_instances(&wgpu_state.device, &wgpu_state.queue);
+
+        // The shaders' time follows the runtime's predicted display time where there is one, so
+        // that animation matches when the frame will be shown, like the poses.
+        let shader_time = {
+            let elapsed = start_time.elapsed().as_secs_f64();
+            #[cfg(feature = "xr")]
+            match xr_frame_state {
+                Some(xr_frame_state) => {
+                    let time = xr_frame_state.predicted_display_time;
+                    let (origin, origin_elapsed) = *xr_time_origin.get_or_insert((time, elapsed));
+                    origin_elapsed + (time.as_nanos() - origin.as_nanos()) as f64 / 1e9
+                }
+                None => elapsed,
+            }
+            #[cfg(not(feature = "xr"))]
+            elapsed
+        };
+        camera_state.write_time(&wgpu_state.queue, shader_time);
+        let matrices = {
+            #[cfg(feature = "xr")]
+            match &pfd {
+                Some(pfd) => {
+                    let mut matrices = camera_state
+                        .data
+                        .to_view_proj_matrices_with_xr_views(&pfd.views, &axis_convention);
+                    // Views rendered at a reduced resolution only use a corner of the
+                    // render target.
+                    let view_scales = xr_state.as_ref().map_or([1.0; 2], |x| x.view_scales());
+                    for (matrix, scale) in matrices.chunks_mut(16).zip(view_scales) {
+                        if scale != 1.0 {
+                            let scaled = camera::clip_to_top_left(scale)
+                                * glam::Mat4::from_cols_slice(matrix);
+                            matrix.copy_from_slice(&scaled.to_cols_array());
+                        }
+                    }
+                    matrices
+                }
+                None => camera_state.data.to_view_proj_matrices(),
+            }
+            #[cfg(not(feature = "xr"))]
+            camera_state.data.to_view_proj_matrices()
+        };
+        if let Some(environment_state) = &environment_state {
+            environment_state.set_view_projections(&wgpu_state.queue, &matrices);
+        }
+        camera_state.write_matrices(&wgpu_state.queue, matrices);
+
+        if let Some(gpu_timer) = &mut gpu_timer {
+            gpu_timer.end(&mut encoder);
+        }
+        wgpu_state.queue.submit(Some(encoder.finish()));
+        if let Some(gpu_timer) = &mut gpu_timer {
+            gpu_timer.submitted();
+        }
+        let cpu_time = frame_start.elapsed();
+
+        #[cfg(feature = "xr")]
+        if let (Some(xr_state), Some(xr_frame_state)) = (xr_state.as_mut(), xr_frame_state) {
+            if xr_frame_state.should_render {
+                let reticle_layer = reticle.as_ref().map(reticle::Reticle::composition_layer);
+                let extra_layers: Vec<&openxr::CompositionLayerBase<openxr::Vulkan>> =
+                    reticle_layer.iter().map(|layer| &**layer).collect();
+                let submitted = match &pfd {
+                    Some(pfd) if !xr_frame_failed => xr_state
+                        .post_queue_submit(xr_frame_state, &pfd.views, &extra_layers)
+                        .map_err(|err| log::error!("Failed to submit XR frame: {err:#}"))
+                        .is_ok(),
+                    _ => false,
+                };
+                if !submitted {
+                    if let Err(err) = xr_state.submit_fallback(xr_frame_state) {
+                        log::error!("Failed to submit XR fallback layer: {err:#}");
+                    }
+                }
+            }
+        }
+
+        if let Some(frame) = frame {
+            frame.present();
+        }
+
+        let frame_period = {
+            #[cfg(feature = "xr")]
+            match xr_frame_state {
+                Some(xr_frame_state) => std::time::Duration::from_nanos(
+                    xr_frame_state.predicted_display_period.as_nanos() as u64,
+                ),
+                None => DESKTOP_FRAME_PERIOD,
+            }
+            #[cfg(not(feature = "xr"))]
+            DESKTOP_FRAME_PERIOD
+        };
+        #[cfg(feature = "xr")]
+        let display_period = xr_frame_state.map(|_| frame_period);
+        #[cfg(not(feature = "xr"))]
+        let display_period = None;
+
+        // Run deferred work if this frame has time to spare.
+        while let Some(task) = deferred_work.next(frame_start.elapsed(), frame_period) {
+            match task {
+                DeferredTask::CapturePanorama => {
+                    let eye_positions = {
+                        #[cfg(feature = "xr")]
+                        match &pfd {
+                            Some(pfd) if !pfd.views.is_empty() => {
+                                camera_state
+                                    .data
+                                    .eye_positions_with_xr_views(&pfd.views, &axis_convention)
+                            }
+                            _ => camera_state.data.eye_positions(),
+                        }
+                        #[cfg(not(feature = "xr"))]
+                        camera_state.data.eye_positions()
+                    };
+                    let path = PathBuf::from(format!(
+                        "panorama-{}.png",
+                        std::time::SystemTime::now()
+                            .duration_since(std::time::UNIX_EPOCH)
+                            .unwrap_or_default()
+                            .as_secs()
+                    ));
+                    let result = panorama_state.capture(
+                        &wgpu_state.device,
+                        &wgpu_state.queue,
+                        &camera_state,
+                        eye_positions,
+                        |encoder, color_view, depth_view, region, camera_bind_group| {
+                            main_state.encode_draw_pass(
+                                encoder,
+                                &DrawTargets {
+                                    color: color_view,
+                                    depth: depth_view,
+                                    motion_vectors: None,
+                                },
+                                LoadBehavior::Clear,
+                                region,
+                                &triangle_mesh,
+                                camera_bind_group,
+                            )
+                        },
+                        &path,
+                    );
+                    match result {
+                        Ok(()) => log::info!("saved panorama to {path:?}"),
+                        Err(err) => log::error!("failed to capture panorama: {err:?}"),
+                    }
+                }
+            }
+        }
+        deferred_work.end_frame();
+
+        let frame_timing = FrameTiming {
+            cpu_time,
+            gpu_time: gpu_timer
+                .as_mut()
+                .and_then(|gpu_timer| gpu_timer.latest(&wgpu_state.device)),
+            frame_time: last_frame_end.elapsed(),
+            display_period,
+            late: cpu_time > frame_period,
+        };
+        frame_stats.record(frame_timing.frame_time);
+        frame_timings.push(frame_timing);
+        last_frame_end = std::time::Instant::now();
+        if title_timer.elapsed().as_millis() > 100 {
+            let mut fps = format!("{:.02} FPS", frame_stats.average_fps().unwrap_or_default());
+            if args.fps_percentiles {
+                if let Some([median, p99]) = frame_stats.percentiles([50.0, 99.0]) {
+                    fps += &format!(
+                        " (1% low {:.02}) | {:.02}/{:.02} ms median/p99",
+                        1.0 / p99.as_secs_f32(),
+                        median.as_secs_f32() * 1000.0,
+                        p99.as_secs_f32() * 1000.0,
+                    );
+                }
+            }
+            window.set_title(&format!(
+                "wgpu-openxr-example: {fps} | {} view",
+                match (view_count, view_index) {
+                    (1, _) => "mono",
+                    (_, 0) => "left",
+                    _ => "right",
+                }
+            ));
+
+            title_timer = std::time::Instant::now();
+        }
+    });
+}
+
+/// Shows a spinner in front of the user while the projection swapchain is being created.
+#[cfg(feature = "xr")]
+fn add_loading_spinner(
+    device: &wgpu::Device,
+    preprocessor: &wgsl::Preprocessor,
+    xr_state: &mut xr::XrState,
+) -> anyhow::Result<()> {
+    let spinner_state =
+        spinner_state::SpinnerState::new(device, preprocessor, xr::WGPU_COLOR_FORMAT);
+    xr_state.set_loading_layer(
+        device,
+        xr::QuadLayerDesc {
+            width: 128,
+            height: 128,
+            // There's no head pose to follow yet, so place it at roughly eye height, a metre
+            // in front of the stage origin.
+            pose: openxr::Posef {
+                orientation: openxr::Quaternionf::IDENTITY,
+                position: openxr::Vector3f {
+                    x: 0.0,
+                    y: 1.5,
+                    z: -1.0,
+                },
+            },
+            size: openxr::Extent2Df {
+                width: 0.25,
+                height: 0.25,
+            },
+            static_image: false,
+        },
+        Box::new(move |encoder, view, time| {
+            // Wrap the time to keep it within the precision of an `f32`.
+            let time = (time.as_nanos() % 1_000_000_000_000) as f64 / 1e9;
+            spinner_state.encode_draw_pass(encoder, view, time as f32);
+        }),
+    )
+}
+
+/// Moves and turns the scene's instances, which are placed relative to the origin, to be
+/// relative to `center` instead. The hands (instances 1 and 2) are left alone, as they follow
+/// the controllers.
+#[cfg(feature = "xr")]
+fn center_scene(instances: &mut [Instance], (translation, rotation): (Vec3, Quat)) {
+    for (_, instance) in instances
+        .iter_mut()
+        .enumerate()
+        .filter(|(index, _)| !(1..=2).contains(index))
+    {
+        instance.translation = translation + rotation * instance.translation;
+        instance.rotation = rotation * instance.rotation;
+    }
+}
+
+/// The outline of the play area's boundary, as lines on the floor. They're in the scene's
+/// coordinate system, so they stay in place as the user moves around.
+#[cfg(feature = "xr")]
+fn boundary_lines(play_area: &xr::PlayArea) -> Vec<Line> {
+    let Some(corners) = play_area.outline() else {
+        return vec![];
+    };
+    (0..corners.len())
+        .map(|i| {
+            Line::new(
+                corners[i],
+                corners[(i + 1) % corners.len()],
+                vec4(0.0, 0.8, 1.0, 1.0),
+            )
+        })
+        .collect()
+}
+
+/// Adds a static quad layer that lies flat on the floor at the origin of the stage.
+#[cfg(feature = "xr")]
+fn add_origin_marker(device: &wgpu::Device, xr_state: &mut xr::XrState) -> anyhow::Result<()> {
+    let half_sqrt = std::f32::consts::FRAC_1_SQRT_2;
+    xr_state.add_quad_layer(
+        device,
+        xr::QuadLayerDesc {
+            width: 16,
+            height: 16,
+            // Rotate the quad by -90 degrees around X, so that it faces upwards.
+            pose: openxr::Posef {
+                orientation: openxr::Quaternionf {
+                    x: -half_sqrt,
+                    y: 0.0,
+                    z: 0.0,
+                    w: half_sqrt,
+                },
+                position: openxr::Vector3f::default(),
+            },
+            size: openxr::Extent2Df {
+                width: 0.5,
+                height: 0.5,
+            },
+            static_image: true,
+        },
+        Box::new(|encoder, view, _| {
+            encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Origin Marker"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color {
+                            r: 0.0,
+                            g: 0.5,
+                            b: 0.5,
+                            a: 0.5,
+                        }),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+        }),
+    )
+}
+
+/// Shows the equirectangular PNG image at `path` behind the scene.
+#[cfg(feature = "xr")]
+fn add_background_layer(
+    device: &wgpu::Device,
+    xr_state: &mut xr::XrState,
+    path: &std::path::Path,
+    radius: f32,
+) -> anyhow::Result<()> {
+    let bytes = std::fs::read(path).with_context(|| format!("failed to read {path:?}"))?;
+    let (width, height, rgba) =
+        decode_png_rgba(&bytes).with_context(|| format!("failed to decode {path:?}"))?;
+    xr_state.set_background_layer(
+        device,
+        xr::EquirectLayerDesc {
+            width,
+            height,
+            rgba,
+            radius,
+        },
+    )
+}
+
+/// Decodes the window icon, which is embedded in the executable.
+fn load_window_icon() -> anyhow::Result<winit::window::Icon> {
+    let (width, height, rgba) = decode_png_rgba(include_bytes!("../assets/icon.png"))?;
+    Ok(winit::window::Icon::from_rgba(rgba, width, height)?)
+}
+
+/// Decodes an 8-bit RGB or RGBA PNG image into its width, height and RGBA pixels.
+fn decode_png_rgba(bytes: &[u8]) -> anyhow::Result<(u32, u32, Vec<u8>)> {
+    let mut decoder = png::Decoder::new(bytes);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels)?;
+    pixels.truncate(info.buffer_size());
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => pixels,
+        png::ColorType::Rgb => pixels
+            .chunks_exact(3)
+            .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], u8::MAX])
+            .collect(),
+        color_type => anyhow::bail!("expected an RGB or RGBA image, got {color_type:?}"),
+    };
+    Ok((info.width, info.height, rgba))
+}
+
+#[cfg(feature = "xr")]
+fn parse_vec3(value: &str) -> anyhow::Result<Vec3> {
+    let components = value
+        .split(',')
+        .map(|c| c.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()?;
+    match components.as_slice() {
+        [x, y, z] => Ok(vec3(*x, *y, *z)),
+        _ => anyhow::bail!("expected three comma-separated components, got {value:?}"),
+    }
+}
+
+fn parse_rect(value: &str) -> anyhow::Result<Rect> {
+    let components = value
+        .split(',')
+        .map(|c| c.trim().parse::<u32>())
+        .collect::<Result<Vec<_>, _>>()?;
+    match components.as_slice() {
+        [x, y, width, height] => Ok(Rect {
+            x: *x,
+            y: *y,
+            width: *width,
+            height: *height,
+        }),
+        _ => anyhow::bail!("expected four comma-separated components, got {value:?}"),
+    }
+}
+
+/// The region of the render target that the scene is drawn to, from `--viewport` and
+/// `--scissor`. Either defaults to the whole target, as does the region if they don't fit
+/// within the target (e.g. after the window is made smaller).
+fn scene_region(
+    viewport: Option<Rect>,
+    scissor: Option<Rect>,
+    width: u32,
+    height: u32,
+) -> RenderRegion {
+    let full = Rect {
+        x: 0,
+        y: 0,
+        width,
+        height,
+    };
+    RenderRegion::new(
+        width,
+        height,
+        viewport.unwrap_or(full),
+        scissor.unwrap_or(full),
+    )
+    .unwrap_or_else(|err| {
+        log::warn!("{err:#}; drawing the scene to the whole render target");
+        RenderRegion::full(width, height)
+    })
+}
+
+fn parse_depth_bias(value: &str) -> anyhow::Result<wgpu::DepthBiasState> {
+    match value.split_once(',') {
+        Some((constant, slope)) => Ok(wgpu::DepthBiasState {
+            constant: constant.trim().parse()?,
+            slope_scale: slope.trim().parse()?,
+            clamp: 0.0,
+        }),
+        None => anyhow::bail!("expected `constant,slope`, got {value:?}"),
+    }
 }
 
 fn create_wgpu_state(
@@ -367,12 +2215,13 @@ fn create_wgpu_state(
             compatible_surface: Some(&surface),
         }))
         .context("Failed to find an appropriate adapter")?;
+    check_multiview_support(adapter.get_info().backend, adapter.features())?;
 
     // Create the logical device and command queue
     let (device, queue) = futures::executor::block_on(adapter.request_device(
         &wgpu::DeviceDescriptor {
             label: None,
-            features: wgpu_features,
+            features: wgpu_features | (adapter.features() & OPTIONAL_FEATURES),
             limits: wgpu_limits,
         },
         None,
@@ -1,16 +1,25 @@
-use glam::{Mat4, Quat, Vec3};
-use std::{borrow::Cow, num::NonZeroU32};
+use glam::{Mat3, Mat4, Quat, Vec3, Vec4};
+use std::{borrow::Cow, num::NonZeroU32, ops::Range};
 use wgpu::util::DeviceExt;
 
 use crate::{
     camera::CameraState,
-    types::{Vertex, DEPTH_FORMAT, VIEW_COUNT},
+    dirty_ranges::DirtyRanges,
+    growable_buffer::GrowableBuffer,
+    mesh::Mesh,
+    types::{has_stencil, LoadBehavior, RenderRegion, Vertex},
 };
 
+#[derive(Clone)]
 pub struct Instance {
     pub translation: Vec3,
     pub rotation: Quat,
     pub scale: Vec3,
+    /// Multiplied with the colours of the instance's vertices.
+    pub color: Vec4,
+    /// Hidden instances keep their slot in the instance buffer, but are uploaded with a
+    /// zero-scale transform so that they don't produce any fragments.
+    pub visible: bool,
 }
 impl Instance {
     pub fn new(translation: Vec3, rotation: Quat, scale: Vec3) -> Self {
@@ -18,27 +27,270 @@ impl Instance {
             translation,
             rotation,
             scale,
+            color: Vec4::ONE,
+            visible: true,
         }
     }
 
-    fn to_cols_array(&self) -> [f32; 16] {
+    fn placement(&self) -> Placement {
+        Placement {
+            translation: self.translation,
+            rotation: self.rotation,
+            scale: self.scale,
+            visible: self.visible,
+        }
+    }
+
+    /// `previous` is where the instance was as of the previous upload, for motion vectors.
+    fn to_data(&self, previous: &Placement) -> InstanceData {
+        let previous_model_matrix = previous.model_matrix().to_cols_array();
+        if !self.visible {
+            // Every vertex is transformed to the same point, so the rasterizer discards the
+            // resulting degenerate triangles.
+            return InstanceData {
+                model_matrix: [0.0; 16],
+                normal_matrix: [0.0; 9],
+                color: [0.0; 4],
+                previous_model_matrix,
+            };
+        }
+        let model_matrix = self.placement().model_matrix();
+        InstanceData {
+            model_matrix: model_matrix.to_cols_array(),
+            normal_matrix: Self::normal_matrix(model_matrix).to_cols_array(),
+            color: self.color.to_array(),
+            previous_model_matrix,
+        }
+    }
+
+    fn to_compact_data(&self, previous: &Placement) -> CompactInstanceData {
+        let [rotation, translation, scale] = self.placement().compact();
+        let [previous_rotation, previous_translation, previous_scale] = previous.compact();
+        CompactInstanceData {
+            rotation,
+            translation,
+            scale: [scale[0], scale[1], scale[2]],
+            color: if self.visible {
+                self.color.to_array()
+            } else {
+                [0.0; 4]
+            },
+            previous_rotation,
+            previous_translation,
+            previous_scale: [previous_scale[0], previous_scale[1], previous_scale[2]],
+        }
+    }
+
+    /// Normals have to be transformed by the inverse-transpose of the model matrix, as the
+    /// model matrix itself would skew them under non-uniform scale.
+    fn normal_matrix(model_matrix: Mat4) -> Mat3 {
+        Mat3::from_mat4(model_matrix).inverse().transpose()
+    }
+}
+
+/// An instance's transform and visibility as of an upload, which is all that motion vectors
+/// need from the previous frame.
+#[derive(Copy, Clone, PartialEq)]
+struct Placement {
+    translation: Vec3,
+    rotation: Quat,
+    scale: Vec3,
+    visible: bool,
+}
+impl Placement {
+    /// The zero matrix for hidden instances.
+    fn model_matrix(&self) -> Mat4 {
+        if !self.visible {
+            return Mat4::ZERO;
+        }
         Mat4::from(glam::Affine3A::from_scale_rotation_translation(
             self.scale,
             self.rotation,
             self.translation,
         ))
-        .to_cols_array()
     }
+
+    /// The rotation, translation and scale for [CompactInstanceData]. The translation has a W
+    /// of 1, or everything is zero for hidden instances, which makes the model matrix that the
+    /// vertex shader builds from them the zero matrix.
+    fn compact(&self) -> [[f32; 4]; 3] {
+        if !self.visible {
+            return [[0.0; 4]; 3];
+        }
+        [
+            self.rotation.to_array(),
+            self.translation.extend(1.0).to_array(),
+            self.scale.extend(0.0).to_array(),
+        ]
+    }
+}
+
+/// How each instance's transform is uploaded to the GPU.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum InstanceFormat {
+    /// The model and normal matrices, computed on the CPU ([InstanceData]).
+    #[default]
+    Matrices,
+    /// The translation, rotation quaternion and scale, from which the vertex shader builds the
+    /// matrices ([CompactInstanceData]). This uploads a little over half as much data per
+    /// instance, at the cost of some vertex shader work, which pays off when many instances
+    /// change every frame.
+    Compact,
+}
+impl InstanceFormat {
+    /// The size of each instance in the instance buffer.
+    fn stride(self) -> usize {
+        match self {
+            Self::Matrices => std::mem::size_of::<InstanceData>(),
+            Self::Compact => std::mem::size_of::<CompactInstanceData>(),
+        }
+    }
+
+    fn vertex_entry_point(self) -> &'static str {
+        match self {
+            Self::Matrices => "vs_main",
+            Self::Compact => "vs_main_compact",
+        }
+    }
+
+    /// The vertex attributes of the instance buffer, which start at shader location 3.
+    fn attributes(self) -> Vec<wgpu::VertexAttribute> {
+        use wgpu::VertexFormat::{Float32x3, Float32x4};
+        let formats = match self {
+            // The model matrix's four columns, followed by the normal matrix's three, the
+            // colour, and the previous model matrix's four columns.
+            Self::Matrices => [&[Float32x4; 4][..], &[Float32x3; 3], &[Float32x4; 1 + 4]].concat(),
+            // The rotation, translation, scale and colour, followed by the previous rotation,
+            // translation and scale.
+            Self::Compact => vec![
+                Float32x4, Float32x4, Float32x3, Float32x4, Float32x4, Float32x4, Float32x3,
+            ],
+        };
+        let mut offset = 0;
+        (3..)
+            .zip(formats)
+            .map(|(shader_location, format)| {
+                let attribute = wgpu::VertexAttribute {
+                    offset,
+                    shader_location,
+                    format,
+                };
+                offset += format.size();
+                attribute
+            })
+            .collect()
+    }
+}
+
+/// The per-instance data uploaded to the GPU with [InstanceFormat::Matrices]. Must match
+/// `InstanceInput` in `main.wgsl`.
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct InstanceData {
+    model_matrix: [f32; 16],
+    normal_matrix: [f32; 9],
+    color: [f32; 4],
+    /// The model matrix as of the previous upload, for motion vectors.
+    previous_model_matrix: [f32; 16],
+}
+
+/// The per-instance data uploaded to the GPU with [InstanceFormat::Compact]. Must match
+/// `CompactInstanceInput` in `main.wgsl`.
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CompactInstanceData {
+    rotation: [f32; 4],
+    translation: [f32; 4],
+    scale: [f32; 3],
+    color: [f32; 4],
+    /// The transform as of the previous upload, for motion vectors.
+    previous_rotation: [f32; 4],
+    previous_translation: [f32; 4],
+    previous_scale: [f32; 3],
+}
+
+/// The ambient light used when the environment's lighting isn't known.
+const DEFAULT_AMBIENT_LIGHT: Vec3 = Vec3::splat(0.3);
+
+/// The parameters for shading the main pass. Must match `Shading` in `main.wgsl`.
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShadingParams {
+    ambient_light: [f32; 3],
+    /// If non-zero, fragments are coloured by their distance from the viewer instead.
+    depth_shading: u32,
+    /// The distances at which the depth shading's ramp starts and ends.
+    depth_range: [f32; 2],
+    _padding: [f32; 2],
+}
+
+/// The format of the motion vector target; see [MainState::enable_motion_vectors].
+pub const MOTION_VECTOR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg16Float;
+
+/// The textures that [MainState::encode_draw_pass] renders into. All of them must have a layer
+/// per view, and be the same size.
+pub struct DrawTargets<'a> {
+    /// In the format the state was created with.
+    pub color: &'a wgpu::TextureView,
+    /// In the depth format the state was created with.
+    pub depth: &'a wgpu::TextureView,
+    /// In [MOTION_VECTOR_FORMAT]. Can only be used once motion vectors have been enabled.
+    pub motion_vectors: Option<&'a wgpu::TextureView>,
+}
+
+/// Which of the main pipelines to create.
+#[derive(Copy, Clone)]
+struct PipelineVariant {
+    /// If true, every fragment is additively blended into the render target regardless of
+    /// depth, so that the render target counts how many times each pixel was shaded.
+    overdraw: bool,
+    /// If true, the pipeline has a second target for motion vectors.
+    motion_vectors: bool,
+    instance_format: InstanceFormat,
+    depth_bias: wgpu::DepthBiasState,
+    /// The number of views rendered with multiview.
+    view_count: u32,
 }
 
 pub struct MainState {
-    #[allow(dead_code)]
     shader: wgpu::ShaderModule,
-    #[allow(dead_code)]
     pipeline_layout: wgpu::PipelineLayout,
     pipeline: wgpu::RenderPipeline,
+    overdraw_pipeline: wgpu::RenderPipeline,
+    /// The scene and overdraw pipelines with a motion vector target, once enabled.
+    motion_vector_pipelines: Option<[wgpu::RenderPipeline; 2]>,
+    /// Whether to render the overdraw heatmap input instead of the scene.
+    pub show_overdraw: bool,
+    /// The colour the render target is cleared to. This is the scene's background, unless it's
+    /// transparent, in which case whatever the render target is composited over shows through
+    /// (e.g. an XR background layer).
+    pub clear_color: wgpu::Color,
+    /// Geometry is only drawn where the stencil buffer equals this value. The stencil is
+    /// cleared to zero at the start of the pass, so nothing is masked out unless another pass
+    /// writes to the stencil first (e.g. to cut out a portal). Ignored if the depth format has
+    /// no stencil aspect.
+    pub stencil_reference: u32,
+    swapchain_format: wgpu::TextureFormat,
+    depth_format: wgpu::TextureFormat,
+    primitive: wgpu::PrimitiveState,
+    depth_bias: wgpu::DepthBiasState,
+    view_count: u32,
+    shading: ShadingParams,
+    shading_buffer: wgpu::Buffer,
+    shading_bind_group: wgpu::BindGroup,
     pub instances: Vec<Instance>,
-    instance_buffer: wgpu::Buffer,
+    instance_format: InstanceFormat,
+    instance_buffer: GrowableBuffer,
+    /// The instances that have changed since they were last uploaded.
+    dirty_instances: DirtyRanges,
+    /// The number of instances in the last upload, or `None` if everything must be uploaded.
+    uploaded_instance_count: Option<usize>,
+    /// The placements of the instances as of the last upload.
+    previous_placements: Vec<Placement>,
+    /// The instances whose placements changed in the last upload. They have to be uploaded
+    /// again even if they haven't changed since, so that their previous placement catches up
+    /// with them.
+    moved_instances: DirtyRanges,
 }
 impl MainState {
     pub fn new(
@@ -46,25 +298,50 @@ impl MainState {
         preprocessor: &crate::wgsl::Preprocessor,
         camera_state: &CameraState,
         swapchain_format: wgpu::TextureFormat,
+        depth_format: wgpu::TextureFormat,
+        primitive: wgpu::PrimitiveState,
         instances: Vec<Instance>,
     ) -> Self {
-        let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Index Buffer"),
-            contents: bytemuck::cast_slice(&Self::instances_to_data(&instances)),
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-        });
-        let instance_buffer_layout = wgpu::VertexBufferLayout {
-            array_stride: (std::mem::size_of::<f32>() * 4 * 4) as _,
-            step_mode: wgpu::VertexStepMode::Instance,
-            attributes: &(0..4)
-                .into_iter()
-                .map(|i| wgpu::VertexAttribute {
-                    offset: (i * std::mem::size_of::<f32>() * 4) as _,
-                    shader_location: 2 + i as u32,
-                    format: wgpu::VertexFormat::Float32x4,
-                })
-                .collect::<Vec<_>>(),
+        let instance_buffer = GrowableBuffer::new(
+            device,
+            "Instance Buffer",
+            wgpu::BufferUsages::VERTEX,
+            (instances.len() * std::mem::size_of::<InstanceData>()) as _,
+        );
+
+        let shading = ShadingParams {
+            ambient_light: DEFAULT_AMBIENT_LIGHT.to_array(),
+            depth_shading: 0,
+            depth_range: [0.0; 2],
+            _padding: [0.0; 2],
         };
+        let shading_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Shading Buffer"),
+            contents: bytemuck::bytes_of(&shading),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let shading_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Shading Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+        let shading_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Shading Bind Group"),
+            layout: &shading_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: shading_buffer.as_entire_binding(),
+            }],
+        });
 
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: None,
@@ -72,6 +349,211 @@ impl MainState {
                 preprocessor.preprocess("main.wgsl").unwrap(),
             )),
         });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[camera_state.bind_group_layout(), &shading_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let [pipeline, overdraw_pipeline] = Self::create_pipelines(
+            device,
+            &shader,
+            &pipeline_layout,
+            swapchain_format,
+            depth_format,
+            primitive,
+            PipelineVariant {
+                overdraw: false,
+                motion_vectors: false,
+                instance_format: InstanceFormat::default(),
+                depth_bias: wgpu::DepthBiasState::default(),
+                view_count: camera_state.view_count(),
+            },
+        );
+        Self {
+            shader,
+            pipeline_layout,
+            pipeline,
+            overdraw_pipeline,
+            motion_vector_pipelines: None,
+            show_overdraw: false,
+            clear_color: wgpu::Color::BLACK,
+            stencil_reference: 0,
+            swapchain_format,
+            depth_format,
+            primitive,
+            depth_bias: wgpu::DepthBiasState::default(),
+            view_count: camera_state.view_count(),
+            shading,
+            shading_buffer,
+            shading_bind_group,
+
+            instances,
+            instance_format: InstanceFormat::default(),
+            instance_buffer,
+            dirty_instances: DirtyRanges::default(),
+            uploaded_instance_count: None,
+            previous_placements: vec![],
+            moved_instances: DirtyRanges::default(),
+        }
+    }
+
+    pub fn cull_mode(&self) -> Option<wgpu::Face> {
+        self.primitive.cull_mode
+    }
+
+    /// Recreates the pipeline with the given `cull_mode`.
+    pub fn set_cull_mode(&mut self, device: &wgpu::Device, cull_mode: Option<wgpu::Face>) {
+        self.primitive.cull_mode = cull_mode;
+        self.recreate_pipelines(device);
+    }
+
+    /// Recreates the pipelines with the given depth bias, which offsets the depth of the
+    /// instances' fragments by `constant` times the smallest representable depth difference,
+    /// plus `slope_scale` times the depth's slope across the triangle. Negative values bring
+    /// the instances towards the viewer, to stop them from z-fighting with coplanar geometry
+    /// drawn by other passes. There's no bias by default.
+    pub fn set_depth_bias(&mut self, device: &wgpu::Device, depth_bias: wgpu::DepthBiasState) {
+        if depth_bias == self.depth_bias {
+            return;
+        }
+        self.depth_bias = depth_bias;
+        self.recreate_pipelines(device);
+    }
+
+    /// Switches to uploading the instances in `instance_format`, recreating the pipelines to
+    /// match. Every instance is uploaded again by the next [Self::upload_instances].
+    pub fn set_instance_format(&mut self, device: &wgpu::Device, instance_format: InstanceFormat) {
+        if instance_format == self.instance_format {
+            return;
+        }
+        self.instance_format = instance_format;
+        self.uploaded_instance_count = None;
+        self.recreate_pipelines(device);
+    }
+
+    /// Creates the pipelines that also output per-pixel motion vectors, so that
+    /// [DrawTargets::motion_vectors] can be used. Each motion vector is how far the surface at
+    /// that pixel has moved on screen since the previous frame, from both the camera's and the
+    /// instance's movement. This is groundwork for temporal techniques, such as temporal
+    /// anti-aliasing or custom reprojection.
+    pub fn enable_motion_vectors(&mut self, device: &wgpu::Device) {
+        if self.motion_vector_pipelines.is_none() {
+            self.motion_vector_pipelines = Some(self.create_own_pipelines(device, true));
+        }
+    }
+
+    /// Recreates the existing pipelines, after a setting that they're created with has changed.
+    fn recreate_pipelines(&mut self, device: &wgpu::Device) {
+        [self.pipeline, self.overdraw_pipeline] = self.create_own_pipelines(device, false);
+        if self.motion_vector_pipelines.is_some() {
+            self.motion_vector_pipelines = Some(self.create_own_pipelines(device, true));
+        }
+    }
+
+    fn create_own_pipelines(
+        &self,
+        device: &wgpu::Device,
+        motion_vectors: bool,
+    ) -> [wgpu::RenderPipeline; 2] {
+        Self::create_pipelines(
+            device,
+            &self.shader,
+            &self.pipeline_layout,
+            self.swapchain_format,
+            self.depth_format,
+            self.primitive,
+            PipelineVariant {
+                overdraw: false,
+                motion_vectors,
+                instance_format: self.instance_format,
+                depth_bias: self.depth_bias,
+                view_count: self.view_count,
+            },
+        )
+    }
+
+    /// Sets the colour of the light that reaches every surface regardless of its orientation.
+    pub fn set_ambient_light(&mut self, queue: &wgpu::Queue, ambient_light: Vec3) {
+        self.set_shading(
+            queue,
+            ShadingParams {
+                ambient_light: ambient_light.to_array(),
+                ..self.shading
+            },
+        );
+    }
+
+    pub fn depth_shading(&self) -> Option<Range<f32>> {
+        let [start, end] = self.shading.depth_range;
+        (self.shading.depth_shading != 0).then_some(start..end)
+    }
+
+    /// Colours fragments by their distance from the viewer, rather than shading them, to show
+    /// how depth is distributed across the scene. The colours ramp from blue at `range.start` to
+    /// red at `range.end` on a logarithmic scale, so that each order of magnitude gets the same
+    /// share of the ramp. `None` goes back to shading normally.
+    pub fn set_depth_shading(&mut self, queue: &wgpu::Queue, range: Option<Range<f32>>) {
+        self.set_shading(
+            queue,
+            ShadingParams {
+                depth_shading: range.is_some().into(),
+                depth_range: range.map_or([0.0; 2], |r| [r.start, r.end]),
+                ..self.shading
+            },
+        );
+    }
+
+    /// The buffer is only written when the parameters change.
+    fn set_shading(&mut self, queue: &wgpu::Queue, shading: ShadingParams) {
+        if shading == self.shading {
+            return;
+        }
+        self.shading = shading;
+        queue.write_buffer(&self.shading_buffer, 0, bytemuck::bytes_of(&shading));
+    }
+
+    /// Creates the scene and overdraw pipelines; `variant.overdraw` is ignored.
+    fn create_pipelines(
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        pipeline_layout: &wgpu::PipelineLayout,
+        swapchain_format: wgpu::TextureFormat,
+        depth_format: wgpu::TextureFormat,
+        primitive: wgpu::PrimitiveState,
+        variant: PipelineVariant,
+    ) -> [wgpu::RenderPipeline; 2] {
+        [false, true].map(|overdraw| {
+            Self::create_pipeline(
+                device,
+                shader,
+                pipeline_layout,
+                swapchain_format,
+                depth_format,
+                primitive,
+                PipelineVariant {
+                    overdraw,
+                    ..variant
+                },
+            )
+        })
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        pipeline_layout: &wgpu::PipelineLayout,
+        swapchain_format: wgpu::TextureFormat,
+        depth_format: wgpu::TextureFormat,
+        primitive: wgpu::PrimitiveState,
+        variant: PipelineVariant,
+    ) -> wgpu::RenderPipeline {
+        let PipelineVariant {
+            overdraw,
+            motion_vectors,
+            instance_format,
+            depth_bias,
+            view_count,
+        } = variant;
         let vertex_buffer_layout = wgpu::VertexBufferLayout {
             array_stride: std::mem::size_of::<Vertex>() as _,
             step_mode: wgpu::VertexStepMode::Vertex,
@@ -86,90 +568,321 @@ impl MainState {
                     shader_location: 1,
                     format: wgpu::VertexFormat::Float32x4,
                 },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 3 + 4]>() as _,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
             ],
         };
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: None,
-            bind_group_layouts: &[(camera_state.bind_group_layout())],
-            push_constant_ranges: &[],
+        let instance_attributes = instance_format.attributes();
+        let instance_buffer_layout = wgpu::VertexBufferLayout {
+            array_stride: instance_format.stride() as _,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &instance_attributes,
+        };
+        let motion_vector_target = motion_vectors.then_some(wgpu::ColorTargetState {
+            format: MOTION_VECTOR_FORMAT,
+            blend: None,
+            write_mask: wgpu::ColorWrites::ALL,
         });
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: None,
-            layout: Some(&pipeline_layout),
+            layout: Some(pipeline_layout),
             vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
+                module: shader,
+                entry_point: instance_format.vertex_entry_point(),
                 buffers: &[vertex_buffer_layout, instance_buffer_layout],
             },
             fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(swapchain_format.into())],
+                module: shader,
+                entry_point: match (overdraw, motion_vectors) {
+                    (false, false) => "fs_main",
+                    (true, false) => "fs_overdraw",
+                    (false, true) => "fs_main_motion_vectors",
+                    (true, true) => "fs_overdraw_motion_vectors",
+                },
+                targets: &[
+                    Some(wgpu::ColorTargetState {
+                        format: swapchain_format,
+                        blend: overdraw.then_some(wgpu::BlendState {
+                            color: wgpu::BlendComponent {
+                                src_factor: wgpu::BlendFactor::One,
+                                dst_factor: wgpu::BlendFactor::One,
+                                operation: wgpu::BlendOperation::Add,
+                            },
+                            alpha: wgpu::BlendComponent::REPLACE,
+                        }),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }),
+                    motion_vector_target,
+                ][..1 + motion_vectors as usize],
             }),
-            primitive: wgpu::PrimitiveState::default(),
+            primitive,
             depth_stencil: Some(wgpu::DepthStencilState {
-                format: DEPTH_FORMAT,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
+                format: depth_format,
+                depth_write_enabled: !overdraw,
+                depth_compare: if overdraw {
+                    wgpu::CompareFunction::Always
+                } else {
+                    wgpu::CompareFunction::Less
+                },
+                stencil: if has_stencil(depth_format) {
+                    let face = wgpu::StencilFaceState {
+                        compare: wgpu::CompareFunction::Equal,
+                        fail_op: wgpu::StencilOperation::Keep,
+                        depth_fail_op: wgpu::StencilOperation::Keep,
+                        pass_op: wgpu::StencilOperation::Keep,
+                    };
+                    wgpu::StencilState {
+                        front: face,
+                        back: face,
+                        read_mask: 0xff,
+                        write_mask: 0,
+                    }
+                } else {
+                    wgpu::StencilState::default()
+                },
+                // The overdraw heatmap ignores depth.
+                bias: if overdraw {
+                    wgpu::DepthBiasState::default()
+                } else {
+                    depth_bias
+                },
             }),
             multisample: wgpu::MultisampleState::default(),
-            multiview: NonZeroU32::new(VIEW_COUNT),
-        });
-        Self {
-            shader,
-            pipeline_layout,
-            pipeline,
+            multiview: NonZeroU32::new(view_count),
+        })
+    }
 
-            instances,
-            instance_buffer,
+    /// Uploads [Self::instances] to the GPU. The instance buffer is reused between frames, and
+    /// is only reallocated when the number of instances exceeds its capacity.
+    ///
+    /// If the number of instances hasn't changed since the last upload, only the instances
+    /// passed to [Self::mark_dirty] are uploaded, with one write per run of adjacent instances.
+    /// Otherwise, every instance is uploaded, and treated as not having moved for the purposes
+    /// of motion vectors.
+    pub fn upload_instances(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if self.uploaded_instance_count != Some(self.instances.len()) {
+            self.previous_placements = self.instances.iter().map(Instance::placement).collect();
+            let data = self.instances_to_data(&self.instances, &self.previous_placements);
+            self.instance_buffer.write(device, queue, &data);
+            self.uploaded_instance_count = Some(self.instances.len());
+            self.dirty_instances.clear();
+            self.moved_instances.clear();
+            return;
+        }
+
+        for index in self.moved_instances.take().into_iter().flatten() {
+            self.dirty_instances.insert(index);
+        }
+        let stride = self.instance_format.stride();
+        for range in self.dirty_instances.take() {
+            let instances = &self.instances[range.clone()];
+            let mut previous = Vec::with_capacity(instances.len());
+            for (index, instance) in range.clone().zip(instances) {
+                let placement = instance.placement();
+                let old = std::mem::replace(&mut self.previous_placements[index], placement);
+                if old != placement {
+                    self.moved_instances.insert(index);
+                }
+                previous.push(old);
+            }
+            let data = self.instances_to_data(instances, &previous);
+            self.instance_buffer
+                .write_at(queue, (range.start * stride) as _, &data);
         }
     }
 
-    pub fn upload_instances(&self, queue: &wgpu::Queue) {
-        queue.write_buffer(
-            &self.instance_buffer,
-            0,
-            bytemuck::cast_slice(&Self::instances_to_data(&self.instances)),
-        );
+    /// Marks the instance at `index` as changed, so that it's uploaded by the next
+    /// [Self::upload_instances]. This must be called after modifying an instance in place;
+    /// adding or removing instances causes all of them to be uploaded.
+    pub fn mark_dirty(&mut self, index: usize) {
+        self.dirty_instances.insert(index);
     }
 
-    fn instances_to_data(poses: &[Instance]) -> Vec<f32> {
-        poses.iter().flat_map(Instance::to_cols_array).collect()
+    /// Shows or hides the instance at `index`, without changing the number of instances. Takes
+    /// effect on the next [Self::upload_instances].
+    #[cfg_attr(not(feature = "xr"), allow(dead_code))]
+    pub fn set_visible(&mut self, index: usize, visible: bool) {
+        self.instances[index].visible = visible;
+        self.mark_dirty(index);
     }
 
+    /// The instance buffer contents for `instances` in the current format, given where each of
+    /// them was as of the previous upload.
+    fn instances_to_data(&self, instances: &[Instance], previous: &[Placement]) -> Vec<u8> {
+        let instances = instances.iter().zip(previous);
+        match self.instance_format {
+            InstanceFormat::Matrices => instances
+                .flat_map(|(instance, previous)| {
+                    bytemuck::bytes_of(&instance.to_data(previous)).to_vec()
+                })
+                .collect(),
+            InstanceFormat::Compact => instances
+                .flat_map(|(instance, previous)| {
+                    bytemuck::bytes_of(&instance.to_compact_data(previous)).to_vec()
+                })
+                .collect(),
+        }
+    }
+
+    /// Draws the instances into `targets`, which don't have to be owned by this example: they
+    /// can be any textures of any size, as long as they match [DrawTargets]' requirements.
+    ///
+    /// `load` applies to every target, depth included, so that content drawn over the previous
+    /// frame's is still occluded by it.
+    ///
+    /// # Panics
+    ///
+    /// If [DrawTargets::motion_vectors] is given before motion vectors have been enabled.
     pub fn encode_draw_pass(
         &self,
         encoder: &mut wgpu::CommandEncoder,
-        rt_view: &wgpu::TextureView,
-        depth_view: &wgpu::TextureView,
-        vertex_buffer: &wgpu::Buffer,
+        targets: &DrawTargets,
+        load: LoadBehavior,
+        region: &RenderRegion,
+        mesh: &Mesh,
         camera_bind_group: &wgpu::BindGroup,
     ) {
-        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: None,
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: rt_view,
+        let [pipeline, overdraw_pipeline] = match targets.motion_vectors {
+            Some(_) => self
+                .motion_vector_pipelines
+                .as_ref()
+                .expect("motion vectors haven't been enabled")
+                .each_ref(),
+            None => [&self.pipeline, &self.overdraw_pipeline],
+        };
+        let mut rpass = self.begin_pass(encoder, targets, load, None);
+        region.apply(&mut rpass);
+        rpass.set_stencil_reference(self.stencil_reference);
+        rpass.set_pipeline(if self.show_overdraw {
+            overdraw_pipeline
+        } else {
+            pipeline
+        });
+        rpass.set_vertex_buffer(0, mesh.vertex_buffer().slice(..));
+        rpass.set_index_buffer(mesh.index_buffer().slice(..), wgpu::IndexFormat::Uint32);
+        rpass.set_vertex_buffer(1, self.instance_buffer.buffer().slice(..));
+        rpass.set_bind_group(0, camera_bind_group, &[]);
+        rpass.set_bind_group(1, &self.shading_bind_group, &[]);
+        rpass.draw_indexed(0..mesh.index_count(), 0, 0..(self.instances.len() as u32));
+    }
+
+    /// Clears `targets` as [Self::encode_draw_pass] would with [LoadBehavior::Clear], without
+    /// drawing anything. The targets can be single-layer views (see [Texture::layer_view]), as
+    /// the pass doesn't use multiview: this is how some layers of a target are cleared while the
+    /// others are kept, as a multiview pass clears or loads every layer alike. Clear each layer
+    /// with this, then draw with [LoadBehavior::Load].
+    ///
+    /// [Texture::layer_view]: crate::texture::Texture::layer_view
+    pub fn encode_clear_pass(&self, encoder: &mut wgpu::CommandEncoder, targets: &DrawTargets) {
+        self.begin_pass(
+            encoder,
+            targets,
+            LoadBehavior::Clear,
+            Some("Layer Clear Pass"),
+        );
+    }
+
+    fn begin_pass<'a>(
+        &self,
+        encoder: &'a mut wgpu::CommandEncoder,
+        targets: &DrawTargets<'a>,
+        load: LoadBehavior,
+        label: Option<&str>,
+    ) -> wgpu::RenderPass<'a> {
+        let color_attachment = |view, clear_color| {
+            Some(wgpu::RenderPassColorAttachment {
+                view,
                 resolve_target: None,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    load: load.load_op(clear_color),
                     store: true,
                 },
-            })],
+            })
+        };
+        let color_attachments = [
+            color_attachment(targets.color, self.clear_color),
+            // Pixels that nothing is drawn to (i.e. the background) haven't moved.
+            targets
+                .motion_vectors
+                .and_then(|view| color_attachment(view, wgpu::Color::TRANSPARENT)),
+        ];
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label,
+            color_attachments: &color_attachments[..1 + targets.motion_vectors.is_some() as usize],
             depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                view: depth_view,
+                view: targets.depth,
                 depth_ops: Some(wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(1.0),
+                    load: load.load_op(1.0),
+                    store: true,
+                }),
+                stencil_ops: has_stencil(self.depth_format).then_some(wgpu::Operations {
+                    load: load.load_op(0),
                     store: true,
                 }),
-                stencil_ops: None,
             }),
-        });
-        rpass.set_pipeline(&self.pipeline);
-        rpass.set_vertex_buffer(0, vertex_buffer.slice(..));
-        rpass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-        rpass.set_bind_group(0, camera_bind_group, &[]);
-        rpass.draw(0..3, 0..(self.instances.len() as u32));
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normal_matrix_keeps_normals_perpendicular_under_non_uniform_scale() {
+        let instance = Instance::new(
+            Vec3::new(1.0, 2.0, 3.0),
+            Quat::from_rotation_y(0.5),
+            Vec3::new(4.0, 1.0, 0.5),
+        );
+        let data = instance.to_data(&instance.placement());
+        let model_matrix = Mat4::from_cols_array(&data.model_matrix);
+        let normal_matrix = Mat3::from_cols_array(&data.normal_matrix);
+
+        // A surface spanned by these tangents has the normal (1, 1, 0).
+        let normal = Vec3::new(1.0, 1.0, 0.0).normalize();
+        for tangent in [Vec3::new(1.0, -1.0, 0.0), Vec3::Z] {
+            let tangent = model_matrix.transform_vector3(tangent);
+            assert!((normal_matrix * normal).dot(tangent).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn hidden_instance_collapses_to_a_point() {
+        let mut instances = vec![Instance::new(Vec3::Z, Quat::IDENTITY, Vec3::ONE); 2];
+        instances[1].visible = false;
+        let area = |data: &InstanceData| {
+            let model_matrix = Mat4::from_cols_array(&data.model_matrix);
+            let [a, b, c] = [
+                Vec3::new(-1.0, -1.0, 0.0),
+                Vec3::Y,
+                Vec3::new(1.0, -1.0, 0.0),
+            ]
+            .map(|p| model_matrix * p.extend(1.0));
+            (b - a).truncate().cross((c - a).truncate()).length()
+        };
+
+        let data: Vec<_> = instances
+            .iter()
+            .map(|instance| instance.to_data(&instance.placement()))
+            .collect();
+        assert!(area(&data[0]) > 0.0);
+        assert_eq!(area(&data[1]), 0.0);
+    }
+
+    #[test]
+    fn instance_attributes_cover_the_instance_data() {
+        for format in [InstanceFormat::Matrices, InstanceFormat::Compact] {
+            let last = *format.attributes().last().unwrap();
+            assert_eq!(
+                last.offset + last.format.size(),
+                format.stride() as u64,
+                "{format:?}"
+            );
+        }
     }
 }
@@ -0,0 +1,120 @@
+use glam::{Vec3, Vec4};
+use wgpu::util::DeviceExt;
+
+use crate::types::Vertex;
+
+/// Indexed triangle geometry, ready to be drawn.
+pub struct Mesh {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    index_count: u32,
+}
+impl Mesh {
+    pub fn new(device: &wgpu::Device, vertices: &[Vertex], indices: &[u32]) -> Self {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Vertex Buffer"),
+            contents: bytemuck::cast_slice(vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Index Buffer"),
+            contents: bytemuck::cast_slice(indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+        Self {
+            vertex_buffer,
+            index_buffer,
+            index_count: indices.len() as u32,
+        }
+    }
+
+    pub fn vertex_buffer(&self) -> &wgpu::Buffer {
+        &self.vertex_buffer
+    }
+
+    pub fn index_buffer(&self) -> &wgpu::Buffer {
+        &self.index_buffer
+    }
+
+    pub fn index_count(&self) -> u32 {
+        self.index_count
+    }
+}
+
+/// Splits the triangle with the given corners into `4^level` triangles of equal size, with the
+/// same winding as the original. The colours are interpolated across the triangle, so the
+/// result looks the same as the original triangle, but costs more to rasterize.
+///
+/// This is used to add a controllable amount of geometry load for testing.
+pub fn subdivided_triangle(
+    positions: [Vec3; 3],
+    colors: [Vec4; 3],
+    normal: Vec3,
+    level: u32,
+) -> (Vec<Vertex>, Vec<u32>) {
+    let segments = 1u32 << level;
+    let step = 1.0 / segments as f32;
+
+    // The vertices form a triangular grid: row `i` steps towards the second corner, and
+    // contains `segments - i + 1` vertices, each stepping towards the third corner.
+    let mut vertices = vec![];
+    let mut row_starts = vec![];
+    for i in 0..=segments {
+        row_starts.push(vertices.len() as u32);
+        for j in 0..=(segments - i) {
+            let (u, v) = (i as f32 * step, j as f32 * step);
+            let w = 1.0 - u - v;
+            vertices.push(Vertex::new(
+                positions[0] * w + positions[1] * u + positions[2] * v,
+                normal,
+                colors[0] * w + colors[1] * u + colors[2] * v,
+            ));
+        }
+    }
+
+    let index = |i: u32, j: u32| row_starts[i as usize] + j;
+    let mut indices = vec![];
+    for i in 0..segments {
+        for j in 0..(segments - i) {
+            indices.extend([index(i, j), index(i + 1, j), index(i, j + 1)]);
+            if j + 1 < segments - i {
+                indices.extend([index(i + 1, j), index(i + 1, j + 1), index(i, j + 1)]);
+            }
+        }
+    }
+
+    (vertices, indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subdivision_covers_the_triangle_with_the_same_winding() {
+        let positions = [
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::Y,
+            Vec3::new(1.0, -1.0, 0.0),
+        ];
+        let signed_area = |[a, b, c]: [Vec3; 3]| (b - a).cross(c - a).z / 2.0;
+        let expected_area = signed_area(positions);
+
+        for level in 0..4 {
+            let (vertices, indices) =
+                subdivided_triangle(positions, [Vec4::ONE; 3], Vec3::Z, level);
+            let vertices: &[[f32; 10]] = bytemuck::cast_slice(&vertices);
+            let triangles: Vec<[Vec3; 3]> = indices
+                .chunks(3)
+                .map(|t| [t[0], t[1], t[2]].map(|i| Vec3::from_slice(&vertices[i as usize][..3])))
+                .collect();
+
+            assert_eq!(triangles.len(), 4usize.pow(level));
+            for triangle in &triangles {
+                let area = signed_area(*triangle);
+                assert!(area.signum() == expected_area.signum());
+                assert!((area - expected_area / triangles.len() as f32).abs() < 1e-5);
+            }
+        }
+    }
+}
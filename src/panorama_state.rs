@@ -0,0 +1,341 @@
+use anyhow::Context;
+use glam::{Mat4, Vec3};
+use std::{borrow::Cow, num::NonZeroU32, path::Path};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    camera::CameraState,
+    types::{RenderRegion, VIEW_COUNT},
+};
+
+/// The width and height of each cube face, in pixels.
+const FACE_SIZE: u32 = 1024;
+/// The format of the panorama, which is written out as-is.
+const OUTPUT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// The forward and up vectors of each cube face. Must match the layout expected by
+/// `panorama.wgsl`.
+const CUBE_FACES: [(Vec3, Vec3); 6] = [
+    (Vec3::X, Vec3::Y),
+    (Vec3::NEG_X, Vec3::Y),
+    (Vec3::Y, Vec3::Z),
+    (Vec3::NEG_Y, Vec3::NEG_Z),
+    (Vec3::Z, Vec3::Y),
+    (Vec3::NEG_Z, Vec3::Y),
+];
+
+/// Captures stereo 360 degree panoramas of the scene.
+///
+/// The scene is rendered into a cubemap for each eye, which are then stitched into a top-bottom
+/// equirectangular image. Each face is rendered with multiview, so this takes six passes over
+/// the scene (twelve views) at [FACE_SIZE], plus a readback of the result, which will cause a
+/// noticeable hitch.
+///
+/// As the eyes stay where they are for every face, the stereo separation is only correct when
+/// looking in the direction the head was facing, and is reversed when looking behind it.
+pub struct PanoramaState {
+    color_format: wgpu::TextureFormat,
+    depth_format: wgpu::TextureFormat,
+    sampler: wgpu::Sampler,
+    params_buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+}
+impl PanoramaState {
+    /// `color_format` and `depth_format` must match the targets of the scene's pipelines.
+    pub fn new(
+        device: &wgpu::Device,
+        preprocessor: &crate::wgsl::Preprocessor,
+        color_format: wgpu::TextureFormat,
+        depth_format: wgpu::TextureFormat,
+    ) -> Self {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Panorama Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let params: Vec<[f32; 4]> = CUBE_FACES
+            .iter()
+            .flat_map(|(forward, up)| [forward.extend(0.0), up.extend(0.0)])
+            .map(|v| v.to_array())
+            .collect();
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Panorama Params Buffer"),
+            contents: bytemuck::cast_slice(&params),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Panorama Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(Cow::Owned(
+                preprocessor.preprocess("panorama.wgsl").unwrap(),
+            )),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Panorama Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "panorama_vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "panorama_fs_main",
+                targets: &[Some(OUTPUT_FORMAT.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            color_format,
+            depth_format,
+            sampler,
+            params_buffer,
+            bind_group_layout,
+            pipeline,
+        }
+    }
+
+    /// Renders a panorama from the given left and right eye positions, and saves it to `path`
+    /// as a PNG. `draw_scene` is called once per cube face to encode the scene's render pass
+    /// into the given colour and depth targets and region, using the given camera bind group.
+    pub fn capture(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        camera_state: &CameraState,
+        eye_positions: [Vec3; 2],
+        mut draw_scene: impl FnMut(
+            &mut wgpu::CommandEncoder,
+            &wgpu::TextureView,
+            &wgpu::TextureView,
+            &RenderRegion,
+            &wgpu::BindGroup,
+        ),
+        path: &Path,
+    ) -> anyhow::Result<()> {
+        let face_extent = |layers| wgpu::Extent3d {
+            width: FACE_SIZE,
+            height: FACE_SIZE,
+            depth_or_array_layers: layers,
+        };
+        let faces_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Panorama Faces"),
+            size: face_extent(CUBE_FACES.len() as u32 * VIEW_COUNT),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.color_format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+        let depth_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Panorama Depth"),
+            size: face_extent(VIEW_COUNT),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.depth_format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        });
+        let depth_view = depth_texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            array_layer_count: NonZeroU32::new(VIEW_COUNT),
+            ..Default::default()
+        });
+
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        // Render each face for both eyes at once; the two eyes of a face are adjacent layers.
+        let camera = &camera_state.data;
+        let proj = Mat4::perspective_rh(90.0f32.to_radians(), 1.0, camera.z_near, camera.z_far);
+        for (index, (forward, up)) in CUBE_FACES.iter().enumerate() {
+            let view_proj_matrices: Vec<f32> = eye_positions
+                .iter()
+                .flat_map(|&eye| {
+                    (proj * Mat4::look_at_rh(eye, eye + *forward, *up)).to_cols_array()
+                })
+                .collect();
+            let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Panorama Camera Buffer"),
+                contents: bytemuck::cast_slice(&view_proj_matrices),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+            // The faces are still images, so the camera hasn't moved since the "previous" frame.
+            let camera_bind_group = CameraState::create_bind_group(
+                device,
+                camera_state.bind_group_layout(),
+                "Panorama Camera Bind Group",
+                &camera_buffer,
+                &camera_buffer,
+                camera_state.time_buffer(),
+            );
+            let face_view = faces_texture.create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::D2Array),
+                base_array_layer: index as u32 * VIEW_COUNT,
+                array_layer_count: NonZeroU32::new(VIEW_COUNT),
+                ..Default::default()
+            });
+            draw_scene(
+                &mut encoder,
+                &face_view,
+                &depth_view,
+                &RenderRegion::full(FACE_SIZE, FACE_SIZE),
+                &camera_bind_group,
+            );
+        }
+
+        // Stitch the faces together.
+        let output_size = wgpu::Extent3d {
+            width: FACE_SIZE * 4,
+            height: FACE_SIZE * 4,
+            depth_or_array_layers: 1,
+        };
+        let output_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Panorama Output"),
+            size: output_size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: OUTPUT_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Panorama Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&faces_texture.create_view(
+                        &wgpu::TextureViewDescriptor {
+                            dimension: Some(wgpu::TextureViewDimension::D2Array),
+                            ..Default::default()
+                        },
+                    )),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.params_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        {
+            let output_view = output_texture.create_view(&wgpu::TextureViewDescriptor::default());
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Panorama Stitch"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &output_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            rpass.set_pipeline(&self.pipeline);
+            rpass.set_bind_group(0, &bind_group, &[]);
+            rpass.draw(0..3, 0..1);
+        }
+
+        // Read the result back. Rows of a buffer copy must be aligned, so the padding has to
+        // be stripped out afterwards.
+        let unpadded_bytes_per_row = output_size.width * 4;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(align) * align;
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Panorama Readback Buffer"),
+            size: (padded_bytes_per_row * output_size.height) as _,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            output_texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &readback_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(padded_bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            output_size,
+        );
+        queue.submit(Some(encoder.finish()));
+
+        let slice = readback_buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        receiver.recv()??;
+
+        let pixels: Vec<u8> = slice
+            .get_mapped_range()
+            .chunks(padded_bytes_per_row as usize)
+            .flat_map(|row| &row[..unpadded_bytes_per_row as usize])
+            .copied()
+            .collect();
+        readback_buffer.unmap();
+
+        let file = std::fs::File::create(path)
+            .with_context(|| format!("failed to create panorama {path:?}"))?;
+        let mut encoder = png::Encoder::new(
+            std::io::BufWriter::new(file),
+            output_size.width,
+            output_size.height,
+        );
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&pixels)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+/// A pass's encoding function, which is given the graph's context (usually the frame's command
+/// encoder) when the graph is executed.
+type EncodeFn<'a, C> = Box<dyn FnOnce(&mut C) + 'a>;
+
+struct Pass<'a, C> {
+    name: &'static str,
+    reads: Vec<&'static str>,
+    writes: Vec<&'static str>,
+    encode: EncodeFn<'a, C>,
+}
+
+/// A lightweight render graph: each pass declares the resources it reads and writes, and the
+/// passes are executed in dependency order, so that optional passes can be added without each
+/// one having to know where it goes relative to the others.
+///
+/// Resources are names rather than textures, and each is written by exactly one pass. A pass
+/// that modifies a texture in place (e.g. SSAO darkening the render target) reads the
+/// texture's current name and writes a new one, which later passes read instead. Passes that
+/// don't depend on each other keep the order they were added in.
+pub struct RenderGraph<'a, C = wgpu::CommandEncoder> {
+    passes: Vec<Pass<'a, C>>,
+    /// Resources that exist before any pass runs, such as a render target that is being reused
+    /// from the previous frame.
+    imported: Vec<&'static str>,
+}
+impl<C> Default for RenderGraph<'_, C> {
+    fn default() -> Self {
+        Self {
+            passes: vec![],
+            imported: vec![],
+        }
+    }
+}
+impl<'a, C> RenderGraph<'a, C> {
+    pub fn add_pass(
+        &mut self,
+        name: &'static str,
+        reads: &[&'static str],
+        writes: &[&'static str],
+        encode: impl FnOnce(&mut C) + 'a,
+    ) {
+        self.passes.push(Pass {
+            name,
+            reads: reads.to_vec(),
+            writes: writes.to_vec(),
+            encode: Box::new(encode),
+        });
+    }
+
+    /// Declares a resource that is available without any pass writing it.
+    pub fn import(&mut self, resource: &'static str) {
+        self.imported.push(resource);
+    }
+
+    /// The names of the passes, in the order they would be executed in.
+    #[cfg(test)]
+    pub fn order(&self) -> anyhow::Result<Vec<&'static str>> {
+        Ok(self
+            .sorted_indices()?
+            .into_iter()
+            .map(|i| self.passes[i].name)
+            .collect())
+    }
+
+    /// Runs each pass in dependency order. Fails without running any passes if a resource is
+    /// read but never written, written by more than one pass, or the passes depend on each
+    /// other in a cycle.
+    pub fn execute(self, context: &mut C) -> anyhow::Result<()> {
+        let order = self.sorted_indices()?;
+        let mut passes: Vec<_> = self.passes.into_iter().map(Some).collect();
+        for index in order {
+            let pass = passes[index].take().unwrap();
+            (pass.encode)(context);
+        }
+        Ok(())
+    }
+
+    fn sorted_indices(&self) -> anyhow::Result<Vec<usize>> {
+        let mut writers = HashMap::new();
+        for (index, pass) in self.passes.iter().enumerate() {
+            for &resource in &pass.writes {
+                anyhow::ensure!(
+                    !self.imported.contains(&resource),
+                    "pass {:?} writes {resource:?}, which is imported",
+                    pass.name
+                );
+                if let Some(other) = writers.insert(resource, index) {
+                    anyhow::bail!(
+                        "{resource:?} is written by both {:?} and {:?}",
+                        self.passes[other].name,
+                        pass.name
+                    );
+                }
+            }
+        }
+
+        let dependencies = self
+            .passes
+            .iter()
+            .map(|pass| {
+                pass.reads
+                    .iter()
+                    .filter(|resource| !self.imported.contains(resource))
+                    .map(|resource| {
+                        writers.get(resource).copied().ok_or_else(|| {
+                            anyhow::anyhow!(
+                                "pass {:?} reads {resource:?}, which nothing writes",
+                                pass.name
+                            )
+                        })
+                    })
+                    .collect::<anyhow::Result<Vec<usize>>>()
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        // Repeatedly runs the earliest-added pass whose dependencies have all run, so that
+        // independent passes keep their relative order.
+        let mut order = Vec::with_capacity(self.passes.len());
+        let mut done = vec![false; self.passes.len()];
+        while order.len() < self.passes.len() {
+            let next = (0..self.passes.len())
+                .find(|&i| !done[i] && dependencies[i].iter().all(|&d| done[d]))
+                .ok_or_else(|| {
+                    let stuck: Vec<_> = (0..self.passes.len())
+                        .filter(|&i| !done[i])
+                        .map(|i| self.passes[i].name)
+                        .collect();
+                    anyhow::anyhow!("the passes {stuck:?} depend on each other in a cycle")
+                })?;
+            done[next] = true;
+            order.push(next);
+        }
+        Ok(order)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passes_run_after_the_passes_they_read_from() {
+        let mut graph = RenderGraph::<Vec<&str>>::default();
+        graph.add_pass("blit", &["overlay"], &["window"], |log| log.push("blit"));
+        graph.add_pass("text", &["ssao"], &["overlay"], |log| log.push("text"));
+        graph.add_pass("main", &[], &["scene", "depth"], |log| log.push("main"));
+        graph.add_pass("ssao", &["scene", "depth"], &["ssao"], |log| {
+            log.push("ssao")
+        });
+        graph.add_pass("stats", &[], &["stats"], |log| log.push("stats"));
+
+        let expected = ["main", "ssao", "text", "blit", "stats"];
+        assert_eq!(graph.order().unwrap(), expected);
+        let mut log = vec![];
+        graph.execute(&mut log).unwrap();
+        assert_eq!(log, expected);
+    }
+
+    #[test]
+    fn invalid_graphs_are_rejected() {
+        let mut missing = RenderGraph::<()>::default();
+        missing.add_pass("blit", &["scene"], &["window"], |_| {});
+        assert!(missing.order().is_err());
+        missing.import("scene");
+        assert!(missing.order().is_ok());
+
+        let mut duplicate = RenderGraph::<()>::default();
+        duplicate.add_pass("a", &[], &["scene"], |_| {});
+        duplicate.add_pass("b", &[], &["scene"], |_| {});
+        assert!(duplicate.order().is_err());
+
+        let mut cycle = RenderGraph::<()>::default();
+        cycle.add_pass("a", &["y"], &["x"], |_| {});
+        cycle.add_pass("b", &["x"], &["y"], |_| {});
+        assert!(cycle.order().is_err());
+    }
+}
@@ -0,0 +1,73 @@
+use openxr as xr;
+
+use crate::xr::{LayerSwapchain, XrState};
+
+/// A small dot a metre ahead of the centre of the views, for checking that they line up (e.g.
+/// after changing the eye layout). It's a quad layer that's built here rather than by
+/// [XrState], and submitted through [XrState::post_queue_submit]'s `extra_layers`; unlike the
+/// built-in quad layers, which are placed in the stage space, it's placed in the view space, so
+/// that it follows the head.
+pub struct Reticle {
+    swapchain: LayerSwapchain,
+    view_space: xr::Space,
+}
+impl Reticle {
+    /// How far in front of the views the dot is, in metres.
+    const DISTANCE: f32 = 1.0;
+    /// The width and height of the dot, in metres.
+    const SIZE: f32 = 0.01;
+
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        xr_state: &XrState,
+    ) -> anyhow::Result<Self> {
+        let session = xr_state.session();
+        let view_space =
+            session.create_reference_space(xr::ReferenceSpaceType::VIEW, xr::Posef::IDENTITY)?;
+        let swapchain =
+            LayerSwapchain::new_static(session, device, queue, 4, 4, |encoder, view| {
+                encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Reticle"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color {
+                                r: 1.0,
+                                g: 1.0,
+                                b: 1.0,
+                                a: 0.75,
+                            }),
+                            store: true,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                });
+            })?;
+        Ok(Self {
+            swapchain,
+            view_space,
+        })
+    }
+
+    pub fn composition_layer(&self) -> xr::CompositionLayerQuad<'_, xr::Vulkan> {
+        xr::CompositionLayerQuad::new()
+            .layer_flags(xr::CompositionLayerFlags::BLEND_TEXTURE_SOURCE_ALPHA)
+            .space(&self.view_space)
+            .eye_visibility(xr::EyeVisibility::BOTH)
+            .sub_image(self.swapchain.sub_image())
+            .pose(xr::Posef {
+                orientation: xr::Quaternionf::IDENTITY,
+                position: xr::Vector3f {
+                    x: 0.0,
+                    y: 0.0,
+                    z: -Self::DISTANCE,
+                },
+            })
+            .size(xr::Extent2Df {
+                width: Self::SIZE,
+                height: Self::SIZE,
+            })
+    }
+}
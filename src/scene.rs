@@ -0,0 +1,108 @@
+use glam::{vec3, Quat, Vec3, Vec4};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::main_state::Instance;
+
+/// The most instances that a [SceneSpec] can generate, to keep a mistyped count from
+/// allocating an enormous instance buffer.
+pub const MAX_SCENE_INSTANCES: usize = 100_000;
+
+/// A procedurally generated scene: `count` instances placed using an RNG seeded with `seed`,
+/// so that the same specification always produces the same scene. The RNG is ChaCha8, whose
+/// output for a seed is fixed, unlike `StdRng`'s, which may change between `rand` versions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SceneSpec {
+    pub seed: u64,
+    pub count: usize,
+}
+impl std::str::FromStr for SceneSpec {
+    type Err = anyhow::Error;
+
+    /// Parses a specification of the form `seed:count`, where `count` is at most
+    /// [MAX_SCENE_INSTANCES].
+    fn from_str(value: &str) -> anyhow::Result<Self> {
+        let (seed, count) = value
+            .split_once(':')
+            .ok_or_else(|| anyhow::anyhow!("expected `seed:count`, got {value:?}"))?;
+        let count = count.trim().parse()?;
+        anyhow::ensure!(
+            count <= MAX_SCENE_INSTANCES,
+            "the instance count must be at most {MAX_SCENE_INSTANCES}, got {count}"
+        );
+        Ok(Self {
+            seed: seed.trim().parse()?,
+            count,
+        })
+    }
+}
+impl SceneSpec {
+    /// Scatters the instances in front of the viewer, with random rotations, sizes and tints.
+    pub fn generate(&self) -> Vec<Instance> {
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        (0..self.count)
+            .map(|_| {
+                let translation = vec3(
+                    rng.gen_range(-5.0..5.0),
+                    rng.gen_range(-2.0..2.0),
+                    rng.gen_range(2.0..12.0),
+                );
+                // Normalising a point in a cube is biased towards the corners, but that doesn't
+                // matter for a test scene.
+                let axis = vec3(
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                )
+                .try_normalize()
+                .unwrap_or(Vec3::Y);
+                let rotation =
+                    Quat::from_axis_angle(axis, rng.gen_range(0.0..std::f32::consts::TAU));
+                let scale = Vec3::splat(rng.gen_range(0.1..0.5));
+                Instance {
+                    color: Vec4::new(
+                        rng.gen_range(0.2..1.0),
+                        rng.gen_range(0.2..1.0),
+                        rng.gen_range(0.2..1.0),
+                        1.0,
+                    ),
+                    ..Instance::new(translation, rotation, scale)
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_generates_the_same_scene() {
+        let spec: SceneSpec = "42:16".parse().unwrap();
+        assert_eq!(
+            spec,
+            SceneSpec {
+                seed: 42,
+                count: 16
+            }
+        );
+
+        let translations = |spec: SceneSpec| {
+            spec.generate()
+                .iter()
+                .map(|i| i.translation)
+                .collect::<Vec<_>>()
+        };
+        assert!(format!("1:{}", MAX_SCENE_INSTANCES + 1)
+            .parse::<SceneSpec>()
+            .is_err());
+
+        assert_eq!(translations(spec).len(), 16);
+        assert_eq!(translations(spec), translations(spec));
+        assert_ne!(
+            translations(spec),
+            translations(SceneSpec { seed: 43, ..spec })
+        );
+    }
+}
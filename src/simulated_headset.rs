@@ -0,0 +1,122 @@
+use glam::{vec3, Quat};
+use openxr as xr;
+
+use crate::xr::PostFrameData;
+
+/// The distance between the simulated eyes, in metres.
+const IPD: f32 = 63.0 / 1_000.0;
+
+/// Stands in for a real headset by producing scripted [PostFrameData], so that the XR rendering
+/// paths (view-projection matrices from XR views, controller-driven instances) can be exercised
+/// without hardware or an OpenXR runtime.
+///
+/// The head slowly looks from side to side, and the controllers move in circles in front of it.
+/// The ambient light slowly shifts between a cool and a warm tint, as if it were being estimated
+/// from a changing environment.
+pub struct SimulatedHeadset {
+    /// The field of view of the left eye; the right eye's is mirrored. Real headsets have
+    /// asymmetric FOVs, so this is too, to catch code that assumes otherwise.
+    fov: xr::Fovf,
+}
+impl Default for SimulatedHeadset {
+    fn default() -> Self {
+        Self {
+            fov: xr::Fovf {
+                angle_left: -0.87,
+                angle_right: 0.76,
+                angle_up: 0.80,
+                angle_down: -0.91,
+            },
+        }
+    }
+}
+impl SimulatedHeadset {
+    /// The data for the frame that is displayed `time` seconds after starting.
+    pub fn post_frame(&self, time: f32) -> PostFrameData {
+        // The views are built in OpenXR's coordinate system, where the right eye is towards +X.
+        let head_rotation = Quat::from_rotation_y((time * 0.5).sin() * 0.35);
+        let views = [-1.0, 1.0]
+            .into_iter()
+            .map(|side: f32| {
+                let fov = if side < 0.0 {
+                    self.fov
+                } else {
+                    xr::Fovf {
+                        angle_left: -self.fov.angle_right,
+                        angle_right: -self.fov.angle_left,
+                        ..self.fov
+                    }
+                };
+                let position = head_rotation * vec3(side * IPD / 2.0, 0.0, 0.0);
+                xr::View {
+                    pose: xr::Posef {
+                        orientation: xr::Quaternionf {
+                            x: head_rotation.x,
+                            y: head_rotation.y,
+                            z: head_rotation.z,
+                            w: head_rotation.w,
+                        },
+                        position: xr::Vector3f {
+                            x: position.x,
+                            y: position.y,
+                            z: position.z,
+                        },
+                    },
+                    fov,
+                }
+            })
+            .collect();
+
+        let hand = |side: f32| {
+            let angle = time * 1.5;
+            let translation = vec3(
+                side * (0.3 + 0.1 * angle.cos()),
+                -0.2 + 0.1 * angle.sin(),
+                0.6,
+            );
+            (translation, Quat::from_rotation_z(side * angle))
+        };
+
+        PostFrameData {
+            views,
+            left_hand: Some(hand(1.0)),
+            right_hand: Some(hand(-1.0)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::PerspectiveCamera;
+    use glam::{Mat4, Vec3};
+
+    #[test]
+    fn point_in_front_is_visible_to_both_eyes_with_disparity() {
+        let camera = PerspectiveCamera {
+            eye: Vec3::ZERO,
+            target: Vec3::Z,
+            up: Vec3::Y,
+            aspect_ratio: 1.0,
+            fov_y_rad: 90.0f32.to_radians(),
+            z_near: 0.05,
+            z_far: 1000.0,
+            convergence: f32::INFINITY,
+            desktop_view: Default::default(),
+        };
+        let data = SimulatedHeadset::default().post_frame(0.0);
+        let matrices = camera.to_view_proj_matrices_with_xr_views(&data.views, &Default::default());
+
+        let point = vec3(0.0, 0.0, 2.0);
+        let projected: Vec<Vec3> = matrices
+            .chunks(16)
+            .map(|m| Mat4::from_cols_slice(m).project_point3(point))
+            .collect();
+        assert_eq!(projected.len(), 2);
+        for p in &projected {
+            assert!(p.x.abs() < 1.0 && p.y.abs() < 1.0 && (0.0..1.0).contains(&p.z));
+        }
+        // The left eye is further to the left, so it sees the point further to the right.
+        assert!(projected[0].x > projected[1].x);
+    }
+}
@@ -0,0 +1,220 @@
+use std::{borrow::Cow, num::NonZeroU32, ops::Range};
+use wgpu::util::DeviceExt;
+
+use crate::types::RenderRegion;
+
+/// The user-adjustable settings of [SsaoState].
+#[derive(Copy, Clone, Debug)]
+pub struct SsaoConfig {
+    /// The radius of the area around each pixel that is checked for occluders, in pixels, for
+    /// a surface 1 metre away. It shrinks with distance, so that the darkened area stays roughly
+    /// the same size in the world.
+    pub radius: f32,
+    /// How strongly occluded pixels are darkened; 1 makes fully occluded pixels black.
+    pub intensity: f32,
+    /// The number of depth samples per pixel. More samples are smoother, but cost more.
+    pub sample_count: u32,
+}
+impl Default for SsaoConfig {
+    fn default() -> Self {
+        Self {
+            radius: 24.0,
+            intensity: 1.0,
+            sample_count: 16,
+        }
+    }
+}
+
+/// Must match `SsaoParams` in `ssao.wgsl`.
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SsaoParams {
+    radius: f32,
+    intensity: f32,
+    z_near: f32,
+    z_far: f32,
+    sample_count: u32,
+    _padding: [u32; 3],
+}
+
+/// Screen-space ambient occlusion: darkens creases and corners in the render target, based on
+/// how many of the depths around each pixel are in front of it. This is a post-process that
+/// runs after the main pass, and before anything that shouldn't be darkened (lines, debug
+/// text) is drawn.
+///
+/// Like the main pass, it's drawn with multiview, so each eye is processed from its own layer of
+/// the depth texture.
+pub struct SsaoState {
+    params_buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+}
+impl SsaoState {
+    /// `depth_view` must only include the depth aspect (see [crate::texture::Texture::depth_view])
+    /// of a depth buffer that was rendered with the depth range `z_range`, and has a layer for
+    /// each of the `view_count` views.
+    pub fn new(
+        device: &wgpu::Device,
+        preprocessor: &crate::wgsl::Preprocessor,
+        depth_view: &wgpu::TextureView,
+        color_format: wgpu::TextureFormat,
+        config: SsaoConfig,
+        z_range: Range<f32>,
+        view_count: u32,
+    ) -> Self {
+        let params = SsaoParams {
+            radius: config.radius,
+            intensity: config.intensity,
+            z_near: z_range.start,
+            z_far: z_range.end,
+            sample_count: config.sample_count,
+            _padding: [0; 3],
+        };
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("SSAO Params Buffer"),
+            contents: bytemuck::bytes_of(&params),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("SSAO Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group =
+            Self::create_bind_group(device, &bind_group_layout, depth_view, &params_buffer);
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(Cow::Owned(
+                preprocessor.preprocess("ssao.wgsl").unwrap(),
+            )),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("SSAO Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "ssao_vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "ssao_fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: color_format,
+                    // Multiplies the render target's colour by the occlusion factor, so that it
+                    // can be applied in place.
+                    blend: Some(wgpu::BlendState {
+                        color: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::Zero,
+                            dst_factor: wgpu::BlendFactor::Src,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                        alpha: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::Zero,
+                            dst_factor: wgpu::BlendFactor::One,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: NonZeroU32::new(view_count),
+        });
+
+        Self {
+            params_buffer,
+            bind_group_layout,
+            bind_group,
+            pipeline,
+        }
+    }
+
+    /// Rebinds the depth texture after it has been recreated (e.g. because the window was
+    /// resized).
+    pub fn resize(&mut self, device: &wgpu::Device, depth_view: &wgpu::TextureView) {
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            depth_view,
+            &self.params_buffer,
+        );
+    }
+
+    /// Darkens `rt_view` in place, within `region`.
+    pub fn encode_draw_pass(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        rt_view: &wgpu::TextureView,
+        region: &RenderRegion,
+    ) {
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("SSAO Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: rt_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        region.apply(&mut rpass);
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, &self.bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        depth_view: &wgpu::TextureView,
+        params_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("SSAO Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(depth_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: params_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+}
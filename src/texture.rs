@@ -1,32 +1,35 @@
 use std::num::NonZeroU32;
 
-use crate::types::{DEPTH_FORMAT, VIEW_COUNT};
-
 pub struct Texture {
-    _texture: wgpu::Texture,
+    texture: wgpu::Texture,
     view: wgpu::TextureView,
 }
 
 impl Texture {
     #[allow(dead_code)]
     pub fn from_wgpu(texture: wgpu::Texture, view: wgpu::TextureView) -> Self {
-        Self {
-            _texture: texture,
-            view,
-        }
+        Self { texture, view }
     }
 
+    /// The render target has a layer for each of the `view_count` views. It's single-sampled:
+    /// wgpu 0.13 doesn't support multisampled array textures, which MSAA with multiview would
+    /// need for both the multisampled target and its per-layer resolve target.
+    ///
+    /// The size is independent of any surface, so that render targets can be created for
+    /// offscreen use as well as for the window.
     pub fn new_rt_texture(
         device: &wgpu::Device,
-        config: &wgpu::SurfaceConfiguration,
+        width: u32,
+        height: u32,
+        view_count: u32,
         texture_format: wgpu::TextureFormat,
     ) -> Self {
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label: Some("Render Target Texture"),
             size: wgpu::Extent3d {
-                width: config.width,
-                height: config.height,
-                depth_or_array_layers: 2,
+                width,
+                height,
+                depth_or_array_layers: view_count,
             },
             mip_level_count: 1,
             sample_count: 1,
@@ -38,41 +41,67 @@ impl Texture {
         });
         let view = texture.create_view(&wgpu::TextureViewDescriptor {
             dimension: Some(wgpu::TextureViewDimension::D2Array),
-            array_layer_count: NonZeroU32::new(VIEW_COUNT),
             ..Default::default()
         });
-        Self {
-            _texture: texture,
-            view,
-        }
+        Self { texture, view }
     }
 
-    pub fn new_depth_texture(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+    /// A depth texture with a layer for each of the `view_count` views, which must be the same
+    /// size as the render target it's used with.
+    pub fn new_depth_texture(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        view_count: u32,
+        format: wgpu::TextureFormat,
+    ) -> Self {
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label: Some("Depth Texture"),
             size: wgpu::Extent3d {
-                width: config.width,
-                height: config.height,
-                depth_or_array_layers: 2,
+                width,
+                height,
+                depth_or_array_layers: view_count,
             },
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: DEPTH_FORMAT,
+            format,
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
         });
         let view = texture.create_view(&wgpu::TextureViewDescriptor {
             dimension: Some(wgpu::TextureViewDimension::D2Array),
-            array_layer_count: NonZeroU32::new(VIEW_COUNT),
             ..Default::default()
         });
-        Self {
-            _texture: texture,
-            view,
-        }
+        Self { texture, view }
     }
 
     pub fn view(&self) -> &wgpu::TextureView {
         &self.view
     }
+
+    #[cfg_attr(not(feature = "xr"), allow(dead_code))]
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    /// A view of only the depth aspect of a texture from [Self::new_depth_texture], for sampling
+    /// it in a shader; views that include the stencil aspect can't be sampled.
+    pub fn depth_view(&self) -> wgpu::TextureView {
+        self.texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            aspect: wgpu::TextureAspect::DepthOnly,
+            ..Default::default()
+        })
+    }
+
+    /// A view of a single layer (i.e. a single view) of an array texture, for passes that
+    /// render to that layer alone rather than to every layer with multiview.
+    pub fn layer_view(&self, layer: u32) -> wgpu::TextureView {
+        self.texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2),
+            base_array_layer: layer,
+            array_layer_count: NonZeroU32::new(1),
+            ..Default::default()
+        })
+    }
 }
@@ -1,19 +1,224 @@
 use glam::{Vec3, Vec4};
 
-pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
 pub const VIEW_COUNT: u32 = 2;
 
+/// The depth formats the main pass can use, in order of preference. Formats with a stencil
+/// aspect are preferred, so that stencil masking is available where possible.
+pub const DEPTH_FORMAT_CANDIDATES: &[wgpu::TextureFormat] = &[
+    wgpu::TextureFormat::Depth32FloatStencil8,
+    wgpu::TextureFormat::Depth24UnormStencil8,
+    wgpu::TextureFormat::Depth24PlusStencil8,
+    wgpu::TextureFormat::Depth32Float,
+    wgpu::TextureFormat::Depth24Plus,
+];
+
+/// Features that are enabled if the adapter supports them: the extra stencil formats, to widen
+/// the choice of depth formats, and timestamp queries, to measure the GPU time of frames.
+pub const OPTIONAL_FEATURES: wgpu::Features = wgpu::Features::DEPTH32FLOAT_STENCIL8
+    .union(wgpu::Features::DEPTH24UNORM_STENCIL8)
+    .union(wgpu::Features::TIMESTAMP_QUERY);
+
+/// Whether the depth format has a stencil aspect.
+pub fn has_stencil(format: wgpu::TextureFormat) -> bool {
+    matches!(
+        format,
+        wgpu::TextureFormat::Depth32FloatStencil8
+            | wgpu::TextureFormat::Depth24UnormStencil8
+            | wgpu::TextureFormat::Depth24PlusStencil8
+    )
+}
+
+/// Picks the first of [DEPTH_FORMAT_CANDIDATES] that the adapter can render to, that doesn't
+/// need a feature the device lacks, and that `accept` returns true for. `accept` is used to
+/// restrict the choice further, e.g. to the depth formats the XR runtime supports for its
+/// swapchains, so that the main pass's depth can be handed to the runtime as-is.
+pub fn select_depth_format(
+    adapter: &wgpu::Adapter,
+    device: &wgpu::Device,
+    accept: impl Fn(wgpu::TextureFormat) -> bool,
+) -> anyhow::Result<wgpu::TextureFormat> {
+    DEPTH_FORMAT_CANDIDATES
+        .iter()
+        .copied()
+        .find(|&format| {
+            device
+                .features()
+                .contains(format.describe().required_features)
+                && adapter
+                    .get_texture_format_features(format)
+                    .allowed_usages
+                    .contains(wgpu::TextureUsages::RENDER_ATTACHMENT)
+                && accept(format)
+        })
+        .ok_or_else(|| anyhow::anyhow!("no supported depth format was found"))
+}
+
+/// Checks that an adapter on `backend` with `features` can render with multiview, which every
+/// pass relies on to draw both views at once, and logs how it's supported.
+///
+/// wgpu 0.13 only exposes multiview where it's native (`VK_KHR_multiview`, on Vulkan), and
+/// never emulates it with a draw per view, so there's no slower emulated case to report: the
+/// GPU either broadcasts each draw to every view, or the adapter can't be used. The downlevel
+/// flags don't cover multiview, so the adapter's features are what's checked.
+pub fn check_multiview_support(
+    backend: wgpu::Backend,
+    features: wgpu::Features,
+) -> anyhow::Result<()> {
+    if features.contains(wgpu::Features::MULTIVIEW) {
+        log::info!("multiview is supported natively by the {backend:?} backend");
+        return Ok(());
+    }
+    // There's no path that renders each view with a separate pass to fall back to yet.
+    if backend == wgpu::Backend::Vulkan {
+        anyhow::bail!(
+            "the Vulkan driver doesn't support VK_KHR_multiview, which is needed to render both \
+             views at once"
+        )
+    }
+    anyhow::bail!(
+        "multiview is needed to render both views at once, and this version of wgpu only \
+         supports it on Vulkan, not {backend:?}"
+    )
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Vertex {
     position: [f32; 3],
     color: [f32; 4],
+    normal: [f32; 3],
 }
 impl Vertex {
-    pub fn new(position: Vec3, color: Vec4) -> Self {
+    pub fn new(position: Vec3, normal: Vec3, color: Vec4) -> Self {
         Self {
             position: position.to_array(),
             color: color.to_array(),
+            normal: normal.to_array(),
+        }
+    }
+}
+
+/// A rectangle within a render target, in pixels from the top-left corner.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+impl Rect {
+    /// Whether the rectangle lies entirely within a target of the given size.
+    pub fn fits_within(&self, target_width: u32, target_height: u32) -> bool {
+        self.x
+            .checked_add(self.width)
+            .is_some_and(|right| right <= target_width)
+            && self
+                .y
+                .checked_add(self.height)
+                .is_some_and(|bottom| bottom <= target_height)
+    }
+}
+
+/// What a pass does with the existing contents of its targets.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum LoadBehavior {
+    /// Clear them, so that the pass starts from scratch.
+    #[default]
+    Clear,
+    /// Keep them, so that the pass draws over whatever was last rendered to them, e.g. the
+    /// previous frame. This is only meaningful for targets that are kept between frames; the
+    /// contents of a freshly acquired window surface texture are undefined.
+    Load,
+}
+impl LoadBehavior {
+    /// The load operation for an attachment that's cleared to `clear_value`.
+    pub fn load_op<V>(self, clear_value: V) -> wgpu::LoadOp<V> {
+        match self {
+            Self::Clear => wgpu::LoadOp::Clear(clear_value),
+            Self::Load => wgpu::LoadOp::Load,
+        }
+    }
+}
+
+/// The part of a render target that a pass draws to: geometry is mapped to the `viewport`, and
+/// fragments outside of the `scissor` rectangle are discarded. Both are checked against the size
+/// of the target when the region is created.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RenderRegion {
+    viewport: Rect,
+    scissor: Rect,
+}
+impl RenderRegion {
+    /// The whole of a target of the given size.
+    pub fn full(width: u32, height: u32) -> Self {
+        let rect = Rect {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        };
+        Self {
+            viewport: rect,
+            scissor: rect,
+        }
+    }
+
+    pub fn new(
+        target_width: u32,
+        target_height: u32,
+        viewport: Rect,
+        scissor: Rect,
+    ) -> anyhow::Result<Self> {
+        for (name, rect) in [("viewport", viewport), ("scissor", scissor)] {
+            anyhow::ensure!(
+                rect.fits_within(target_width, target_height),
+                "{name} {rect:?} does not fit within the {target_width}x{target_height} target"
+            );
         }
+        Ok(Self { viewport, scissor })
+    }
+
+    pub fn apply(&self, rpass: &mut wgpu::RenderPass) {
+        let Rect {
+            x,
+            y,
+            width,
+            height,
+        } = self.viewport;
+        rpass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+        let Rect {
+            x,
+            y,
+            width,
+            height,
+        } = self.scissor;
+        rpass.set_scissor_rect(x, y, width, height);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_region_rejects_rects_outside_the_target() {
+        let rect = |x, y, width, height| Rect {
+            x,
+            y,
+            width,
+            height,
+        };
+        let full = rect(0, 0, 100, 50);
+        assert!(RenderRegion::new(100, 50, full, rect(10, 10, 90, 40)).is_ok());
+        assert!(RenderRegion::new(100, 50, rect(1, 0, 100, 50), full).is_err());
+        assert!(RenderRegion::new(100, 50, full, rect(0, u32::MAX, 1, 1)).is_err());
+    }
+
+    #[test]
+    fn multiview_is_only_accepted_where_the_adapter_has_it() {
+        let vulkan = wgpu::Backend::Vulkan;
+        assert!(check_multiview_support(vulkan, wgpu::Features::MULTIVIEW).is_ok());
+        assert!(check_multiview_support(vulkan, wgpu::Features::PUSH_CONSTANTS).is_err());
+        assert!(check_multiview_support(wgpu::Backend::Gl, wgpu::Features::empty()).is_err());
     }
 }
@@ -20,7 +20,7 @@ pub fn preprocess(files: &HashMap<PathBuf, String>, current_file: &str) -> anyho
             .map(|l| match l.strip_prefix("#include ") {
                 Some(filename) => Ok(files
                     .get(&PathBuf::from(filename))
-                    .context("failed to find file")?
+                    .with_context(|| format!("failed to find file {filename:?}"))?
                     .as_ref()),
                 None => Ok(l),
             })
@@ -95,4 +95,15 @@ mod tests {
 
         assert_eq!(preprocess(&files, main_file).unwrap(), expected_output);
     }
+
+    #[test]
+    fn preprocess_reports_missing_include() {
+        let main_file = "#include missing.wgsl\n// and good night!";
+        let files = [(PathBuf::from("main.wgsl"), main_file.to_string())]
+            .into_iter()
+            .collect();
+
+        let error = preprocess(&files, main_file).unwrap_err();
+        assert!(format!("{error:#}").contains("missing.wgsl"));
+    }
 }
@@ -1,19 +1,80 @@
 use std::{
-    ffi::{c_void, CString},
+    collections::BTreeMap,
+    ffi::{c_void, CStr, CString},
     num::NonZeroU32,
+    path::{Path, PathBuf},
+    sync::mpsc,
 };
 
 use anyhow::Context;
 use ash::vk::{self, Handle};
-use glam::{Quat, Vec3};
+use glam::{Quat, Vec2, Vec3};
 use openxr::{self as xr, ViewConfigurationView};
+use serde::{Deserialize, Serialize};
+use wgpu::util::DeviceExt;
 
-use crate::{texture::Texture, types::VIEW_COUNT, WgpuState};
+use crate::{
+    blit_state::ColorCorrection,
+    haptics::{HapticPattern, HapticPlayback},
+    idle_timer::IdleTimer,
+    texture::Texture,
+    types::{LoadBehavior, VIEW_COUNT},
+    WgpuState,
+};
 
 pub const WGPU_COLOR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
 pub const VK_COLOR_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+/// The depth formats that can be shared between wgpu and an XR swapchain. Formats like
+/// `Depth24Plus` aren't included, as the Vulkan format wgpu uses for them depends on the device.
+/// `D16_UNORM`, which is commonly supported by runtimes, has no wgpu equivalent yet.
+const DEPTH_FORMATS: &[(wgpu::TextureFormat, vk::Format)] = &[
+    (wgpu::TextureFormat::Depth32Float, vk::Format::D32_SFLOAT),
+    (
+        wgpu::TextureFormat::Depth32FloatStencil8,
+        vk::Format::D32_SFLOAT_S8_UINT,
+    ),
+    (
+        wgpu::TextureFormat::Depth24UnormStencil8,
+        vk::Format::D24_UNORM_S8_UINT,
+    ),
+];
 
 const VIEW_TYPE: xr::ViewConfigurationType = xr::ViewConfigurationType::PRIMARY_STEREO;
+/// The default bindings of the built-in actions, as (action, interaction profile, input or
+/// output path).
+const DEFAULT_BINDINGS: &[(&str, &str, &str)] = &[
+    (
+        "left_hand",
+        "/interaction_profiles/khr/simple_controller",
+        "/user/hand/left/input/grip/pose",
+    ),
+    (
+        "right_hand",
+        "/interaction_profiles/khr/simple_controller",
+        "/user/hand/right/input/grip/pose",
+    ),
+    (
+        "left_haptic",
+        "/interaction_profiles/khr/simple_controller",
+        "/user/hand/left/output/haptic",
+    ),
+    (
+        "right_haptic",
+        "/interaction_profiles/khr/simple_controller",
+        "/user/hand/right/output/haptic",
+    ),
+];
+/// The colour of the layer shown in place of the scene when a frame fails to render.
+const FALLBACK_COLOR: wgpu::Color = wgpu::Color {
+    r: 0.1,
+    g: 0.1,
+    b: 0.1,
+    a: 1.0,
+};
+/// How far the head or a hand has to move, in metres, to reset the idle timeout.
+const IDLE_DISTANCE_THRESHOLD: f32 = 0.02;
+/// How far the head or a hand has to turn, in degrees, to reset the idle timeout.
+const IDLE_ANGLE_THRESHOLD_DEGREES: f32 = 2.0;
 
 #[derive(Default)]
 pub struct PostFrameData {
@@ -22,14 +83,631 @@ pub struct PostFrameData {
     pub right_hand: Option<(Vec3, Quat)>,
 }
 
-pub fn openxr_pose_to_glam(pose: &openxr::Posef) -> (Vec3, Quat) {
-    // with enough sign errors anything is possible
-    let rotation = {
-        let o = pose.orientation;
-        Quat::from_rotation_x(180.0f32.to_radians()) * glam::quat(o.w, o.z, o.y, o.x)
-    };
-    let translation = glam::vec3(-pose.position.x, pose.position.y, -pose.position.z);
-    (translation, rotation)
+/// The part of the physical space that the user has set up to move around in.
+#[derive(Copy, Clone, Debug)]
+pub struct PlayArea {
+    /// The centre of the play area on the floor, rotated to face the play area's forward
+    /// direction. This is in the scene's coordinate system, like the poses from
+    /// [AxisConvention::pose_to_glam].
+    pub center: (Vec3, Quat),
+    /// The width and depth of the play area's bounding rectangle, in metres, or `None` if the
+    /// runtime doesn't know them (e.g. because the user hasn't set up a boundary).
+    pub size: Option<Vec2>,
+}
+impl PlayArea {
+    /// The corners of the play area's boundary on the floor, in order around it, or `None` if
+    /// its size isn't known. This is the bounding rectangle, as the `openxr` crate doesn't
+    /// expose any extensions for the exact boundary polygon, so the actual boundary may be
+    /// inside it.
+    pub fn outline(&self) -> Option<[Vec3; 4]> {
+        let half = self.size? / 2.0;
+        let (center, rotation) = self.center;
+        Some(
+            [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+                .map(|(x, z)| center + rotation * Vec3::new(x * half.x, 0.0, z * half.y)),
+        )
+    }
+}
+
+/// Configuration for [XrState], provided at initialisation.
+///
+/// This can be saved to and loaded from a TOML file; any fields missing from the file
+/// will use their default values.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct XrConfig {
+    /// Whether to show a marker on the floor at the origin of the stage reference space.
+    pub origin_marker: bool,
+    /// An equirectangular (360 degree) PNG image to show behind the scene, composited by the
+    /// runtime as an equirect layer. Needs `XR_KHR_composition_layer_equirect2`.
+    pub background_image: Option<PathBuf>,
+    /// The radius of the sphere that `background_image` is mapped onto, in metres, centred on
+    /// the stage's origin. 0 makes it infinitely large, so that it doesn't move as the user
+    /// does.
+    pub background_radius: f32,
+    /// Whether to outline the play area's boundary on the floor.
+    pub show_boundary: bool,
+    /// Whether to place the scene relative to the centre of the play area, rather than the
+    /// origin of the stage reference space. These only differ if `reference_space_offset`
+    /// is set.
+    pub center_scene: bool,
+    /// Whether to always render at half the display rate, relying on the compositor to
+    /// reproject the previous frame in between. Half-rate rendering is also enabled
+    /// automatically while the runtime reports thermal or performance pressure.
+    pub half_rate: bool,
+    /// Whether to run the frame's CPU simulation work (animation, building debug geometry)
+    /// before waiting for the frame, rather than after. This overlaps that work with the
+    /// compositor displaying the previous frame, at the cost of it being based on a slightly
+    /// earlier time.
+    pub simulate_before_wait: bool,
+    /// What happens to a hand's indicator while its controller isn't being tracked.
+    pub untracked_hands: UntrackedHands,
+    /// Experimental: render this eye at a reduced resolution, as a rough approximation of
+    /// foveated rendering. The eye is rendered into a corner of its render target, which is
+    /// submitted as a smaller sub-image for the runtime to scale up.
+    pub reduced_eye: Option<Eye>,
+    /// The scale of the reduced eye's resolution on each axis, from 0.25 to 1.
+    pub reduced_eye_scale: f32,
+    /// Whether the eyes share a projection swapchain with a layer each, or each have their own
+    /// swapchain. Some runtimes prefer the latter, and it lets the reduced eye's swapchain be
+    /// smaller. The scene is rendered with multiview either way; only the blit to the headset
+    /// and the submission change.
+    pub eye_layout: EyeLayout,
+    /// How long to wait for the compositor to release a projection swapchain image, in
+    /// milliseconds. If it takes longer, the frame is skipped rather than hanging, and the
+    /// wait is retried on the next frame.
+    pub swapchain_wait_timeout_ms: u64,
+    /// Whether to log the state of every action (and whether each hand is tracked) while
+    /// rendering, to diagnose bindings and interaction profiles.
+    pub log_input: bool,
+    /// How often the input is logged with `log_input`, in milliseconds.
+    pub log_input_interval_ms: u64,
+    /// If set, the session is ended after neither the head nor the hands have moved for this
+    /// many seconds, to save the battery of a headset that has been put down.
+    pub idle_timeout_secs: Option<u64>,
+    // The poses are serialized as tables, which TOML requires to come after all of the plain
+    // values.
+    /// The pose of the stage reference space's origin, relative to the origin the runtime
+    /// provides. This is in OpenXR's coordinate system, and can be used to adjust where content
+    /// sits relative to the physical space (e.g. lowering the origin for a seated experience).
+    #[serde(with = "PosefDef")]
+    pub reference_space_offset: xr::Posef,
+    /// Where the left hand's indicator rests while its controller isn't being tracked, if
+    /// `untracked_hands` is `rest`. This is in OpenXR's coordinate system.
+    #[serde(with = "PosefDef")]
+    pub left_rest_pose: xr::Posef,
+    /// Where the right hand's indicator rests while its controller isn't being tracked, if
+    /// `untracked_hands` is `rest`. This is in OpenXR's coordinate system.
+    #[serde(with = "PosefDef")]
+    pub right_rest_pose: xr::Posef,
+    /// The pose of the left hand's indicator relative to the controller's grip pose, to align
+    /// held content with the hand (e.g. moving a sword's origin from the blade to the hilt).
+    /// This is in OpenXR's coordinate system, relative to the grip.
+    #[serde(with = "PosefDef")]
+    pub left_hand_offset: xr::Posef,
+    /// The pose of the right hand's indicator relative to the controller's grip pose. See
+    /// `left_hand_offset`.
+    #[serde(with = "PosefDef")]
+    pub right_hand_offset: xr::Posef,
+    /// The colour correction applied to the left and right eyes' images, to calibrate out
+    /// differences between the headset's displays. Also applied to each view in the window.
+    pub eye_color_correction: [ColorCorrection; 2],
+    /// The inputs that drive each action, by action name, which replace the action's default
+    /// bindings. Actions that aren't listed keep their defaults, and an empty list leaves an
+    /// action unbound.
+    pub bindings: ActionBindings,
+}
+impl Default for XrConfig {
+    fn default() -> Self {
+        Self {
+            origin_marker: false,
+            background_image: None,
+            background_radius: 0.0,
+            show_boundary: false,
+            center_scene: false,
+            half_rate: false,
+            simulate_before_wait: false,
+            untracked_hands: UntrackedHands::Hide,
+            reduced_eye: None,
+            reduced_eye_scale: 0.5,
+            eye_layout: EyeLayout::Array,
+            swapchain_wait_timeout_ms: 100,
+            log_input: false,
+            log_input_interval_ms: 500,
+            idle_timeout_secs: None,
+            reference_space_offset: xr::Posef::IDENTITY,
+            // At the sides of someone standing at the origin, facing forward.
+            left_rest_pose: xr::Posef {
+                orientation: xr::Quaternionf::IDENTITY,
+                position: xr::Vector3f {
+                    x: -0.3,
+                    y: 0.8,
+                    z: 0.0,
+                },
+            },
+            right_rest_pose: xr::Posef {
+                orientation: xr::Quaternionf::IDENTITY,
+                position: xr::Vector3f {
+                    x: 0.3,
+                    y: 0.8,
+                    z: 0.0,
+                },
+            },
+            left_hand_offset: xr::Posef::IDENTITY,
+            right_hand_offset: xr::Posef::IDENTITY,
+            eye_color_correction: [ColorCorrection::default(); 2],
+            bindings: default_bindings(),
+        }
+    }
+}
+
+/// The inputs bound to each action, by action name.
+pub type ActionBindings = BTreeMap<String, Vec<InputBinding>>;
+
+/// An input that drives an action when using a particular interaction profile.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputBinding {
+    /// The interaction profile, e.g. `/interaction_profiles/khr/simple_controller`.
+    pub profile: String,
+    /// The input's path, e.g. `/user/hand/left/input/select/click`.
+    pub path: String,
+}
+impl InputBinding {
+    fn new(profile: &str, path: &str) -> Self {
+        Self {
+            profile: profile.to_string(),
+            path: path.to_string(),
+        }
+    }
+
+    /// Checks that the profile and path are well-formed OpenXR paths of the right kind. Whether
+    /// the profile actually has the input is only known to the runtime.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for (path, prefix) in [
+            (&self.profile, "/interaction_profiles/"),
+            (&self.path, "/user/"),
+        ] {
+            anyhow::ensure!(
+                path.starts_with(prefix),
+                "{path:?} should start with {prefix:?}"
+            );
+            // Paths are made of non-empty components of lowercase letters, numbers, `-`, `_`
+            // and `.`, and components can't be `.` or `..`.
+            let well_formed = path[1..].split('/').all(|component| {
+                !matches!(component, "" | "." | "..")
+                    && component
+                        .chars()
+                        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "-_.".contains(c))
+            });
+            anyhow::ensure!(well_formed, "{path:?} is not a well-formed OpenXR path");
+        }
+        Ok(())
+    }
+}
+
+/// The bindings of the built-in actions.
+fn default_bindings() -> ActionBindings {
+    let mut bindings = ActionBindings::new();
+    for (action, profile, path) in DEFAULT_BINDINGS {
+        bindings
+            .entry(action.to_string())
+            .or_default()
+            .push(InputBinding::new(profile, path));
+    }
+    bindings
+}
+impl XrConfig {
+    /// The pose of the hand indicator at `index` (0 for left, 1 for right) for the given tracked
+    /// pose, or `None` if the indicator should be hidden.
+    pub fn hand_pose(
+        &self,
+        index: usize,
+        tracked: Option<(Vec3, Quat)>,
+        axis_convention: &AxisConvention,
+    ) -> Option<(Vec3, Quat)> {
+        tracked.or_else(|| match self.untracked_hands {
+            UntrackedHands::Hide => None,
+            UntrackedHands::Rest => Some(
+                axis_convention.pose_to_glam([&self.left_rest_pose, &self.right_rest_pose][index]),
+            ),
+        })
+    }
+}
+
+/// What happens to a hand's indicator while its controller isn't being tracked, so that it
+/// doesn't freeze in midair.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum UntrackedHands {
+    /// Hide the indicator until tracking resumes
+    Hide,
+    /// Move the indicator to its rest pose
+    Rest,
+}
+/// One of the two eyes of a stereo headset.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Eye {
+    /// The left eye
+    Left,
+    /// The right eye
+    Right,
+}
+
+/// How the eyes' images are laid out in the projection swapchains.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum EyeLayout {
+    /// One swapchain, with a layer per eye
+    Array,
+    /// A swapchain per eye, each with a single layer
+    Separate,
+}
+impl EyeLayout {
+    /// The number of layers in each projection swapchain.
+    fn array_size(self) -> u32 {
+        match self {
+            Self::Array => VIEW_COUNT,
+            Self::Separate => 1,
+        }
+    }
+}
+
+impl XrConfig {
+    /// The scale of each view's resolution, relative to the render target. See
+    /// [Self::reduced_eye].
+    pub fn view_scales(&self) -> anyhow::Result<[f32; 2]> {
+        let mut scales = [1.0; 2];
+        if let Some(eye) = self.reduced_eye {
+            anyhow::ensure!(
+                (0.25..=1.0).contains(&self.reduced_eye_scale),
+                "the reduced eye scale must be between 0.25 and 1, not {}",
+                self.reduced_eye_scale
+            );
+            scales[eye as usize] = self.reduced_eye_scale;
+        }
+        Ok(scales)
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read XR config from {path:?}"))?;
+        toml::from_str(&contents).with_context(|| format!("failed to parse XR config {path:?}"))
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        std::fs::write(path, toml::to_string_pretty(self)?)
+            .with_context(|| format!("failed to write XR config to {path:?}"))
+    }
+
+    /// Saves only the eye colour correction to the config file at `path`, keeping the file's
+    /// other settings rather than replacing them with this config's, which may have been
+    /// overridden on the command line.
+    pub fn save_eye_color_correction(&self, path: &Path) -> anyhow::Result<()> {
+        let mut config = Self::load(path)?;
+        config.eye_color_correction = self.eye_color_correction;
+        config.save(path)
+    }
+}
+
+// Mirrors of the OpenXR types used in [XrConfig], so that they can be (de)serialized.
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "xr::Posef")]
+struct PosefDef {
+    #[serde(with = "QuaternionfDef")]
+    orientation: xr::Quaternionf,
+    #[serde(with = "Vector3fDef")]
+    position: xr::Vector3f,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "xr::Quaternionf")]
+struct QuaternionfDef {
+    x: f32,
+    y: f32,
+    z: f32,
+    w: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "xr::Vector3f")]
+struct Vector3fDef {
+    x: f32,
+    y: f32,
+    z: f32,
+}
+
+/// The composition layer types that the runtime accepts, and how many layers it can composite
+/// at once. Submitting a layer type whose extension isn't enabled is an error, which some
+/// runtimes treat as fatal, so this should be checked before enabling a feature that needs one.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SupportedLayerTypes {
+    /// Projection layers are part of the core specification, so they're always available.
+    pub projection: bool,
+    /// Quad layers are also part of the core specification, so they're always available.
+    pub quad: bool,
+    /// `XR_KHR_composition_layer_depth`
+    pub depth: bool,
+    /// `XR_KHR_composition_layer_cylinder`
+    pub cylinder: bool,
+    /// `XR_KHR_composition_layer_equirect`
+    pub equirect: bool,
+    /// `XR_KHR_composition_layer_equirect2`
+    pub equirect2: bool,
+    /// `XR_FB_passthrough`
+    pub passthrough: bool,
+    /// The maximum number of layers that can be submitted in a single frame, including the
+    /// projection layer.
+    pub max_layer_count: u32,
+}
+impl SupportedLayerTypes {
+    fn new(extensions: &xr::ExtensionSet, system_properties: &xr::SystemProperties) -> Self {
+        Self {
+            projection: true,
+            quad: true,
+            depth: extensions.khr_composition_layer_depth,
+            cylinder: extensions.khr_composition_layer_cylinder,
+            equirect: extensions.khr_composition_layer_equirect,
+            equirect2: extensions.khr_composition_layer_equirect2,
+            passthrough: extensions.fb_passthrough,
+            max_layer_count: system_properties.graphics_properties.max_layer_count,
+        }
+    }
+}
+
+/// Draws the contents of a quad layer into the given view of its swapchain image, for a frame
+/// that will be displayed at the given time.
+pub type QuadLayerRenderFn =
+    Box<dyn FnMut(&mut wgpu::CommandEncoder, &wgpu::TextureView, xr::Time)>;
+
+/// Describes a quad composition layer: a flat rectangle placed in the stage reference space,
+/// which is composited by the runtime on top of the projection layer.
+pub struct QuadLayerDesc {
+    pub width: u32,
+    pub height: u32,
+    /// The pose of the centre of the quad; the quad faces towards +Z.
+    pub pose: xr::Posef,
+    /// The size of the quad, in metres.
+    pub size: xr::Extent2Df,
+    /// If true, the layer's swapchain is created with `STATIC_IMAGE`. The runtime will only
+    /// provide a single image, which is rendered once and then reused for every frame; this
+    /// lets the runtime avoid work for content that never changes.
+    pub static_image: bool,
+}
+
+/// Describes an equirect composition layer: an equirectangular (360 degree) image mapped onto a
+/// sphere around the stage's origin, which is composited by the runtime behind the projection
+/// layer. Unlike a skybox drawn into the scene, it stays stable while the compositor reprojects.
+pub struct EquirectLayerDesc {
+    pub width: u32,
+    pub height: u32,
+    /// The image's sRGB RGBA8 pixels, as tightly packed rows, top row first.
+    pub rgba: Vec<u8>,
+    /// The radius of the sphere, in metres. 0 makes it infinitely large, so that the image
+    /// doesn't move as the user does.
+    pub radius: f32,
+}
+
+struct QuadLayer {
+    desc: QuadLayerDesc,
+    swapchain: Swapchain,
+    render: QuadLayerRenderFn,
+    /// Whether an image has been acquired this frame, and must be released after submission.
+    acquired: bool,
+    /// Whether an image has been released, and there is something to submit.
+    has_image: bool,
+}
+
+/// Collects the actions in an action set and their suggested bindings, and attaches the action
+/// set to a session.
+///
+/// OpenXR only allows an action set to be attached once, and actions can't be created or bound
+/// after that, so all actions must be registered first. Registering an action after
+/// [Self::attach] is an error here, rather than an obscure failure from the runtime.
+///
+/// Bindings from the user's configuration take precedence over the defaults given when an
+/// action is registered.
+pub struct ActionRegistry {
+    instance: xr::Instance,
+    action_set: xr::ActionSet,
+    user_bindings: ActionBindings,
+    actions: Vec<RegisteredAction>,
+    attached: bool,
+}
+
+struct RegisteredAction {
+    name: String,
+    action: Box<dyn BindableAction>,
+    /// The suggested bindings, as pairs of interaction profile and input path.
+    bindings: Vec<(xr::Path, xr::Path)>,
+}
+
+/// Lets actions of different types be bound and logged together.
+trait BindableAction {
+    fn binding(&self, path: xr::Path) -> xr::Binding<'_>;
+
+    /// The action's state as of the last sync, for logging.
+    fn describe_state(&self, session: &xr::Session<xr::Vulkan>) -> xr::Result<String>;
+}
+impl<T: DescribeState> BindableAction for xr::Action<T> {
+    fn binding(&self, path: xr::Path) -> xr::Binding<'_> {
+        xr::Binding::new(self, path)
+    }
+
+    fn describe_state(&self, session: &xr::Session<xr::Vulkan>) -> xr::Result<String> {
+        T::describe_state(self, session)
+    }
+}
+
+/// The action types whose states can be logged, which are all of them.
+pub trait DescribeState: xr::ActionTy {
+    fn describe_state(
+        action: &xr::Action<Self>,
+        session: &xr::Session<xr::Vulkan>,
+    ) -> xr::Result<String>;
+}
+impl DescribeState for bool {
+    fn describe_state(
+        action: &xr::Action<Self>,
+        session: &xr::Session<xr::Vulkan>,
+    ) -> xr::Result<String> {
+        describe_input(action, session, |value| value.to_string())
+    }
+}
+impl DescribeState for f32 {
+    fn describe_state(
+        action: &xr::Action<Self>,
+        session: &xr::Session<xr::Vulkan>,
+    ) -> xr::Result<String> {
+        describe_input(action, session, |value| format!("{value:.2}"))
+    }
+}
+impl DescribeState for xr::Vector2f {
+    fn describe_state(
+        action: &xr::Action<Self>,
+        session: &xr::Session<xr::Vulkan>,
+    ) -> xr::Result<String> {
+        describe_input(action, session, |value| {
+            format!("({:.2}, {:.2})", value.x, value.y)
+        })
+    }
+}
+/// Poses are located rather than read, so only whether the action is active is logged.
+impl DescribeState for xr::Posef {
+    fn describe_state(
+        action: &xr::Action<Self>,
+        session: &xr::Session<xr::Vulkan>,
+    ) -> xr::Result<String> {
+        Ok(if action.is_active(session, xr::Path::NULL)? {
+            "active"
+        } else {
+            "inactive"
+        }
+        .to_string())
+    }
+}
+/// Haptic outputs don't have a state to read.
+impl DescribeState for xr::Haptic {
+    fn describe_state(
+        _action: &xr::Action<Self>,
+        _session: &xr::Session<xr::Vulkan>,
+    ) -> xr::Result<String> {
+        Ok("output".to_string())
+    }
+}
+
+fn describe_input<T: xr::ActionInput>(
+    action: &xr::Action<T>,
+    session: &xr::Session<xr::Vulkan>,
+    describe_value: impl FnOnce(T) -> String,
+) -> xr::Result<String> {
+    let state = action.state(session, xr::Path::NULL)?;
+    Ok(if state.is_active {
+        describe_value(state.current_state)
+    } else {
+        "inactive".to_string()
+    })
+}
+
+/// The rotation and position of `pose`, without changing the coordinate system (unlike
+/// [AxisConvention::pose_to_glam]), for doing arithmetic on poses in OpenXR's.
+fn pose_parts(pose: &xr::Posef) -> (Quat, Vec3) {
+    let (o, p) = (pose.orientation, pose.position);
+    (
+        Quat::from_xyzw(o.x, o.y, o.z, o.w),
+        Vec3::new(p.x, p.y, p.z),
+    )
+}
+
+/// The inverse of [pose_parts].
+fn pose_from_parts(rotation: Quat, position: Vec3) -> xr::Posef {
+    xr::Posef {
+        orientation: xr::Quaternionf {
+            x: rotation.x,
+            y: rotation.y,
+            z: rotation.z,
+            w: rotation.w,
+        },
+        position: xr::Vector3f {
+            x: position.x,
+            y: position.y,
+            z: position.z,
+        },
+    }
+}
+
+/// The pose that undoes `pose`, in OpenXR's coordinate system.
+fn invert_pose(pose: &xr::Posef) -> xr::Posef {
+    let (rotation, position) = pose_parts(pose);
+    let rotation = rotation.inverse();
+    pose_from_parts(rotation, rotation * -position)
+}
+
+/// `local` relative to `pose`, in OpenXR's coordinate system.
+fn compose_poses(pose: &xr::Posef, local: &xr::Posef) -> xr::Posef {
+    let ((rotation, position), (local_rotation, local_position)) =
+        (pose_parts(pose), pose_parts(local));
+    pose_from_parts(
+        rotation * local_rotation,
+        position + rotation * local_position,
+    )
+}
+
+/// How OpenXR's coordinate system maps onto the scene's: which of OpenXR's axes are negated.
+/// OpenXR's is right-handed, with +Y up and -Z forward, and the scene looks down +Z, so by
+/// default X and Z are negated, which turns the user around to face the scene without mirroring
+/// anything.
+///
+/// Negating an odd number of axes mirrors the world, which is almost certainly wrong, but the
+/// convention can be changed at runtime to see what each choice of signs does.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AxisConvention {
+    pub negate: [bool; 3],
+}
+impl Default for AxisConvention {
+    fn default() -> Self {
+        Self {
+            negate: [true, false, true],
+        }
+    }
+}
+impl AxisConvention {
+    /// The sign applied to each axis.
+    fn signs(&self) -> Vec3 {
+        Vec3::from(self.negate.map(|negate| if negate { -1.0 } else { 1.0 }))
+    }
+
+    /// Converts an OpenXR pose into the scene's coordinate system.
+    pub fn pose_to_glam(&self, pose: &xr::Posef) -> (Vec3, Quat) {
+        let (o, p) = (pose.orientation, pose.position);
+        let signs = self.signs();
+        // Negating axes conjugates the rotation, which negates the same components of the
+        // quaternion's axis, unless the axes are mirrored: mirroring also flips the sense of
+        // rotation, which negates the whole axis.
+        let handedness = signs.x * signs.y * signs.z;
+        let axis = Vec3::new(o.x, o.y, o.z) * signs * handedness;
+        let rotation = Quat::from_xyzw(axis.x, axis.y, axis.z, o.w);
+        (Vec3::new(p.x, p.y, p.z) * signs, rotation)
+    }
+
+    /// The direction that a view with an identity orientation looks in, in the scene's
+    /// coordinate system.
+    pub fn forward(&self) -> Vec3 {
+        -Vec3::Z * self.signs()
+    }
+
+    /// The up direction of a view with an identity orientation, in the scene's coordinate
+    /// system.
+    pub fn up(&self) -> Vec3 {
+        Vec3::Y * self.signs()
+    }
+}
+
+/// A controller, for the methods that act on one of them.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Hand {
+    Left,
+    Right,
 }
 
 pub struct XrState {
@@ -37,22 +715,80 @@ pub struct XrState {
     environment_blend_mode: xr::EnvironmentBlendMode,
     session: xr::Session<xr::Vulkan>,
     session_running: bool,
+    session_state: xr::SessionState,
     frame_wait: xr::FrameWaiter,
     frame_stream: xr::FrameStream<xr::Vulkan>,
-    action_set: xr::ActionSet,
+    actions: ActionRegistry,
     right_action: xr::Action<xr::Posef>,
     left_action: xr::Action<xr::Posef>,
+    /// The vibration outputs of the left and right controllers.
+    haptic_actions: [xr::Action<xr::Haptic>; 2],
+    /// The haptic pattern being played on each controller, if any.
+    haptic_playback: [Option<HapticPlayback>; 2],
     right_space: xr::Space,
     left_space: xr::Space,
     stage: xr::Space,
+    /// The pose [Self::stage] was created at within the runtime's stage reference space.
+    reference_space_offset: xr::Posef,
+    /// The poses of the hands' indicators relative to their grips. See
+    /// [XrConfig::left_hand_offset].
+    hand_offsets: [xr::Posef; 2],
+    view_space: xr::Space,
     event_storage: xr::EventDataBuffer,
+    hand_paths: [xr::Path; 2],
+    interaction_profiles: [Option<String>; 2],
     views: Vec<openxr::ViewConfigurationView>,
-    swapchain: Option<Swapchain>,
+    eye_layout: EyeLayout,
+    /// The projection swapchains, laid out according to [Self::eye_layout], or empty until
+    /// they have been created.
+    swapchains: Vec<ProjectionSwapchain>,
+    /// Receives the projection swapchains while they're being created on another thread.
+    pending_swapchains: Option<mpsc::Receiver<anyhow::Result<Vec<xr::Swapchain<xr::Vulkan>>>>>,
+    /// Shown in place of the scene until the projection swapchain is ready.
+    loading_layer: Option<QuadLayer>,
+    quad_layers: Vec<QuadLayer>,
+    /// Submitted behind the projection layer.
+    background_layer: Option<EquirectLayer>,
+    /// Submitted in place of everything else when a frame fails to render.
+    fallback_layer: QuadLayer,
+    swapchain_wait_timeout: xr::Duration,
+    /// Whether waiting for the projection image timed out this frame, so nothing was rendered
+    /// to the headset.
+    wait_timed_out: bool,
+    supported_layer_types: SupportedLayerTypes,
+    half_rate: bool,
+    /// Whether the runtime has reported that the CPU and GPU (respectively) are under pressure.
+    perf_pressure: [bool; 2],
+    /// Whether this frame is skipped, with the previous frame being resubmitted for the
+    /// compositor to reproject.
+    reprojecting: bool,
+    /// The views that the contents of the projection swapchain were last rendered with.
+    last_views: Option<Vec<openxr::View>>,
+    /// The scale of each view's sub-image, relative to the projection swapchain.
+    view_scales: [f32; 2],
+    /// Whether a change to the stage reference space is pending, which may change the play area.
+    play_area_changed: bool,
+    axis_convention: AxisConvention,
+    /// How often to log the input, if it's logged at all.
+    input_log_interval: Option<xr::Duration>,
+    /// The display time of the last frame that the input was logged for.
+    last_input_log: Option<xr::Time>,
+    idle_timer: Option<IdleTimer>,
+    /// Whether the session has been asked to end, after which the app should exit once it's
+    /// in the `EXITING` state.
+    exit_requested: bool,
 }
 impl XrState {
+    /// Creates the OpenXR session and the wgpu device that renders to it.
+    ///
+    /// `register_actions` is called with the action set after the built-in controller pose
+    /// actions have been registered, to add any other actions the application needs. The
+    /// action set is then attached to the session, after which no more actions can be added.
     pub fn initialize_with_wgpu(
         wgpu_features: wgpu::Features,
         wgpu_limits: wgpu::Limits,
+        config: &XrConfig,
+        register_actions: impl FnOnce(&mut ActionRegistry) -> anyhow::Result<()>,
     ) -> anyhow::Result<(WgpuState, XrState)> {
         use wgpu_hal::{api::Vulkan as V, Api};
 
@@ -63,6 +799,20 @@ impl XrState {
 
         let mut enabled_extensions = xr::ExtensionSet::default();
         enabled_extensions.khr_vulkan_enable2 = true;
+        // Used to find out about thermal and performance pressure, so that we can fall back
+        // to rendering at a reduced rate.
+        enabled_extensions.ext_performance_settings = available_extensions.ext_performance_settings;
+        // Enable the extensions for the other composition layer types where available, so that
+        // features using them can check `supported_layer_types` instead of the runtime.
+        enabled_extensions.khr_composition_layer_depth =
+            available_extensions.khr_composition_layer_depth;
+        enabled_extensions.khr_composition_layer_cylinder =
+            available_extensions.khr_composition_layer_cylinder;
+        enabled_extensions.khr_composition_layer_equirect =
+            available_extensions.khr_composition_layer_equirect;
+        enabled_extensions.khr_composition_layer_equirect2 =
+            available_extensions.khr_composition_layer_equirect2;
+        enabled_extensions.fb_passthrough = available_extensions.fb_passthrough;
         #[cfg(target_os = "android")]
         {
             enabled_extensions.khr_android_create_instance = true;
@@ -93,6 +843,9 @@ impl XrState {
             }
         );
 
+        let supported_layer_types = SupportedLayerTypes::new(&enabled_extensions, &system_props);
+        log::info!("supported composition layers: {supported_layer_types:?}");
+
         let environment_blend_mode =
             xr_instance.enumerate_environment_blend_modes(xr_system_id, VIEW_TYPE)?[0];
         let vk_target_version = vk::make_api_version(0, 1, 1, 0);
@@ -158,6 +911,18 @@ impl XrState {
 
         let vk_device_properties =
             unsafe { vk_instance.get_physical_device_properties(vk_physical_device) };
+        // The runtime picks the GPU that the headset is connected to, which may not be the one
+        // that wgpu would have picked (e.g. on laptops with both an integrated and a discrete
+        // GPU), so it's logged to make it easy to check.
+        let vk_device_name =
+            unsafe { CStr::from_ptr(vk_device_properties.device_name.as_ptr()) }.to_string_lossy();
+        log::info!(
+            "the XR runtime selected {vk_device_name:?} ({:?}, Vulkan {}.{}.{})",
+            vk_device_properties.device_type,
+            vk::api_version_major(vk_device_properties.api_version),
+            vk::api_version_minor(vk_device_properties.api_version),
+            vk::api_version_patch(vk_device_properties.api_version),
+        );
         if vk_device_properties.api_version < vk_target_version {
             unsafe { vk_instance.destroy_instance(None) }
             panic!("Vulkan physical device doesn't support version 1.1");
@@ -178,6 +943,12 @@ impl XrState {
         let wgpu_exposed_adapter = wgpu_vk_instance
             .expose_adapter(vk_physical_device)
             .context("failed to expose adapter")?;
+        crate::types::check_multiview_support(
+            wgpu::Backend::Vulkan,
+            wgpu_exposed_adapter.features,
+        )?;
+        let wgpu_features =
+            wgpu_features | (wgpu_exposed_adapter.features & crate::types::OPTIONAL_FEATURES);
 
         let enabled_extensions = wgpu_exposed_adapter
             .adapter
@@ -275,31 +1046,97 @@ impl XrState {
                 },
             )
         }?;
-        let action_set = xr_instance.create_action_set("input", "input pose information", 0)?;
-        let right_action =
-            action_set.create_action::<xr::Posef>("right_hand", "Right Hand Controller", &[])?;
-        let left_action =
-            action_set.create_action::<xr::Posef>("left_hand", "Left Hand Controller", &[])?;
-        xr_instance.suggest_interaction_profile_bindings(
-            xr_instance.string_to_path("/interaction_profiles/khr/simple_controller")?,
-            &[
-                xr::Binding::new(
-                    &right_action,
-                    xr_instance.string_to_path("/user/hand/right/input/grip/pose")?,
-                ),
-                xr::Binding::new(
-                    &left_action,
-                    xr_instance.string_to_path("/user/hand/left/input/grip/pose")?,
-                ),
-            ],
+
+        // Actions are registered, then attached, and only then are their spaces created.
+        let mut actions = ActionRegistry::new(
+            &xr_instance,
+            "input",
+            "input pose information",
+            &config.bindings,
         )?;
-        session.attach_action_sets(&[&action_set])?;
+        let defaults_for = |action: &str| -> Vec<(&str, &str)> {
+            DEFAULT_BINDINGS
+                .iter()
+                .filter(|(a, _, _)| *a == action)
+                .map(|(_, profile, path)| (*profile, *path))
+                .collect()
+        };
+        let right_action = actions.register::<xr::Posef>(
+            "right_hand",
+            "Right Hand Controller",
+            &defaults_for("right_hand"),
+        )?;
+        let left_action = actions.register::<xr::Posef>(
+            "left_hand",
+            "Left Hand Controller",
+            &defaults_for("left_hand"),
+        )?;
+        let haptic_actions = [
+            actions.register::<xr::Haptic>(
+                "left_haptic",
+                "Left Hand Vibration",
+                &defaults_for("left_haptic"),
+            )?,
+            actions.register::<xr::Haptic>(
+                "right_haptic",
+                "Right Hand Vibration",
+                &defaults_for("right_haptic"),
+            )?,
+        ];
+        register_actions(&mut actions)?;
+        actions.attach(&session)?;
+
         let right_space =
             right_action.create_space(session.clone(), xr::Path::NULL, xr::Posef::IDENTITY)?;
         let left_space =
             left_action.create_space(session.clone(), xr::Path::NULL, xr::Posef::IDENTITY)?;
-        let stage =
-            session.create_reference_space(xr::ReferenceSpaceType::STAGE, xr::Posef::IDENTITY)?;
+        let stage = session
+            .create_reference_space(xr::ReferenceSpaceType::STAGE, config.reference_space_offset)?;
+        let view_space =
+            session.create_reference_space(xr::ReferenceSpaceType::VIEW, xr::Posef::IDENTITY)?;
+
+        // Created up front, so that it can be shown even if creating resources is what failed.
+        // It's a large quad locked to the head, which covers the whole field of view.
+        let fallback_layer = QuadLayer::new(
+            &session,
+            &wgpu_device,
+            QuadLayerDesc {
+                width: 16,
+                height: 16,
+                pose: xr::Posef {
+                    orientation: xr::Quaternionf::IDENTITY,
+                    position: xr::Vector3f {
+                        x: 0.0,
+                        y: 0.0,
+                        z: -1.0,
+                    },
+                },
+                size: xr::Extent2Df {
+                    width: 10.0,
+                    height: 10.0,
+                },
+                static_image: true,
+            },
+            Box::new(|encoder, view, _| {
+                encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Fallback Layer"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(FALLBACK_COLOR),
+                            store: true,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                });
+            }),
+        )?;
+
+        let hand_paths = [
+            xr_instance.string_to_path("/user/hand/left")?,
+            xr_instance.string_to_path("/user/hand/right")?,
+        ];
 
         let views = xr_instance
             .enumerate_view_configuration_views(xr_system_id, VIEW_TYPE)
@@ -319,17 +1156,55 @@ impl XrState {
                 environment_blend_mode,
                 session,
                 session_running: false,
+                session_state: xr::SessionState::IDLE,
                 frame_wait,
                 frame_stream,
-                action_set,
+                actions,
                 right_action,
                 left_action,
+                haptic_actions,
+                haptic_playback: [None, None],
                 right_space,
                 left_space,
                 stage,
+                reference_space_offset: config.reference_space_offset,
+                hand_offsets: [config.left_hand_offset, config.right_hand_offset],
+                view_space,
                 event_storage: xr::EventDataBuffer::new(),
+                hand_paths,
+                interaction_profiles: [None, None],
                 views,
-                swapchain: None,
+                eye_layout: config.eye_layout,
+                swapchains: vec![],
+                pending_swapchains: None,
+                loading_layer: None,
+                quad_layers: vec![],
+                background_layer: None,
+                fallback_layer,
+                swapchain_wait_timeout: xr::Duration::from_nanos(
+                    config.swapchain_wait_timeout_ms as i64 * 1_000_000,
+                ),
+                wait_timed_out: false,
+                supported_layer_types,
+                half_rate: config.half_rate,
+                perf_pressure: [false, false],
+                reprojecting: false,
+                last_views: None,
+                view_scales: config.view_scales()?,
+                play_area_changed: false,
+                axis_convention: AxisConvention::default(),
+                input_log_interval: config.log_input.then(|| {
+                    xr::Duration::from_nanos(config.log_input_interval_ms as i64 * 1_000_000)
+                }),
+                last_input_log: None,
+                idle_timer: config.idle_timeout_secs.map(|secs| {
+                    IdleTimer::new(
+                        std::time::Duration::from_secs(secs),
+                        IDLE_DISTANCE_THRESHOLD,
+                        IDLE_ANGLE_THRESHOLD_DEGREES.to_radians(),
+                    )
+                }),
+                exit_requested: false,
             },
         ))
     }
@@ -342,6 +1217,7 @@ impl XrState {
                     // Session state change is where we can begin and end sessions, as well as
                     // find quit messages!
                     log::info!("entered state {:?}", e.state());
+                    self.session_state = e.state();
                     match e.state() {
                         xr::SessionState::READY => {
                             self.session.begin(VIEW_TYPE)?;
@@ -360,6 +1236,48 @@ impl XrState {
                 InstanceLossPending(_) => {
                     return Ok(None);
                 }
+                InteractionProfileChanged(_) => {
+                    for (path, profile) in self
+                        .hand_paths
+                        .iter()
+                        .zip(self.interaction_profiles.iter_mut())
+                    {
+                        let profile_path = self.session.current_interaction_profile(*path)?;
+                        *profile = if profile_path == xr::Path::NULL {
+                            None
+                        } else {
+                            Some(self.xr_instance.path_to_string(profile_path)?)
+                        };
+                    }
+                    log::info!(
+                        "interaction profiles changed: {:?}",
+                        self.interaction_profiles
+                    );
+                }
+                PerfSettingsEXT(e) => {
+                    let index = match e.domain() {
+                        xr::PerfSettingsDomainEXT::CPU => 0,
+                        _ => 1,
+                    };
+                    self.perf_pressure[index] =
+                        e.to_level() != xr::PerfSettingsNotificationLevelEXT::NORMAL;
+                    // `self` can't be borrowed as a whole while the event is alive.
+                    let half_rate = self.half_rate || self.perf_pressure.iter().any(|p| *p);
+                    log::warn!(
+                        "performance notification for {:?}/{:?}: {:?} -> {:?}; half-rate: {}",
+                        e.domain(),
+                        e.sub_domain(),
+                        e.from_level(),
+                        e.to_level(),
+                        half_rate
+                    );
+                }
+                ReferenceSpaceChangePending(e)
+                    if e.reference_space_type() == xr::ReferenceSpaceType::STAGE =>
+                {
+                    log::info!("the stage reference space is changing");
+                    self.play_area_changed = true;
+                }
                 EventsLost(e) => {
                     log::warn!("lost {} events", e.lost_event_count());
                 }
@@ -379,6 +1297,13 @@ impl XrState {
         // Must be called before any rendering is done!
         self.frame_stream.begin()?;
 
+        // When rendering at half rate, every other frame reuses the last image we submitted.
+        // We can only do this once the projection swapchain has an image to reuse.
+        if xr_frame_state.should_render {
+            self.reprojecting =
+                self.is_half_rate() && !self.reprojecting && self.last_views.is_some();
+        }
+
         Ok(Some(xr_frame_state))
     }
 
@@ -389,7 +1314,6 @@ impl XrState {
         encoder: &mut wgpu::CommandEncoder,
         blit_state: &crate::BlitState,
     ) -> anyhow::Result<PostFrameData> {
-        use wgpu_hal::{api::Vulkan as V, Api};
         if !xr_frame_state.should_render {
             self.frame_stream.end(
                 xr_frame_state.predicted_display_time,
@@ -398,136 +1322,148 @@ impl XrState {
             )?;
             return Ok(PostFrameData::default());
         }
+        self.update_haptics(xr_frame_state.predicted_display_time)?;
 
-        let swapchain = self.swapchain.get_or_insert_with(|| {
-            // Now we need to find all the viewpoints we need to take care of! This is a
-            // property of the view configuration type; in this example we use PRIMARY_STEREO,
-            // so we should have 2 viewpoints.
+        if !self.fallback_layer.has_image && !self.fallback_layer.acquired {
+            self.fallback_layer
+                .render(encoder, xr_frame_state.predicted_display_time)?;
+        }
 
-            // Create a swapchain for the viewpoints! A swapchain is a set of texture buffers
-            // used for displaying to screen, typically this is a backbuffer and a front buffer,
-            // one for rendering data to, and one for displaying on-screen.
-            let resolution = vk::Extent2D {
-                width: self.views[0].recommended_image_rect_width,
-                height: self.views[0].recommended_image_rect_height,
-            };
-            let handle = self
-                .session
-                .create_swapchain(&xr::SwapchainCreateInfo {
-                    create_flags: xr::SwapchainCreateFlags::EMPTY,
-                    usage_flags: xr::SwapchainUsageFlags::COLOR_ATTACHMENT
-                        | xr::SwapchainUsageFlags::SAMPLED,
-                    format: VK_COLOR_FORMAT.as_raw() as _,
-                    // The Vulkan graphics pipeline we create is not set up for multisampling,
-                    // so we hardcode this to 1. If we used a proper multisampling setup, we
-                    // could set this to `views[0].recommended_swapchain_sample_count`.
-                    sample_count: 1,
-                    width: resolution.width,
-                    height: resolution.height,
-                    face_count: 1,
-                    array_size: VIEW_COUNT,
-                    mip_count: 1,
-                })
-                .unwrap();
-
-            // We'll want to track our own information about the swapchain, so we can draw stuff
-            // onto it! We'll also create a buffer for each generated texture here as well.
-            let images = handle.enumerate_images().unwrap();
-            Swapchain {
-                handle,
-                resolution,
-                buffers: images
-                    .into_iter()
-                    .map(|color_image| {
-                        let color_image = vk::Image::from_raw(color_image);
-                        let wgpu_hal_texture = unsafe {
-                            <V as Api>::Device::texture_from_raw(
-                                color_image,
-                                &wgpu_hal::TextureDescriptor {
-                                    label: Some("VR Swapchain"),
-                                    size: wgpu::Extent3d {
-                                        width: resolution.width,
-                                        height: resolution.height,
-                                        depth_or_array_layers: 2,
-                                    },
-                                    mip_level_count: 1,
-                                    sample_count: 1,
-                                    dimension: wgpu::TextureDimension::D2,
-                                    format: WGPU_COLOR_FORMAT,
-                                    usage: wgpu_hal::TextureUses::COLOR_TARGET
-                                        | wgpu_hal::TextureUses::COPY_DST,
-                                    memory_flags: wgpu_hal::MemoryFlags::empty(),
-                                },
-                                None,
-                            )
-                        };
-                        let texture = unsafe {
-                            device.create_texture_from_hal::<V>(
-                                wgpu_hal_texture,
-                                &wgpu::TextureDescriptor {
-                                    label: Some("VR Swapchain"),
-                                    size: wgpu::Extent3d {
-                                        width: resolution.width,
-                                        height: resolution.height,
-                                        depth_or_array_layers: 2,
-                                    },
-                                    mip_level_count: 1,
-                                    sample_count: 1,
-                                    dimension: wgpu::TextureDimension::D2,
-                                    format: WGPU_COLOR_FORMAT,
-                                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT
-                                        | wgpu::TextureUsages::COPY_DST,
-                                },
-                            )
-                        };
-                        let view = texture.create_view(&wgpu::TextureViewDescriptor {
-                            dimension: Some(wgpu::TextureViewDimension::D2Array),
-                            array_layer_count: NonZeroU32::new(VIEW_COUNT),
-                            ..Default::default()
-                        });
-                        Texture::from_wgpu(texture, view)
-                    })
-                    .collect(),
+        if self.swapchains.is_empty() {
+            // Creating the swapchains can take a while, so it's done on another thread, and
+            // the loading layer is shown in the meantime.
+            match self.swapchain_receiver().try_recv() {
+                Ok(handles) => self.finish_swapchains(device, handles?)?,
+                Err(mpsc::TryRecvError::Empty) => {
+                    if let Some(layer) = &mut self.loading_layer {
+                        layer.render(encoder, xr_frame_state.predicted_display_time)?;
+                    }
+                    return Ok(PostFrameData::default());
+                }
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    anyhow::bail!("the swapchain creation thread exited unexpectedly")
+                }
             }
-        });
+        }
+        if self.reprojecting {
+            // Nothing is rendered this frame; the compositor will reproject the previous image,
+            // which is resubmitted with the views it was rendered with in `post_queue_submit`.
+            return Ok(PostFrameData {
+                views: self.last_views.clone().unwrap_or_default(),
+                ..Default::default()
+            });
+        }
 
-        self.session.sync_actions(&[(&self.action_set).into()])?;
+        self.session
+            .sync_actions(&[self.actions.action_set().into()])?;
         let locate_hand_pose = |action: &xr::Action<xr::Posef>,
-                                space: &xr::Space|
+                                space: &xr::Space,
+                                offset: &xr::Posef|
          -> anyhow::Result<Option<(Vec3, Quat)>> {
             if action.is_active(&self.session, xr::Path::NULL)? {
-                Ok(Some(openxr_pose_to_glam(
-                    &space
-                        .locate(&self.stage, xr_frame_state.predicted_display_time)?
-                        .pose,
-                )))
+                let grip = space
+                    .locate(&self.stage, xr_frame_state.predicted_display_time)?
+                    .pose;
+                Ok(Some(
+                    self.axis_convention
+                        .pose_to_glam(&compose_poses(&grip, offset)),
+                ))
             } else {
                 Ok(None)
             }
         };
 
-        let left_hand = locate_hand_pose(&self.left_action, &self.left_space)?;
-        let right_hand = locate_hand_pose(&self.right_action, &self.right_space)?;
+        let [left_offset, right_offset] = &self.hand_offsets;
+        let left_hand = locate_hand_pose(&self.left_action, &self.left_space, left_offset)?;
+        let right_hand = locate_hand_pose(&self.right_action, &self.right_space, right_offset)?;
+        self.log_input(
+            xr_frame_state.predicted_display_time,
+            [left_hand, right_hand],
+        )?;
 
         let (_, views) = self.session.locate_views(
             VIEW_TYPE,
             xr_frame_state.predicted_display_time,
             &self.stage,
         )?;
+        if let (Some(idle_timer), false) = (&mut self.idle_timer, self.exit_requested) {
+            let head = views
+                .first()
+                .map(|view| self.axis_convention.pose_to_glam(&view.pose));
+            let time = std::time::Duration::from_nanos(
+                xr_frame_state.predicted_display_time.as_nanos() as u64,
+            );
+            if idle_timer.update(time, &[head, left_hand, right_hand]) {
+                log::info!(
+                    "nothing has moved for {:?}; ending the session",
+                    idle_timer.timeout()
+                );
+                self.session.request_exit()?;
+                self.exit_requested = true;
+            }
+        }
 
-        // We need to ask which swapchain image to use for rendering! Which one will we get?
-        // Who knows! It's up to the runtime to decide.
-        let image_index = swapchain.handle.acquire_image().unwrap();
+        for projection in &mut self.swapchains {
+            // We need to ask which swapchain image to use for rendering! Which one will we
+            // get? Who knows! It's up to the runtime to decide. If the wait for the last
+            // frame's image timed out, it's still acquired, so the wait is retried instead.
+            if projection.acquired.is_none() {
+                projection.acquired = Some(projection.swapchain.handle.acquire_image()?);
+            }
 
-        // Wait until the image is available to render to. The compositor could still be
-        // reading from it.
-        swapchain.handle.wait_image(xr::Duration::INFINITE).unwrap();
+            // Wait until the image is available to render to. The compositor could still be
+            // reading from it. If it doesn't let go of it in time (e.g. because the runtime is
+            // misbehaving), the frame is skipped, rather than hanging until it does.
+            if !projection.waited {
+                if !projection
+                    .swapchain
+                    .wait_image(self.swapchain_wait_timeout)?
+                {
+                    log::warn!("timed out waiting for a swapchain image; skipping the frame");
+                    self.wait_timed_out = true;
+                    return Ok(PostFrameData {
+                        views,
+                        left_hand,
+                        right_hand,
+                    });
+                }
+                projection.waited = true;
+            }
+        }
 
-        blit_state.encode_draw_pass(
-            encoder,
-            swapchain.buffers[image_index as usize].view(),
-            None,
-        );
+        match self.eye_layout {
+            EyeLayout::Array => blit_state.encode_draw_pass(
+                encoder,
+                self.swapchains[0].image_view(),
+                None,
+                LoadBehavior::Clear,
+            ),
+            // Each eye's swapchain is sized to match its view scale, so blitting the used part
+            // of its layer fills the swapchain's image.
+            EyeLayout::Separate => {
+                for (view_index, projection) in self.swapchains.iter().enumerate() {
+                    blit_state.encode_draw_pass_to(
+                        encoder,
+                        projection.image_view(),
+                        WGPU_COLOR_FORMAT,
+                        view_index as u32,
+                        LoadBehavior::Clear,
+                    )?;
+                }
+            }
+        }
+
+        for layer in &mut self.quad_layers {
+            // A static swapchain's single image can only be acquired once, so once it has been
+            // rendered, it's reused for the lifetime of the layer.
+            if layer.desc.static_image && layer.has_image {
+                continue;
+            }
+            layer.render(encoder, xr_frame_state.predicted_display_time)?;
+        }
+        if let Some(layer) = &mut self.background_layer {
+            layer.upload(encoder)?;
+        }
 
         Ok(PostFrameData {
             views,
@@ -536,56 +1472,892 @@ impl XrState {
         })
     }
 
+    /// Releases this frame's images and ends the frame, submitting the projection layer and
+    /// the quad layers.
+    ///
+    /// `extra_layers` are composited on top of those, in order. They can be any layers built
+    /// by the caller (e.g. from a [LayerSwapchain] and a space created with [Self::session], as
+    /// [crate::reticle::Reticle] does), for layers this example doesn't build in; their images
+    /// must already be released.
     pub fn post_queue_submit(
         &mut self,
         xr_frame_state: xr::FrameState,
         views: &[openxr::View],
+        extra_layers: &[&xr::CompositionLayerBase<xr::Vulkan>],
     ) -> anyhow::Result<()> {
-        if let Some(swapchain) = &mut self.swapchain {
-            swapchain.handle.release_image().unwrap();
-
-            let rect = xr::Rect2Di {
-                offset: xr::Offset2Di { x: 0, y: 0 },
-                extent: xr::Extent2Di {
-                    width: swapchain.resolution.width as _,
-                    height: swapchain.resolution.height as _,
-                },
-            };
+        if self.reprojecting {
+            // Resubmit the last released image; no new image was acquired this frame.
+            let views = self.last_views.as_deref().unwrap_or(views).to_vec();
+            return self.end_frame(xr_frame_state, &views, extra_layers);
+        }
 
+        self.release_layer_images()?;
+        if std::mem::take(&mut self.wait_timed_out) {
+            // The projection image is still acquired, and nothing else was rendered, so the
+            // frame is ended without any layers.
             self.frame_stream.end(
                 xr_frame_state.predicted_display_time,
                 self.environment_blend_mode,
-                &[&xr::CompositionLayerProjection::new()
-                    .space(&self.stage)
-                    .views(&[
-                        xr::CompositionLayerProjectionView::new()
-                            .pose(views[0].pose)
-                            .fov(views[0].fov)
-                            .sub_image(
-                                xr::SwapchainSubImage::new()
-                                    .swapchain(&swapchain.handle)
-                                    .image_array_index(0)
-                                    .image_rect(rect),
-                            ),
-                        xr::CompositionLayerProjectionView::new()
-                            .pose(views[1].pose)
-                            .fov(views[1].fov)
-                            .sub_image(
-                                xr::SwapchainSubImage::new()
-                                    .swapchain(&swapchain.handle)
-                                    .image_array_index(1)
-                                    .image_rect(rect),
-                            ),
-                    ])],
+                &[],
             )?;
+            return Ok(());
+        }
+        self.release_projection_images()?;
+        if !self.swapchains.is_empty() {
+            self.last_views = Some(views.to_vec());
+        }
+
+        self.end_frame(xr_frame_state, views, extra_layers)
+    }
+
+    /// Ends a frame that failed to render by submitting only the fallback layer, so that the
+    /// headset shows a solid colour instead of freezing or going black. Any images acquired
+    /// during the frame are released, so that the next frame can try again as normal. Call
+    /// this after submitting the frame's commands, in place of [Self::post_queue_submit].
+    pub fn submit_fallback(&mut self, xr_frame_state: xr::FrameState) -> anyhow::Result<()> {
+        self.release_layer_images()?;
+        // If the wait for the projection image timed out, it stays acquired until the wait is
+        // retried on the next frame.
+        self.wait_timed_out = false;
+        self.release_projection_images()?;
+        // The projection swapchain's last released image may not match `last_views` any more.
+        self.last_views = None;
+
+        let fallback = self
+            .fallback_layer
+            .has_image
+            .then(|| self.fallback_layer.composition_layer(&self.view_space));
+        let layers: Vec<&xr::CompositionLayerBase<xr::Vulkan>> =
+            fallback.iter().map(|q| &**q).collect();
+        self.frame_stream.end(
+            xr_frame_state.predicted_display_time,
+            self.environment_blend_mode,
+            &layers,
+        )?;
+        Ok(())
+    }
+
+    /// Releases the projection swapchains' images that were acquired and waited for.
+    fn release_projection_images(&mut self) -> anyhow::Result<()> {
+        for projection in &mut self.swapchains {
+            if projection.acquired.is_some() && projection.waited {
+                projection.swapchain.release_image()?;
+                projection.acquired = None;
+                projection.waited = false;
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts playing `pattern` on the given hand's controller, in place of anything that's
+    /// already playing on it. The segments are started as the frames are rendered, so they're
+    /// only as precise as the frame rate.
+    pub fn play_haptic_pattern(
+        &mut self,
+        hand: Hand,
+        pattern: &HapticPattern,
+    ) -> anyhow::Result<()> {
+        self.haptic_actions[hand as usize].stop_feedback(&self.session, xr::Path::NULL)?;
+        self.haptic_playback[hand as usize] = Some(HapticPlayback::new(pattern.clone()));
+        Ok(())
+    }
+
+    /// Starts the segments of the haptic patterns that are due by `time`.
+    fn update_haptics(&mut self, time: xr::Time) -> anyhow::Result<()> {
+        let time = std::time::Duration::from_nanos(time.as_nanos() as u64);
+        for (action, playback) in self.haptic_actions.iter().zip(&mut self.haptic_playback) {
+            let Some(current) = playback else {
+                continue;
+            };
+            match current.update(time) {
+                Some(segment) if segment.amplitude > 0.0 => action.apply_feedback(
+                    &self.session,
+                    xr::Path::NULL,
+                    &xr::HapticVibration::new()
+                        .amplitude(segment.amplitude)
+                        .frequency(segment.frequency.unwrap_or(xr::FREQUENCY_UNSPECIFIED))
+                        .duration(xr::Duration::from_nanos(segment.duration.as_nanos() as i64)),
+                )?,
+                Some(_) => action.stop_feedback(&self.session, xr::Path::NULL)?,
+                None => {}
+            }
+            if current.is_finished() {
+                *playback = None;
+            }
+        }
+        Ok(())
+    }
+
+    /// Logs the state of every action and hand, if the input is being logged and it's been
+    /// long enough since it last was.
+    fn log_input(
+        &mut self,
+        time: xr::Time,
+        hands: [Option<(Vec3, Quat)>; 2],
+    ) -> anyhow::Result<()> {
+        let Some(interval) = self.input_log_interval else {
+            return Ok(());
+        };
+        if self
+            .last_input_log
+            .is_some_and(|last| time.as_nanos() - last.as_nanos() < interval.as_nanos())
+        {
+            return Ok(());
+        }
+        self.last_input_log = Some(time);
+
+        let [left, right] = hands.map(|hand| match hand {
+            Some((translation, _)) => format!("tracked at {translation:.2}"),
+            None => "not tracked".to_string(),
+        });
+        log::info!(
+            "input: {}; left hand {left}, right hand {right}",
+            self.actions.describe_states(&self.session)?.join(", ")
+        );
+        Ok(())
+    }
+
+    /// Creates the projection swapchains now, blocking until they're ready, rather than on
+    /// another thread once the first frame is rendered, which shows the loading layer until
+    /// they are. Does nothing if they already exist.
+    pub fn create_swapchains(&mut self, device: &wgpu::Device) -> anyhow::Result<()> {
+        if self.swapchains.is_empty() {
+            let handles = self
+                .swapchain_receiver()
+                .recv()
+                .context("the swapchain creation thread exited unexpectedly")?;
+            self.finish_swapchains(device, handles?)?;
+        }
+        Ok(())
+    }
+
+    /// Receives the projection swapchains' handles from the thread that creates them, which is
+    /// started if it hasn't been already.
+    fn swapchain_receiver(
+        &mut self,
+    ) -> &mpsc::Receiver<anyhow::Result<Vec<xr::Swapchain<xr::Vulkan>>>> {
+        let resolutions = self.projection_resolutions();
+        let array_size = self.eye_layout.array_size();
+        let session = &self.session;
+        self.pending_swapchains.get_or_insert_with(|| {
+            let (sender, receiver) = mpsc::channel();
+            let session = session.clone();
+            std::thread::spawn(move || {
+                let _ = sender.send(
+                    resolutions
+                        .into_iter()
+                        .map(|resolution| {
+                            Swapchain::create_handle(
+                                &session,
+                                resolution,
+                                array_size,
+                                xr::SwapchainCreateFlags::EMPTY,
+                            )
+                        })
+                        .collect(),
+                );
+            });
+            receiver
+        })
+    }
+
+    fn finish_swapchains(
+        &mut self,
+        device: &wgpu::Device,
+        handles: Vec<xr::Swapchain<xr::Vulkan>>,
+    ) -> anyhow::Result<()> {
+        self.pending_swapchains = None;
+        let array_size = self.eye_layout.array_size();
+        self.swapchains = handles
+            .into_iter()
+            .zip(self.projection_resolutions())
+            .map(|(handle, resolution)| {
+                Ok(ProjectionSwapchain::new(Swapchain::from_handle(
+                    handle, device, resolution, array_size,
+                )?))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        // Its last image was released in the previous frame (if it was shown at all), so it's
+        // safe to drop.
+        self.loading_layer = None;
+        Ok(())
+    }
+
+    /// The resolution of each projection swapchain. With [EyeLayout::Separate], each eye's
+    /// swapchain is only as large as the part of the render target its view is rendered to.
+    fn projection_resolutions(&self) -> Vec<vk::Extent2D> {
+        // Now we need to find all the viewpoints we need to take care of! This is a property
+        // of the view configuration type; in this example we use PRIMARY_STEREO, so we should
+        // have 2 viewpoints.
+        let resolution = |scale: f32| vk::Extent2D {
+            width: (self.views[0].recommended_image_rect_width as f32 * scale).ceil() as _,
+            height: (self.views[0].recommended_image_rect_height as f32 * scale).ceil() as _,
+        };
+        match self.eye_layout {
+            EyeLayout::Array => vec![resolution(1.0)],
+            EyeLayout::Separate => self.view_scales.iter().map(|&s| resolution(s)).collect(),
+        }
+    }
+
+    /// Releases the images of the quad layers that were rendered to this frame.
+    fn release_layer_images(&mut self) -> anyhow::Result<()> {
+        for layer in self
+            .quad_layers
+            .iter_mut()
+            .chain(self.loading_layer.iter_mut())
+            .chain(std::iter::once(&mut self.fallback_layer))
+        {
+            if layer.acquired {
+                layer.swapchain.release_image()?;
+                layer.acquired = false;
+                layer.has_image = true;
+            }
+        }
+        if let Some(layer) = self
+            .background_layer
+            .as_mut()
+            .filter(|layer| layer.acquired)
+        {
+            layer.swapchain.release_image()?;
+            layer.acquired = false;
+            layer.upload = None;
+        }
+        Ok(())
+    }
+
+    /// Ends the frame, submitting the projection layer (using the most recently released
+    /// swapchain image) and any quad layers that have an image. Until the projection swapchain
+    /// is ready, the loading layer is submitted instead.
+    fn end_frame(
+        &mut self,
+        xr_frame_state: xr::FrameState,
+        views: &[openxr::View],
+        extra_layers: &[&xr::CompositionLayerBase<xr::Vulkan>],
+    ) -> anyhow::Result<()> {
+        let projection_views: Vec<_> = if self.swapchains.is_empty() {
+            vec![]
+        } else {
+            views
+                .iter()
+                .enumerate()
+                .map(|(index, view)| {
+                    let (swapchain, array_index, scale) = match self.eye_layout {
+                        EyeLayout::Array => (
+                            &self.swapchains[0].swapchain,
+                            index,
+                            self.view_scales[index],
+                        ),
+                        EyeLayout::Separate => (&self.swapchains[index].swapchain, 0, 1.0),
+                    };
+                    xr::CompositionLayerProjectionView::new()
+                        .pose(view.pose)
+                        .fov(view.fov)
+                        .sub_image(
+                            xr::SwapchainSubImage::new()
+                                .swapchain(&swapchain.handle)
+                                .image_array_index(array_index as u32)
+                                .image_rect(swapchain.rect(scale)),
+                        )
+                })
+                .collect()
+        };
+        let background = self
+            .background_layer
+            .as_ref()
+            .filter(|layer| layer.has_image())
+            .map(|layer| layer.composition_layer(&self.stage));
+        let projection = xr::CompositionLayerProjection::new()
+            // The background shows through wherever the scene is transparent.
+            .layer_flags(if background.is_some() {
+                xr::CompositionLayerFlags::BLEND_TEXTURE_SOURCE_ALPHA
+            } else {
+                xr::CompositionLayerFlags::EMPTY
+            })
+            .space(&self.stage)
+            .views(&projection_views);
+        let quad_layers: Vec<&QuadLayer> = if !self.swapchains.is_empty() {
+            self.quad_layers.iter().collect()
+        } else {
+            self.loading_layer.iter().collect()
+        };
+        let quads: Vec<_> = quad_layers
+            .into_iter()
+            .filter(|layer| layer.has_image)
+            .map(|layer| layer.composition_layer(&self.stage))
+            .collect();
+
+        let mut layers: Vec<&xr::CompositionLayerBase<xr::Vulkan>> = vec![];
+        layers.extend(background.as_ref().map(EquirectLayer::as_base));
+        if !projection_views.is_empty() {
+            layers.push(&projection);
         }
+        layers.extend(quads.iter().map(|q| &**q));
+        layers.extend_from_slice(extra_layers);
+        anyhow::ensure!(
+            layers.len() <= self.supported_layer_types.max_layer_count as usize,
+            "{} layers were submitted, but the runtime can only composite {} at once",
+            layers.len(),
+            self.supported_layer_types.max_layer_count
+        );
+        self.frame_stream.end(
+            xr_frame_state.predicted_display_time,
+            self.environment_blend_mode,
+            &layers,
+        )?;
+
+        Ok(())
+    }
+
+    /// Adds a quad layer, which will be composited on top of the scene. `render` is called to
+    /// draw the layer's contents every frame, or only once if the layer is static.
+    pub fn add_quad_layer(
+        &mut self,
+        device: &wgpu::Device,
+        desc: QuadLayerDesc,
+        render: QuadLayerRenderFn,
+    ) -> anyhow::Result<()> {
+        // The projection layer is always submitted alongside the quad layers, and so is the
+        // background layer if there is one.
+        let reserved = 1 + u32::from(self.background_layer.is_some());
+        let max_quad_layers = self
+            .supported_layer_types
+            .max_layer_count
+            .saturating_sub(reserved);
+        anyhow::ensure!(
+            (self.quad_layers.len() as u32) < max_quad_layers,
+            "the runtime can only composite {max_quad_layers} quad layers at once"
+        );
+        let layer = QuadLayer::new(&self.session, device, desc, render)?;
+        self.quad_layers.push(layer);
+        Ok(())
+    }
+
+    /// Shows an equirectangular image behind the scene, in place of any previous one. The
+    /// scene only lets it show through where it's transparent, so the main pass should clear
+    /// to a transparent colour (see [crate::main_state::MainState::clear_color]).
+    pub fn set_background_layer(
+        &mut self,
+        device: &wgpu::Device,
+        desc: EquirectLayerDesc,
+    ) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.supported_layer_types.equirect2,
+            "the runtime doesn't support equirect layers (XR_KHR_composition_layer_equirect2)"
+        );
+        // Replacing the background doesn't change how many layers are submitted.
+        let layer_count = self.quad_layers.len() as u32 + 2;
+        anyhow::ensure!(
+            self.background_layer.is_some()
+                || layer_count <= self.supported_layer_types.max_layer_count,
+            "the runtime can only composite {} layers at once, which are taken by the quad layers",
+            self.supported_layer_types.max_layer_count
+        );
+        self.background_layer = Some(EquirectLayer::new(&self.session, device, desc)?);
+        Ok(())
+    }
+
+    pub fn has_background_layer(&self) -> bool {
+        self.background_layer.is_some()
+    }
 
+    /// Sets the quad layer that is shown while the projection swapchain is being created. It
+    /// should not be static, so that it can be animated.
+    pub fn set_loading_layer(
+        &mut self,
+        device: &wgpu::Device,
+        desc: QuadLayerDesc,
+        render: QuadLayerRenderFn,
+    ) -> anyhow::Result<()> {
+        self.loading_layer = Some(QuadLayer::new(&self.session, device, desc, render)?);
         Ok(())
     }
 
+    /// The composition layer types that can be submitted, and how many at once.
+    pub fn supported_layer_types(&self) -> SupportedLayerTypes {
+        self.supported_layer_types
+    }
+
+    /// The depth formats, in terms of wgpu formats, that the runtime can create swapchains with.
+    pub fn depth_swapchain_formats(&self) -> anyhow::Result<Vec<wgpu::TextureFormat>> {
+        let formats = self.session.enumerate_swapchain_formats()?;
+        Ok(DEPTH_FORMATS
+            .iter()
+            .filter(|(_, vk_format)| formats.contains(&(vk_format.as_raw() as _)))
+            .map(|(format, _)| *format)
+            .collect())
+    }
+
+    /// Whether frames are currently being rendered at half the display rate, either because it
+    /// was requested or because the runtime has reported thermal or performance pressure.
+    pub fn is_half_rate(&self) -> bool {
+        self.half_rate || self.perf_pressure.iter().any(|p| *p)
+    }
+
+    /// Whether rendering should be skipped for the current frame, as the previous frame will
+    /// be reprojected by the compositor instead.
+    pub fn is_reprojecting(&self) -> bool {
+        self.reprojecting
+    }
+
+    /// Whether the application has input focus. When it doesn't (e.g. because a system menu is
+    /// open), controller input is intended for the runtime, not for us.
+    pub fn is_focused(&self) -> bool {
+        self.session_state == xr::SessionState::FOCUSED
+    }
+
+    /// Whether the session is ending for good (e.g. after the idle timeout, or because the
+    /// runtime is shutting down), after which the application should exit.
+    pub fn is_exiting(&self) -> bool {
+        matches!(
+            self.session_state,
+            xr::SessionState::EXITING | xr::SessionState::LOSS_PENDING
+        )
+    }
+
+    /// Whether the application's frames are being shown to the user.
+    pub fn is_visible(&self) -> bool {
+        matches!(
+            self.session_state,
+            xr::SessionState::VISIBLE | xr::SessionState::FOCUSED
+        )
+    }
+
+    /// The interaction profiles currently in use for the left and right hands, if any.
+    pub fn interaction_profiles(&self) -> &[Option<String>; 2] {
+        &self.interaction_profiles
+    }
+
     pub fn views(&self) -> &[ViewConfigurationView] {
         self.views.as_ref()
     }
+
+    /// The session, for creating swapchains and spaces for custom composition layers.
+    pub fn session(&self) -> &xr::Session<xr::Vulkan> {
+        &self.session
+    }
+
+    /// The centre and size of the play area. By convention, the centre is the origin of the
+    /// runtime's stage reference space, so it's only away from the origin of
+    /// [Self::stage] when [XrConfig::reference_space_offset] is set. The size can change while
+    /// the app is running (e.g. if the user redraws their boundary), so this should be queried
+    /// again when it's needed.
+    ///
+    /// See [Self::take_play_area_changed] for when it changes.
+    pub fn play_area(&self) -> anyhow::Result<PlayArea> {
+        let size = self
+            .session
+            .reference_space_bounds_rect(xr::ReferenceSpaceType::STAGE)?
+            .map(|extent| Vec2::new(extent.width, extent.height));
+
+        // The stage space was created at `reference_space_offset` within the runtime's stage
+        // space, so the runtime's origin is at the inverse of the offset within it.
+        let center = self
+            .axis_convention
+            .pose_to_glam(&invert_pose(&self.reference_space_offset));
+        Ok(PlayArea { center, size })
+    }
+
+    /// Sets how the poses that this returns (the hands' and the play area's) are converted to
+    /// the scene's coordinate system. The play area counts as changed if the convention did.
+    pub fn set_axis_convention(&mut self, axis_convention: AxisConvention) {
+        if axis_convention != self.axis_convention {
+            self.axis_convention = axis_convention;
+            self.play_area_changed = true;
+        }
+    }
+
+    /// Whether the stage reference space has changed (e.g. because the user redrew their
+    /// boundary) since this was last called, in which case [Self::play_area] should be queried
+    /// again.
+    pub fn take_play_area_changed(&mut self) -> bool {
+        std::mem::take(&mut self.play_area_changed)
+    }
+
+    /// The fraction of the render target's width and height that each view is rendered to,
+    /// from its top-left corner. This is less than 1 for the reduced eye, if there is one.
+    pub fn view_scales(&self) -> [f32; 2] {
+        self.view_scales
+    }
+}
+
+impl ActionRegistry {
+    fn new(
+        instance: &xr::Instance,
+        name: &str,
+        localized_name: &str,
+        user_bindings: &ActionBindings,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            instance: instance.clone(),
+            action_set: instance.create_action_set(name, localized_name, 0)?,
+            user_bindings: user_bindings.clone(),
+            actions: vec![],
+            attached: false,
+        })
+    }
+
+    /// Creates an action, with default bindings given as pairs of interaction profile and
+    /// input path (e.g. `/interaction_profiles/khr/simple_controller` and
+    /// `/user/hand/left/input/select/click`). These are replaced by the user's bindings for the
+    /// action, if they have any.
+    pub fn register<T: DescribeState + 'static>(
+        &mut self,
+        name: &str,
+        localized_name: &str,
+        default_bindings: &[(&str, &str)],
+    ) -> anyhow::Result<xr::Action<T>> {
+        anyhow::ensure!(
+            !self.attached,
+            "can't register action {name:?}: actions must be registered before the action set \
+             is attached to the session"
+        );
+        let action = self
+            .action_set
+            .create_action::<T>(name, localized_name, &[])?;
+        let bindings = match self.user_bindings.get(name) {
+            Some(bindings) => bindings.clone(),
+            None => default_bindings
+                .iter()
+                .map(|(profile, path)| InputBinding::new(profile, path))
+                .collect(),
+        };
+        let bindings = bindings
+            .iter()
+            .map(|binding| {
+                binding.validate()?;
+                Ok((
+                    self.instance.string_to_path(&binding.profile)?,
+                    self.instance.string_to_path(&binding.path)?,
+                ))
+            })
+            .collect::<anyhow::Result<_>>()
+            .with_context(|| format!("invalid binding for action {name:?}"))?;
+        self.actions.push(RegisteredAction {
+            name: name.to_string(),
+            action: Box::new(action.clone()),
+            bindings,
+        });
+        Ok(action)
+    }
+
+    /// Suggests the bindings of every registered action, and attaches the action set to
+    /// `session`. This can only be done once.
+    fn attach<G>(&mut self, session: &xr::Session<G>) -> anyhow::Result<()> {
+        anyhow::ensure!(!self.attached, "the action set is already attached");
+
+        // Each suggestion for an interaction profile replaces the last, so all of a profile's
+        // bindings have to be suggested at once.
+        let mut profiles: Vec<xr::Path> = self
+            .actions
+            .iter()
+            .flat_map(|a| a.bindings.iter().map(|(profile, _)| *profile))
+            .collect();
+        profiles.sort_by_key(|p| p.into_raw());
+        profiles.dedup();
+        for profile in profiles {
+            let bindings: Vec<_> = self
+                .actions
+                .iter()
+                .flat_map(|a| {
+                    a.bindings
+                        .iter()
+                        .filter(move |(p, _)| *p == profile)
+                        .map(|(_, path)| a.action.binding(*path))
+                })
+                .collect();
+            // This is where the runtime rejects inputs that the profile doesn't have.
+            self.instance
+                .suggest_interaction_profile_bindings(profile, &bindings)
+                .with_context(|| {
+                    format!(
+                        "failed to suggest bindings for {}",
+                        self.instance.path_to_string(profile).unwrap_or_default()
+                    )
+                })?;
+        }
+        for name in self.user_bindings.keys() {
+            if !self.actions.iter().any(|a| &a.name == name) {
+                log::warn!("ignoring bindings for unknown action {name:?}");
+            }
+        }
+
+        session.attach_action_sets(&[&self.action_set])?;
+        self.attached = true;
+        Ok(())
+    }
+
+    pub fn action_set(&self) -> &xr::ActionSet {
+        &self.action_set
+    }
+
+    /// Each action's name and state as of the last sync, e.g. `left_hand: active`.
+    fn describe_states(&self, session: &xr::Session<xr::Vulkan>) -> xr::Result<Vec<String>> {
+        self.actions
+            .iter()
+            .map(|a| Ok(format!("{}: {}", a.name, a.action.describe_state(session)?)))
+            .collect()
+    }
+}
+
+impl QuadLayer {
+    fn new(
+        session: &xr::Session<xr::Vulkan>,
+        device: &wgpu::Device,
+        desc: QuadLayerDesc,
+        render: QuadLayerRenderFn,
+    ) -> anyhow::Result<Self> {
+        let swapchain = Swapchain::new(
+            session,
+            device,
+            vk::Extent2D {
+                width: desc.width,
+                height: desc.height,
+            },
+            1,
+            if desc.static_image {
+                xr::SwapchainCreateFlags::STATIC_IMAGE
+            } else {
+                xr::SwapchainCreateFlags::EMPTY
+            },
+        )?;
+        Ok(Self {
+            desc,
+            swapchain,
+            render,
+            acquired: false,
+            has_image: false,
+        })
+    }
+
+    /// Acquires an image and encodes the layer's rendering into it. The image must be
+    /// released after the commands have been submitted.
+    fn render(&mut self, encoder: &mut wgpu::CommandEncoder, time: xr::Time) -> anyhow::Result<()> {
+        let image_index = self.swapchain.handle.acquire_image()?;
+        self.acquired = true;
+        self.swapchain.wait_image(xr::Duration::INFINITE)?;
+        (self.render)(
+            encoder,
+            self.swapchain.buffers[image_index as usize].view(),
+            time,
+        );
+        Ok(())
+    }
+
+    fn composition_layer<'a>(
+        &'a self,
+        space: &'a xr::Space,
+    ) -> xr::CompositionLayerQuad<'a, xr::Vulkan> {
+        xr::CompositionLayerQuad::new()
+            .layer_flags(xr::CompositionLayerFlags::BLEND_TEXTURE_SOURCE_ALPHA)
+            .space(space)
+            .eye_visibility(xr::EyeVisibility::BOTH)
+            .sub_image(
+                xr::SwapchainSubImage::new()
+                    .swapchain(&self.swapchain.handle)
+                    .image_array_index(0)
+                    .image_rect(self.swapchain.rect(1.0)),
+            )
+            .pose(self.desc.pose)
+            .size(self.desc.size)
+    }
+}
+
+struct EquirectLayer {
+    radius: f32,
+    swapchain: Swapchain,
+    /// The image, with its rows padded to wgpu's alignment for copies, and the padded size of
+    /// each row. It's kept until it has been copied into the swapchain's single (static) image.
+    upload: Option<(wgpu::Buffer, u32)>,
+    /// Whether the image has been acquired this frame, and must be released after submission.
+    acquired: bool,
+}
+impl EquirectLayer {
+    fn new(
+        session: &xr::Session<xr::Vulkan>,
+        device: &wgpu::Device,
+        desc: EquirectLayerDesc,
+    ) -> anyhow::Result<Self> {
+        let row_len = desc.width as usize * 4;
+        anyhow::ensure!(
+            desc.rgba.len() == row_len * desc.height as usize,
+            "the equirect image should be {}x{} RGBA8, but has {} bytes",
+            desc.width,
+            desc.height,
+            desc.rgba.len()
+        );
+        let swapchain = Swapchain::new(
+            session,
+            device,
+            vk::Extent2D {
+                width: desc.width,
+                height: desc.height,
+            },
+            1,
+            xr::SwapchainCreateFlags::STATIC_IMAGE,
+        )?;
+
+        let padded_row_len = row_len.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize);
+        let mut padded = vec![0; padded_row_len * desc.height as usize];
+        for (padded_row, row) in padded
+            .chunks_mut(padded_row_len)
+            .zip(desc.rgba.chunks(row_len))
+        {
+            padded_row[..row_len].copy_from_slice(row);
+        }
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Equirect Layer Upload"),
+            contents: &padded,
+            usage: wgpu::BufferUsages::COPY_SRC,
+        });
+
+        Ok(Self {
+            radius: desc.radius,
+            swapchain,
+            upload: Some((buffer, padded_row_len as u32)),
+            acquired: false,
+        })
+    }
+
+    /// Whether the image has been released, and there is something to submit.
+    fn has_image(&self) -> bool {
+        self.upload.is_none()
+    }
+
+    /// Acquires the swapchain's image and copies the image into it, if that hasn't been done
+    /// yet. The image must be released after the commands have been submitted.
+    fn upload(&mut self, encoder: &mut wgpu::CommandEncoder) -> anyhow::Result<()> {
+        let Some((buffer, bytes_per_row)) = self.upload.as_ref().filter(|_| !self.acquired) else {
+            return Ok(());
+        };
+        let image_index = self.swapchain.handle.acquire_image()?;
+        self.acquired = true;
+        self.swapchain.wait_image(xr::Duration::INFINITE)?;
+        let resolution = self.swapchain.resolution;
+        encoder.copy_buffer_to_texture(
+            wgpu::ImageCopyBuffer {
+                buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(*bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            self.swapchain.buffers[image_index as usize]
+                .texture()
+                .as_image_copy(),
+            wgpu::Extent3d {
+                width: resolution.width,
+                height: resolution.height,
+                depth_or_array_layers: 1,
+            },
+        );
+        Ok(())
+    }
+
+    /// The `openxr` crate doesn't export a builder for equirect2 layers, so this is the raw
+    /// struct; see [Self::as_base] for submitting it. It refers to the swapchain and `space`
+    /// without borrowing them, so they must outlive it.
+    fn composition_layer(&self, space: &xr::Space) -> xr::sys::CompositionLayerEquirect2KHR {
+        use std::f32::consts::{FRAC_PI_2, TAU};
+        xr::sys::CompositionLayerEquirect2KHR {
+            ty: xr::sys::CompositionLayerEquirect2KHR::TYPE,
+            next: std::ptr::null(),
+            layer_flags: xr::CompositionLayerFlags::EMPTY,
+            space: space.as_raw(),
+            eye_visibility: xr::EyeVisibility::BOTH,
+            sub_image: xr::SwapchainSubImage::new()
+                .swapchain(&self.swapchain.handle)
+                .image_array_index(0)
+                .image_rect(self.swapchain.rect(1.0))
+                .into_raw(),
+            pose: xr::Posef::IDENTITY,
+            radius: self.radius,
+            // The whole sphere.
+            central_horizontal_angle: TAU,
+            upper_vertical_angle: FRAC_PI_2,
+            lower_vertical_angle: -FRAC_PI_2,
+        }
+    }
+
+    fn as_base(
+        layer: &xr::sys::CompositionLayerEquirect2KHR,
+    ) -> &xr::CompositionLayerBase<'_, xr::Vulkan> {
+        // SAFETY: every composition layer struct starts with the fields of the base header,
+        // which `CompositionLayerBase` wraps; this is how the crate's own builders are passed
+        // as the base type.
+        unsafe { &*(layer as *const xr::sys::CompositionLayerEquirect2KHR).cast() }
+    }
+}
+
+/// A swapchain with a single static image, for a composition layer that the caller builds and
+/// submits through [XrState::post_queue_submit]'s `extra_layers`, rather than one that
+/// [XrState] builds in.
+pub struct LayerSwapchain {
+    swapchain: Swapchain,
+}
+impl LayerSwapchain {
+    /// Creates the swapchain and renders its image with `render`, waiting for the commands to
+    /// be submitted before the image is released. The image is then used as-is for every frame.
+    pub fn new_static(
+        session: &xr::Session<xr::Vulkan>,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        width: u32,
+        height: u32,
+        render: impl FnOnce(&mut wgpu::CommandEncoder, &wgpu::TextureView),
+    ) -> anyhow::Result<Self> {
+        let mut swapchain = Swapchain::new(
+            session,
+            device,
+            vk::Extent2D { width, height },
+            1,
+            xr::SwapchainCreateFlags::STATIC_IMAGE,
+        )?;
+        let image_index = swapchain.handle.acquire_image()?;
+        anyhow::ensure!(
+            swapchain.wait_image(xr::Duration::INFINITE)?,
+            "timed out waiting for the layer's image"
+        );
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        render(&mut encoder, swapchain.buffers[image_index as usize].view());
+        queue.submit(Some(encoder.finish()));
+        swapchain.release_image()?;
+        Ok(Self { swapchain })
+    }
+
+    /// The whole of the swapchain's image, for the layer's `sub_image`.
+    pub fn sub_image(&self) -> xr::SwapchainSubImage<'_, xr::Vulkan> {
+        xr::SwapchainSubImage::new()
+            .swapchain(&self.swapchain.handle)
+            .image_array_index(0)
+            .image_rect(self.swapchain.rect(1.0))
+    }
+}
+
+/// A projection swapchain, along with the state of its image for the current frame.
+struct ProjectionSwapchain {
+    swapchain: Swapchain,
+    /// The image that has been acquired and not yet released.
+    acquired: Option<u32>,
+    /// Whether waiting for the acquired image has succeeded; it can't be released until it
+    /// has.
+    waited: bool,
+}
+impl ProjectionSwapchain {
+    fn new(swapchain: Swapchain) -> Self {
+        Self {
+            swapchain,
+            acquired: None,
+            waited: false,
+        }
+    }
+
+    /// The view of the acquired image.
+    fn image_view(&self) -> &wgpu::TextureView {
+        let image_index = self.acquired.expect("no image has been acquired");
+        self.swapchain.buffers[image_index as usize].view()
+    }
 }
 
 struct Swapchain {
@@ -593,3 +2365,353 @@ struct Swapchain {
     resolution: vk::Extent2D,
     buffers: Vec<Texture>,
 }
+impl Swapchain {
+    fn new(
+        session: &xr::Session<xr::Vulkan>,
+        device: &wgpu::Device,
+        resolution: vk::Extent2D,
+        array_size: u32,
+        create_flags: xr::SwapchainCreateFlags,
+    ) -> anyhow::Result<Self> {
+        let handle = Self::create_handle(session, resolution, array_size, create_flags)?;
+        Self::from_handle(handle, device, resolution, array_size)
+    }
+
+    /// Creates the OpenXR swapchain itself. This doesn't touch wgpu, so it can be called from
+    /// another thread.
+    fn create_handle(
+        session: &xr::Session<xr::Vulkan>,
+        resolution: vk::Extent2D,
+        array_size: u32,
+        create_flags: xr::SwapchainCreateFlags,
+    ) -> anyhow::Result<xr::Swapchain<xr::Vulkan>> {
+        // Create a swapchain for the viewpoints! A swapchain is a set of texture buffers
+        // used for displaying to screen, typically this is a backbuffer and a front buffer,
+        // one for rendering data to, and one for displaying on-screen.
+        Ok(session.create_swapchain(&xr::SwapchainCreateInfo {
+            create_flags,
+            usage_flags: xr::SwapchainUsageFlags::COLOR_ATTACHMENT
+                | xr::SwapchainUsageFlags::SAMPLED
+                | xr::SwapchainUsageFlags::TRANSFER_DST,
+            format: VK_COLOR_FORMAT.as_raw() as _,
+            // The Vulkan graphics pipeline we create is not set up for multisampling,
+            // so we hardcode this to 1. If we used a proper multisampling setup, we
+            // could set this to `views[0].recommended_swapchain_sample_count`.
+            sample_count: 1,
+            width: resolution.width,
+            height: resolution.height,
+            face_count: 1,
+            array_size,
+            mip_count: 1,
+        })?)
+    }
+
+    /// Wraps the images of a swapchain created with [Self::create_handle] in wgpu textures.
+    fn from_handle(
+        handle: xr::Swapchain<xr::Vulkan>,
+        device: &wgpu::Device,
+        resolution: vk::Extent2D,
+        array_size: u32,
+    ) -> anyhow::Result<Self> {
+        use wgpu_hal::{api::Vulkan as V, Api};
+
+        // We'll want to track our own information about the swapchain, so we can draw stuff
+        // onto it! We'll also create a buffer for each generated texture here as well.
+        let images = handle.enumerate_images()?;
+        let size = wgpu::Extent3d {
+            width: resolution.width,
+            height: resolution.height,
+            depth_or_array_layers: array_size,
+        };
+        let buffers = images
+            .into_iter()
+            .map(|color_image| {
+                let color_image = vk::Image::from_raw(color_image);
+                let wgpu_hal_texture = unsafe {
+                    <V as Api>::Device::texture_from_raw(
+                        color_image,
+                        &wgpu_hal::TextureDescriptor {
+                            label: Some("VR Swapchain"),
+                            size,
+                            mip_level_count: 1,
+                            sample_count: 1,
+                            dimension: wgpu::TextureDimension::D2,
+                            format: WGPU_COLOR_FORMAT,
+                            usage: wgpu_hal::TextureUses::COLOR_TARGET
+                                | wgpu_hal::TextureUses::COPY_DST,
+                            memory_flags: wgpu_hal::MemoryFlags::empty(),
+                        },
+                        // The images belong to the runtime, which destroys them along with the
+                        // swapchain. Without a drop guard, wgpu would destroy them itself when the
+                        // texture is dropped, which happens mid-session for quad layers.
+                        Some(Box::new(())),
+                    )
+                };
+                let texture = unsafe {
+                    device.create_texture_from_hal::<V>(
+                        wgpu_hal_texture,
+                        &wgpu::TextureDescriptor {
+                            label: Some("VR Swapchain"),
+                            size,
+                            mip_level_count: 1,
+                            sample_count: 1,
+                            dimension: wgpu::TextureDimension::D2,
+                            format: WGPU_COLOR_FORMAT,
+                            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                                | wgpu::TextureUsages::COPY_DST,
+                        },
+                    )
+                };
+                let view = texture.create_view(&wgpu::TextureViewDescriptor {
+                    dimension: Some(if array_size > 1 {
+                        wgpu::TextureViewDimension::D2Array
+                    } else {
+                        wgpu::TextureViewDimension::D2
+                    }),
+                    array_layer_count: NonZeroU32::new(array_size),
+                    ..Default::default()
+                });
+                Texture::from_wgpu(texture, view)
+            })
+            .collect();
+
+        Ok(Self {
+            handle,
+            resolution,
+            buffers,
+        })
+    }
+
+    /// Waits for the oldest acquired image to be available to render to, returning false if
+    /// `timeout` expires first. The `openxr` crate's `wait_image` reports a timeout as success,
+    /// so the runtime is called directly; [Self::release_image] must be used to release the
+    /// image to match.
+    fn wait_image(&mut self, timeout: xr::Duration) -> anyhow::Result<bool> {
+        let info = xr::sys::SwapchainImageWaitInfo {
+            ty: xr::sys::SwapchainImageWaitInfo::TYPE,
+            next: std::ptr::null_mut(),
+            timeout,
+        };
+        let result = unsafe {
+            (self.handle.instance().fp().wait_swapchain_image)(self.handle.as_raw(), &info)
+        };
+        match result {
+            xr::sys::Result::SUCCESS => Ok(true),
+            xr::sys::Result::TIMEOUT_EXPIRED => Ok(false),
+            err => Err(err.into()),
+        }
+    }
+
+    /// Releases the oldest acquired image, after it has been waited for with
+    /// [Self::wait_image].
+    fn release_image(&mut self) -> anyhow::Result<()> {
+        let result = unsafe {
+            (self.handle.instance().fp().release_swapchain_image)(
+                self.handle.as_raw(),
+                std::ptr::null(),
+            )
+        };
+        match result {
+            xr::sys::Result::SUCCESS => Ok(()),
+            err => Err(err.into()),
+        }
+    }
+
+    /// The top-left `scale` of the swapchain's images, on each axis.
+    fn rect(&self, scale: f32) -> xr::Rect2Di {
+        xr::Rect2Di {
+            offset: xr::Offset2Di { x: 0, y: 0 },
+            extent: xr::Extent2Di {
+                width: (self.resolution.width as f32 * scale).ceil() as _,
+                height: (self.resolution.height as f32 * scale).ceil() as _,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inverted_pose_undoes_the_pose() {
+        let pose = pose_from_parts(
+            Quat::from_rotation_y(std::f32::consts::FRAC_PI_4),
+            Vec3::new(1.0, -0.5, 2.0),
+        );
+        let to_glam = |pose: &xr::Posef| {
+            let (rotation, position) = pose_parts(pose);
+            glam::Affine3A::from_rotation_translation(rotation, position)
+        };
+        let undone = to_glam(&pose) * to_glam(&invert_pose(&pose));
+        assert!(undone.abs_diff_eq(glam::Affine3A::IDENTITY, 1e-6));
+    }
+
+    #[test]
+    fn hand_offset_is_relative_to_the_grip() {
+        // Turned 90 degrees to the left, so the grip's forward (-Z) is the stage's -X.
+        let turn = Quat::from_rotation_y(std::f32::consts::FRAC_PI_2);
+        let grip = pose_from_parts(turn, Vec3::new(1.0, 1.0, 0.0));
+        let offset = pose_from_parts(Quat::IDENTITY, Vec3::new(0.0, 0.0, -0.5));
+        let (rotation, position) = pose_parts(&compose_poses(&grip, &offset));
+        assert!(position.abs_diff_eq(Vec3::new(0.5, 1.0, 0.0), 1e-6));
+        assert_eq!(rotation, turn);
+
+        // A rotated offset is positioned by the grip's rotation, not by its own.
+        let tilt = Quat::from_rotation_x(1.0);
+        let offset = pose_from_parts(tilt, Vec3::new(0.0, 0.0, -0.5));
+        let (rotation, position) = pose_parts(&compose_poses(&grip, &offset));
+        assert!(position.abs_diff_eq(Vec3::new(0.5, 1.0, 0.0), 1e-6));
+        assert!(rotation.abs_diff_eq(turn * tilt, 1e-6));
+
+        let unchanged = compose_poses(&grip, &xr::Posef::IDENTITY);
+        assert_eq!(unchanged.position, grip.position);
+    }
+
+    #[test]
+    fn play_area_outline_lies_on_the_floor_around_the_centre() {
+        let offset = pose_from_parts(
+            Quat::from_rotation_y(std::f32::consts::FRAC_PI_4),
+            Vec3::new(1.0, 0.0, 2.0),
+        );
+        let play_area = PlayArea {
+            center: AxisConvention::default().pose_to_glam(&invert_pose(&offset)),
+            size: Some(Vec2::new(3.0, 2.0)),
+        };
+        let center = play_area.center.0;
+        let corners = play_area.outline().unwrap();
+        for (i, corner) in corners.iter().enumerate() {
+            assert!((corner.y - center.y).abs() < 1e-5);
+            // Each side is as long as the width or the depth, alternately.
+            let side = corner.distance(corners[(i + 1) % corners.len()]);
+            assert!((side - [3.0, 2.0][i % 2]).abs() < 1e-5, "{side}");
+        }
+        assert!(((corners[0] + corners[2]) / 2.0).abs_diff_eq(center, 1e-5));
+
+        assert!(PlayArea {
+            size: None,
+            ..play_area
+        }
+        .outline()
+        .is_none());
+    }
+
+    #[test]
+    fn axis_conventions_move_points_with_their_poses() {
+        let xr_rotation = Quat::from_euler(glam::EulerRot::YXZ, 0.3, -0.7, 1.1);
+        let pose = pose_from_parts(xr_rotation, Vec3::new(1.0, 2.0, 3.0));
+        // The conversion that was hardcoded before the convention could be changed.
+        let (translation, rotation) = AxisConvention::default().pose_to_glam(&pose);
+        let o = pose.orientation;
+        let original =
+            Quat::from_rotation_x(180.0f32.to_radians()) * glam::quat(o.w, o.z, o.y, o.x);
+        assert_eq!(translation, Vec3::new(-1.0, 2.0, -3.0));
+        assert!(rotation.dot(original).abs() > 1.0 - 1e-6);
+
+        // Whichever axes are negated, a point held at an offset from the pose ends up where the
+        // negated axes put the same point in OpenXR's coordinates.
+        let local = Vec3::new(0.2, -0.4, 0.6);
+        let xr_point = Vec3::new(1.0, 2.0, 3.0) + xr_rotation * local;
+        for bits in 0..8 {
+            let convention = AxisConvention {
+                negate: [0, 1, 2].map(|axis| bits & (1 << axis) != 0),
+            };
+            let signs = convention.signs();
+            let (translation, rotation) = convention.pose_to_glam(&pose);
+            assert!(
+                (translation + rotation * (local * signs)).abs_diff_eq(xr_point * signs, 1e-5),
+                "{convention:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn untracked_hands_rest_at_their_rest_poses_after_round_trip() {
+        let config = XrConfig {
+            untracked_hands: UntrackedHands::Rest,
+            ..Default::default()
+        };
+        let config: XrConfig = toml::from_str(&toml::to_string_pretty(&config).unwrap()).unwrap();
+        assert_eq!(config.untracked_hands, UntrackedHands::Rest);
+
+        let convention = AxisConvention::default();
+        let tracked = Some((Vec3::ONE, Quat::IDENTITY));
+        assert_eq!(config.hand_pose(0, tracked, &convention), tracked);
+        let (left, _) = config.hand_pose(0, None, &convention).unwrap();
+        let (right, _) = config.hand_pose(1, None, &convention).unwrap();
+        assert_ne!(left, right);
+        assert_eq!(XrConfig::default().hand_pose(0, None, &convention), None);
+    }
+
+    #[test]
+    fn eye_color_correction_survives_a_round_trip_and_defaults_to_none() {
+        let mut config = XrConfig::default();
+        config.eye_color_correction[1].gain = [1.0, 0.9, 1.1];
+        let config: XrConfig = toml::from_str(&toml::to_string_pretty(&config).unwrap()).unwrap();
+        assert_eq!(config.eye_color_correction[0], ColorCorrection::default());
+        assert_eq!(config.eye_color_correction[1].gain, [1.0, 0.9, 1.1]);
+
+        // Channels that are missing from the file aren't corrected.
+        let config: XrConfig = toml::from_str(
+            "[[eye_color_correction]]\n[[eye_color_correction]]\ngamma = [1.2, 1.0, 1.0]\n",
+        )
+        .unwrap();
+        assert_eq!(config.eye_color_correction[1].gain, [1.0; 3]);
+        assert_eq!(config.eye_color_correction[1].gamma, [1.2, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn saving_eye_color_correction_keeps_the_rest_of_the_file() {
+        let path = std::env::temp_dir().join(format!("xr-config-{}.toml", std::process::id()));
+        let file_config = XrConfig {
+            origin_marker: true,
+            ..Default::default()
+        };
+        file_config.save(&path).unwrap();
+
+        let mut config = XrConfig {
+            half_rate: true,
+            ..Default::default()
+        };
+        config.eye_color_correction[0].gamma = [1.1; 3];
+        config.save_eye_color_correction(&path).unwrap();
+        let saved = XrConfig::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(saved.origin_marker && !saved.half_rate);
+        assert_eq!(saved.eye_color_correction, config.eye_color_correction);
+    }
+
+    #[test]
+    fn remapped_bindings_survive_a_round_trip_and_are_validated() {
+        let mut config = XrConfig::default();
+        let remapped = InputBinding::new(
+            "/interaction_profiles/valve/index_controller",
+            "/user/hand/left/input/aim/pose",
+        );
+        config
+            .bindings
+            .insert("left_hand".to_string(), vec![remapped.clone()]);
+        let config: XrConfig = toml::from_str(&toml::to_string_pretty(&config).unwrap()).unwrap();
+        assert_eq!(
+            config.bindings["left_hand"],
+            std::slice::from_ref(&remapped)
+        );
+        assert_eq!(
+            config.bindings["right_hand"],
+            default_bindings()["right_hand"]
+        );
+        assert!(remapped.validate().is_ok());
+
+        for (profile, path) in [
+            ("/user/hand/left", "/user/hand/left/input/aim/pose"),
+            (
+                "/interaction_profiles/khr/simple_controller",
+                "/user/hand/left/Input",
+            ),
+            ("/interaction_profiles/khr/simple_controller", "/user//hand"),
+            ("/interaction_profiles/khr/simple_controller", "/user/hand/"),
+        ] {
+            assert!(InputBinding::new(profile, path).validate().is_err());
+        }
+    }
+}